@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS following (
+  relay_id INT NOT NULL,       -- The local relay doing the following
+  following_id INT NOT NULL,   -- The remote relay being followed
+  PRIMARY KEY (relay_id, following_id),
+  FOREIGN KEY (relay_id) REFERENCES relays(id) ON DELETE CASCADE,
+  FOREIGN KEY (following_id) REFERENCES relays(id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- Add protocol describing how clients launch the app (webxr, deeplink, native)
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS protocol VARCHAR(32) NOT NULL DEFAULT 'webxr';
@@ -0,0 +1,2 @@
+-- Track when we last received an activity from each relay
+ALTER TABLE relays ADD COLUMN IF NOT EXISTS last_activity_at TIMESTAMPTZ;
@@ -0,0 +1,5 @@
+-- Remember the AP ids of deleted apps so they can be answered with 410 Gone
+CREATE TABLE IF NOT EXISTS tombstones (
+  activitypub_id VARCHAR(255) PRIMARY KEY,
+  deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
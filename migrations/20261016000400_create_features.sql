@@ -0,0 +1,5 @@
+-- Runtime overrides for feature flags set from /admin/features
+CREATE TABLE IF NOT EXISTS features (
+  name VARCHAR(255) PRIMARY KEY,
+  enabled BOOLEAN NOT NULL
+);
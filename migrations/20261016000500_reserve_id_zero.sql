@@ -0,0 +1,3 @@
+-- Id 0 is reserved for the relay system user; apps must never take it.
+-- NOT VALID so existing rows are reported by the startup check instead of failing the migration
+ALTER TABLE apps ADD CONSTRAINT apps_id_not_zero CHECK (id > 0) NOT VALID;
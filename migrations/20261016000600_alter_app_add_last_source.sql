@@ -0,0 +1,2 @@
+-- Which write path (beacon, federation, admin, owner) last changed each app
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS last_source VARCHAR(16);
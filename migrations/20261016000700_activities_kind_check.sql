@@ -0,0 +1,3 @@
+-- Only activity kinds the relay knows how to store (see ActivityKind)
+ALTER TABLE activities DROP CONSTRAINT IF EXISTS activities_kind_check;
+ALTER TABLE activities ADD CONSTRAINT activities_kind_check CHECK (kind IN ('Follow', 'Create', 'Update'));
@@ -0,0 +1,4 @@
+-- Results of the optional beacon health check (HEALTHCHECK_BEACONS)
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS reachable BOOLEAN;
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS last_checked_at TIMESTAMPTZ;
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS failed_checks INT NOT NULL DEFAULT 0;
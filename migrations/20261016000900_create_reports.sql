@@ -0,0 +1,11 @@
+-- Reports of spam or broken worlds submitted from the directory
+CREATE TABLE IF NOT EXISTS reports (
+  id SERIAL PRIMARY KEY,
+  app_id INT NOT NULL REFERENCES apps(id) ON DELETE CASCADE,
+  reason TEXT NOT NULL,
+  reporter_ip VARCHAR(64) NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  resolved BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX IF NOT EXISTS reports_reporter_ip_created_at ON reports (reporter_ip, created_at);
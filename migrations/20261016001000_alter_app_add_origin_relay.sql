@@ -0,0 +1,13 @@
+-- Relay each app was first received from (0 for beacons registered here)
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS origin_relay_id INT REFERENCES relays(id) ON DELETE SET NULL;
+
+UPDATE apps a SET origin_relay_id = r.id
+FROM activities ac
+JOIN relays r ON r.activitypub_id = ac.actor
+WHERE ac.obj = a.activitypub_id AND ac.kind = 'Create' AND a.origin_relay_id IS NULL;
+
+UPDATE apps SET origin_relay_id = 0
+WHERE origin_relay_id IS NULL
+  AND activitypub_id LIKE (SELECT activitypub_id FROM relays WHERE id = 0) || '/%';
+
+CREATE INDEX IF NOT EXISTS apps_origin_relay_id ON apps (origin_relay_id);
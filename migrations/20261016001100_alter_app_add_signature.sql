@@ -0,0 +1,3 @@
+-- Key that signed the most recent federated Create/Update for each app
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS signature_key_id VARCHAR(255);
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS signature_verified_at TIMESTAMPTZ;
@@ -0,0 +1,4 @@
+-- When each activity was stored; rows from before this migration stay NULL
+ALTER TABLE activities ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ;
+ALTER TABLE activities ALTER COLUMN created_at SET DEFAULT NOW();
+CREATE INDEX IF NOT EXISTS activities_created_at ON activities (created_at);
@@ -0,0 +1,12 @@
+-- Remember the local id and slug of deleted apps so their pages can answer 410 Gone
+ALTER TABLE tombstones ADD COLUMN IF NOT EXISTS app_id INT;
+ALTER TABLE tombstones ADD COLUMN IF NOT EXISTS slug VARCHAR(255);
+
+-- Beacons registered here were given AP ids ending in their app id
+UPDATE tombstones
+SET app_id = substring(activitypub_id FROM '/beacon/([0-9]+)$')::INT
+WHERE app_id IS NULL
+  AND activitypub_id LIKE (SELECT activitypub_id FROM relays WHERE id = 0) || '/beacon/%';
+
+CREATE INDEX IF NOT EXISTS tombstones_app_id ON tombstones (app_id);
+CREATE INDEX IF NOT EXISTS tombstones_slug ON tombstones (slug);
@@ -0,0 +1,2 @@
+-- The activity document exactly as received or sent; rows from before this migration stay NULL
+ALTER TABLE activities ADD COLUMN IF NOT EXISTS raw JSONB;
@@ -0,0 +1,2 @@
+-- When the app's site last listed this relay in /.well-known/spatial-relay
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS domain_verified_at TIMESTAMPTZ;
@@ -0,0 +1,4 @@
+-- Claims by a site's owner over an app, usually one that arrived through federation
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS claimed_at TIMESTAMPTZ;
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS claimed_by VARCHAR(255);
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS claim_key_hash VARCHAR(64);
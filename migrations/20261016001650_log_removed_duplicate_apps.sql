@@ -0,0 +1,10 @@
+-- 20261016001700 deletes apps that share an AP id with an older row. Keep a copy of
+-- each row it removes so admins can review what was dropped. This migration sorts
+-- first, so it runs before that one wherever it hasn't been applied yet; databases
+-- that already ran it get an empty table.
+CREATE TABLE IF NOT EXISTS removed_duplicate_apps AS
+SELECT a.*, now() AS removed_at
+FROM apps a
+WHERE EXISTS (
+  SELECT 1 FROM apps b WHERE b.activitypub_id = a.activitypub_id AND b.id < a.id
+);
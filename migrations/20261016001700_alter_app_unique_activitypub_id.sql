@@ -0,0 +1,4 @@
+-- An app's AP id identifies it across the federation, so only one row may hold it.
+-- Older duplicates are dropped in favour of the first row created.
+DELETE FROM apps a USING apps b WHERE a.activitypub_id = b.activitypub_id AND a.id > b.id;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_apps_activitypub_id ON apps(activitypub_id);
@@ -0,0 +1,10 @@
+-- Pending claims: the site proves control by publishing `nonce`, and only the requester,
+-- who holds the key behind `key_hash`, can complete the claim
+CREATE TABLE IF NOT EXISTS claim_challenges (
+  id SERIAL PRIMARY KEY,
+  app_id INT NOT NULL REFERENCES apps(id) ON DELETE CASCADE,
+  nonce VARCHAR(64) NOT NULL,
+  key_hash VARCHAR(64) NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IF NOT EXISTS idx_claim_challenges_app_id ON claim_challenges(app_id);
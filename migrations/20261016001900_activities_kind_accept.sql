@@ -0,0 +1,3 @@
+-- Strict-mode Accepts are stored so their ids can be fetched
+ALTER TABLE activities DROP CONSTRAINT IF EXISTS activities_kind_check;
+ALTER TABLE activities ADD CONSTRAINT activities_kind_check CHECK (kind IN ('Follow', 'Create', 'Update', 'Accept'));
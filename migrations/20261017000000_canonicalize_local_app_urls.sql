@@ -0,0 +1,50 @@
+-- Beacon URLs are canonicalized before they are stored (see canonicalize_beacon_url):
+-- lowercase scheme and host, no trailing slash and no utm_*, fbclid or gclid params.
+-- Bring worlds registered here before that in line so resubmissions find them.
+-- Federated worlds keep the URL their relay sent. A world whose canonical URL another
+-- world already has keeps its URL and is listed in app_url_conflicts for an admin to
+-- merge, rather than becoming a duplicate.
+CREATE TABLE IF NOT EXISTS app_url_conflicts (
+  app_id INT PRIMARY KEY REFERENCES apps(id) ON DELETE CASCADE,
+  url VARCHAR(255) NOT NULL,
+  canonical_url VARCHAR(255) NOT NULL,
+  conflicting_app_id INT NOT NULL,
+  found_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TEMPORARY TABLE canonical_app_urls AS
+SELECT id, url,
+  lower(m[1]) || '://' || lower(m[2]) || rtrim(m[3], '/')
+  || COALESCE('?' || NULLIF((
+       SELECT string_agg(param, '&' ORDER BY ord)
+       FROM unnest(string_to_array(substr(m[4], 2), '&')) WITH ORDINALITY AS p(param, ord)
+       WHERE param <> ''
+         AND lower(split_part(param, '=', 1)) NOT LIKE 'utm\_%'
+         AND lower(split_part(param, '=', 1)) NOT IN ('fbclid', 'gclid')
+     ), ''), '')
+  || COALESCE(m[5], '') AS canonical_url
+FROM (
+  SELECT id, url,
+    regexp_match(url, '^([A-Za-z][A-Za-z0-9+.-]*)://([^/?#]*)([^?#]*)(\?[^#]*)?(#.*)?$') AS m
+  FROM apps
+  WHERE origin_relay_id = 0
+) parts
+WHERE m IS NOT NULL;
+DELETE FROM canonical_app_urls WHERE canonical_url = url;
+
+-- Another world already has the URL, or an older world is being moved to it
+INSERT INTO app_url_conflicts (app_id, url, canonical_url, conflicting_app_id)
+SELECT c.id, c.url, c.canonical_url, COALESCE(
+  (SELECT MIN(a.id) FROM apps a WHERE a.url = c.canonical_url AND a.id <> c.id),
+  (SELECT MIN(o.id) FROM canonical_app_urls o WHERE o.canonical_url = c.canonical_url AND o.id < c.id)
+)
+FROM canonical_app_urls c
+WHERE EXISTS (SELECT 1 FROM apps a WHERE a.url = c.canonical_url AND a.id <> c.id)
+   OR EXISTS (SELECT 1 FROM canonical_app_urls o WHERE o.canonical_url = c.canonical_url AND o.id < c.id)
+ON CONFLICT (app_id) DO NOTHING;
+
+UPDATE apps a SET url = c.canonical_url
+FROM canonical_app_urls c
+WHERE a.id = c.id AND NOT EXISTS (SELECT 1 FROM app_url_conflicts f WHERE f.app_id = c.id);
+
+DROP TABLE canonical_app_urls;
@@ -1,20 +1,31 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
 use activitypub_federation::{
     config::Data,
+    error::Error as FederationError,
     fetch::object_id::ObjectId,
-    kinds::activity::{CreateType, FollowType, UpdateType},
+    kinds::activity::{AcceptType, CreateType, FollowType, UpdateType},
+    protocol::context::WithContext,
     traits::{ActivityHandler, Actor},
+    FEDERATION_CONTENT_TYPE,
 };
+use chrono::{DateTime, Utc};
 use serde::{self, Deserialize, Serialize};
+use serde_json::Value;
 use sqlx::{self, postgres::PgRow, FromRow, Row};
 use url::Url;
 
-use super::apps::DbApp;
+use super::actors::DbRelay;
+use super::apps::{DbApp, SOURCE_ADMIN, SOURCE_OWNER};
 use super::db::{
-    add_follower_to_relay, create_activity, create_app, create_relay,
-    get_relay_follower_id_by_ap_id,
+    add_follower_to_relay, create_activity, create_app_with_activity, create_local_activity,
+    create_relay, get_relay_by_ap_id, get_relay_follower_id_by_ap_id, get_system_user,
+    is_following_relay, is_relay_follower, update_app_with_activity,
 };
 use super::error::Error;
-use super::{actors::DbRelay, db::update_app};
+use super::services::localize_remote_image;
 use crate::AppState;
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -25,6 +36,9 @@ pub struct Follow {
     #[serde(rename = "type")]
     pub kind: FollowType,
     pub id: Url,
+    /// When the activity was sent; receiving inboxes refuse ones outside their replay window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<Utc>>,
 }
 
 impl Follow {
@@ -34,6 +48,7 @@ impl Follow {
             object,
             kind: Default::default(),
             id,
+            published: Some(Utc::now()),
         }
     }
 }
@@ -58,26 +73,128 @@ impl ActivityHandler for Follow {
     async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
         let actor = self.actor.dereference(data).await?;
         let actor_ap_id = actor.ap_id.inner().as_str();
-        create_relay(
-            data,
-            &actor.name,
-            actor_ap_id,
-            actor.inbox.as_str(),
-            actor.outbox.as_str(),
-            actor.public_key_pem(),
-        )
-        .await?;
-        create_activity(
+        if actor.ap_id.inner() == self.object.inner() {
+            return Err(Error::Validation("A relay can't follow itself".to_string()));
+        }
+        // The relay may already be stored if we followed it first
+        if get_relay_by_ap_id(actor_ap_id.to_string(), data).await?.is_none() {
+            create_relay(
+                data,
+                &actor.name,
+                actor_ap_id,
+                actor.inbox.as_str(),
+                actor.outbox.as_str(),
+                actor.public_key_pem(),
+            )
+            .await?;
+        }
+        let follower_id = get_relay_follower_id_by_ap_id(data, actor_ap_id).await?;
+        let already_following = is_relay_follower(data, follower_id).await?;
+        if !already_following {
+            create_activity(
+                data,
+                self.id.to_string(),
+                actor_ap_id,
+                self.object.inner().as_str(),
+                ActivityKind::Follow,
+            )
+            .await?;
+        }
+        // In strict mode a follow only counts once the follower has received our Accept,
+        // so nothing is delivered over a half-open follow
+        if data.features().strict_follows {
+            let system_user = get_system_user(data).await?;
+            let accept = Accept::new(system_user.ap_id.clone(), self.clone(), data).await?;
+            if let Err(e) = system_user
+                .send(accept, vec![actor.shared_inbox_or_inbox()], false, data)
+                .await
+            {
+                log_error!("Not adding follower {}: Accept was not delivered: {}", actor_ap_id, e);
+                return Err(e);
+            }
+        }
+        // A repeated Follow still gets its Accept above, for a peer that missed the first
+        if already_following {
+            log_info!("{} already follows this relay", actor_ap_id);
+            return Ok(());
+        }
+        add_follower_to_relay(data, follower_id).await?;
+
+        // Only follow back remote relays we don't follow yet, so two relays with
+        // the flag on settle after one Follow each way
+        if data.features().auto_follow_back
+            && !actor.local
+            && !is_following_relay(data, follower_id).await?
+        {
+            let system_user = get_system_user(data).await?;
+            if system_user.ap_id.inner() != actor.ap_id.inner() {
+                if let Err(e) = system_user.follow_relay(&actor, data).await {
+                    log_error!("Error following back {}: {}", actor_ap_id, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Confirms a Follow we received. Only sent, in strict follow mode
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Accept {
+    pub actor: ObjectId<DbRelay>,
+    pub object: Follow,
+    #[serde(rename = "type")]
+    pub kind: AcceptType,
+    pub id: Url,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<Utc>>,
+}
+
+impl Accept {
+    /// Stores the Accept for `follow` so its id can be fetched back
+    pub async fn new(
+        actor: ObjectId<DbRelay>,
+        follow: Follow,
+        data: &Data<AppState>,
+    ) -> Result<Accept, Error> {
+        let id = create_local_activity(
             data,
-            self.id.to_string(),
-            actor_ap_id,
-            self.object.inner().as_str(),
-            "Follow",
+            actor.inner().as_str(),
+            follow.id.as_str(),
+            ActivityKind::Accept,
         )
         .await?;
-        let follower_id = get_relay_follower_id_by_ap_id(data, actor_ap_id).await?;
-        add_follower_to_relay(data, follower_id).await?;
+        let id = Url::parse(&id)?;
+        Ok(Accept {
+            actor,
+            object: follow,
+            kind: Default::default(),
+            id,
+            published: Some(Utc::now()),
+        })
+    }
+}
 
+#[async_trait::async_trait]
+impl ActivityHandler for Accept {
+    type DataType = AppState;
+    type Error = Error;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        self.actor.inner()
+    }
+
+    async fn verify(&self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    // Accepts for our own Follows aren't routed here (see RelayAcceptedActivities)
+    async fn receive(self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
         Ok(())
     }
 }
@@ -90,6 +207,8 @@ pub struct Create {
     #[serde(rename = "type")]
     pub kind: CreateType,
     pub id: Url,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<Utc>>,
 }
 
 #[async_trait::async_trait]
@@ -110,28 +229,21 @@ impl ActivityHandler for Create {
     }
 
     async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
-        let app = self.object.dereference(data).await?;
-        create_app(
-            data,
-            app.ap_id.inner().to_string(),
-            app.url,
-            app.name,
-            app.description,
-            app.active,
-            app.image,
-            app.adult,
-            app.tags,
-        )
-        .await?;
-        create_activity(
-            data,
-            self.id.to_string(),
-            self.actor.inner().as_str(),
-            self.object.inner().as_str(),
-            "Create",
-        )
-        .await?;
-        Ok(())
+        let mut app = self.object.dereference(data).await?;
+        if data.require_https_beacons && !app.uses_https() {
+            log_info!("Ignoring federated app {} with insecure URL {}", app.ap_id.inner(), app.url);
+            return Ok(());
+        }
+        localize_remote_image(data, &mut app).await;
+        let actor = self.actor.inner().as_str();
+        match create_app_with_activity(data, None, &app, Some(self.id.as_str()), actor).await {
+            // A redelivered Create, or one for an app we already got from another relay
+            Err(Error::Duplicate(_)) => {
+                log_info!("Ignoring Create for known app {}", app.ap_id.inner());
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        }
     }
 }
 
@@ -143,6 +255,8 @@ pub struct Update {
     #[serde(rename = "type")]
     pub kind: UpdateType,
     pub id: Url,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<Utc>>,
 }
 
 #[async_trait::async_trait]
@@ -163,36 +277,224 @@ impl ActivityHandler for Update {
     }
 
     async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
-        let app = self.object.dereference_forced(data).await?;
-        update_app(
-            data,
-            app.url,
-            app.name,
-            app.description,
-            app.active,
-            app.image,
-            app.adult,
-            app.tags,
-        )
-        .await?;
-        create_activity(
-            data,
-            self.id.to_string(),
-            self.actor.inner().as_str(),
-            self.object.inner().as_str(),
-            "Update",
-        )
-        .await?;
+        let error = match self.object.dereference_forced(data).await {
+            Ok(app) => return self.apply(app, data).await,
+            Err(e) => e,
+        };
+        if object_is_gone(&error, self.object.inner(), data).await {
+            log_info!("Ignoring Update {}: {} is gone ({})", self.id, self.object.inner(), error);
+            return Ok(());
+        }
+        // Keep the Update and try again in the background instead of dropping the change
+        if data.update_retries.push(self.clone()) {
+            log_error!("Deferring Update {}: {}", self.id, error);
+        } else {
+            log_error!("Dropping Update {}, too many are waiting for a retry: {}", self.id, error);
+        }
         Ok(())
     }
 }
 
+/// Whether a failed fetch of an Update's object means it no longer exists, so retrying
+/// is pointless. Fetch errors don't carry the status, so other failures are checked with
+/// one more request.
+async fn object_is_gone(error: &Error, object: &Url, data: &Data<AppState>) -> bool {
+    match error {
+        Error::NotFound | Error::Federation(FederationError::ObjectDeleted(_)) => return true,
+        Error::Federation(FederationError::ParseFetchedObject(..)) => {}
+        _ => return false,
+    }
+    if data.outbound_guard.check(object).await.is_err() {
+        return false;
+    }
+    data.http_client
+        .get(object.as_str())
+        .header(reqwest::header::ACCEPT, FEDERATION_CONTENT_TYPE)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .is_ok_and(|response| {
+            matches!(
+                response.status(),
+                reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::GONE
+            )
+        })
+}
+
+/// Retries the queued Updates that are due: applies those whose object can be fetched
+/// now, drops those whose object is gone and schedules the rest again
+pub async fn retry_due_updates(data: &Data<AppState>) {
+    for (update, attempts) in data.update_retries.take_due() {
+        let data = data.reset_request_count();
+        let error = match update.object.dereference_forced(&data).await {
+            Ok(app) => {
+                match update.apply(app, &data).await {
+                    Ok(_) => log_info!("Applied deferred Update {}", update.id),
+                    Err(e) => log_error!("Error applying deferred Update {}: {}", update.id, e),
+                }
+                continue;
+            }
+            Err(e) => e,
+        };
+        if object_is_gone(&error, update.object.inner(), &data).await {
+            log_info!("Dropping deferred Update {}: its object is gone", update.id);
+            continue;
+        }
+        let id = update.id.clone();
+        if data.update_retries.retry_again(update, attempts + 1) {
+            log_error!("Deferred Update {} still failing: {}", id, error);
+        } else {
+            log_error!("Giving up on Update {} after repeated fetch failures: {}", id, error);
+        }
+    }
+}
+
+impl Update {
+    /// Stores the freshly fetched app unless it was claimed or a local edit is protected
+    async fn apply(&self, mut app: DbApp, data: &Data<AppState>) -> Result<(), Error> {
+        if data.require_https_beacons && !app.uses_https() {
+            log_info!(
+                "Ignoring federated Update moving {} to insecure URL {}",
+                app.ap_id.inner(),
+                app.url
+            );
+            return Ok(());
+        }
+        // Owners' edits always win over their world's origin relay; admin edits only
+        // when protected
+        let protected_sources: &[&str] = if data.features().protect_admin_edits {
+            &[SOURCE_OWNER, SOURCE_ADMIN]
+        } else {
+            &[SOURCE_OWNER]
+        };
+        localize_remote_image(data, &mut app).await;
+        let actor = self.actor.inner().as_str();
+        let updated =
+            update_app_with_activity(data, &app, self.id.as_str(), actor, protected_sources)
+                .await?;
+        if !updated {
+            log_info!("Ignoring federated Update to locally edited app {}", app.ap_id.inner());
+        }
+        Ok(())
+    }
+}
+
+/// `@context` for outbound activities and the actor document, from `ACTIVITY_CONTEXT`
+pub fn activity_context(data: &Data<AppState>) -> Value {
+    Value::Array(
+        data.activity_context
+            .iter()
+            .map(|iri| Value::String(iri.clone()))
+            .collect(),
+    )
+}
+
+/// Wraps an activity or actor in the context produced by [`activity_context`]
+pub fn with_activity_context<T>(inner: T, data: &Data<AppState>) -> WithContext<T> {
+    WithContext::new(inner, activity_context(data))
+}
+
+/// Activity types stored in the activities table
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActivityKind {
+    Follow,
+    Create,
+    Update,
+    /// Only sent, in reply to a Follow in strict mode
+    Accept,
+}
+
+impl ActivityKind {
+    pub const ALL: [ActivityKind; 4] = [
+        ActivityKind::Follow,
+        ActivityKind::Create,
+        ActivityKind::Update,
+        ActivityKind::Accept,
+    ];
+
+    /// The types [`RelayAcceptedActivities`](super::services::RelayAcceptedActivities)
+    /// handles in the inbox
+    pub const INBOX: [ActivityKind; 3] =
+        [ActivityKind::Follow, ActivityKind::Create, ActivityKind::Update];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityKind::Follow => "Follow",
+            ActivityKind::Create => "Create",
+            ActivityKind::Update => "Update",
+            ActivityKind::Accept => "Accept",
+        }
+    }
+}
+
+impl Display for ActivityKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ActivityKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ActivityKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown activity kind: {}", s))
+    }
+}
+
 #[derive(Serialize)]
 pub struct DbActivity {
     pub ap_id: ObjectId<DbRelay>,
     pub actor: ObjectId<DbRelay>,
     pub object: ObjectId<DbApp>,
-    pub kind: String,
+    pub kind: ActivityKind,
+    /// The document as received or sent, if it was stored
+    #[serde(skip)]
+    pub raw: Option<Value>,
+}
+
+/// Narrows the activity log on `/admin/activities`; unset fields match everything
+#[derive(Clone, Debug, Default)]
+pub struct ActivityFilter {
+    pub kind: Option<ActivityKind>,
+    /// Host of the actor's AP id, e.g. `relay.example.com`
+    pub actor_host: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// A stored activity with the local app its object refers to, if any
+#[derive(Serialize, Debug)]
+pub struct ActivityLogEntry {
+    pub id: i32,
+    pub ap_id: String,
+    pub actor: String,
+    pub object: String,
+    pub kind: ActivityKind,
+    /// `None` for activities stored before timestamps were recorded
+    pub created_at: Option<DateTime<Utc>>,
+    pub app_id: Option<i32>,
+    pub app_slug: Option<String>,
+}
+
+impl FromRow<'_, sqlx::postgres::PgRow> for ActivityLogEntry {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            ap_id: row.try_get("activitypub_id")?,
+            actor: row.try_get("actor")?,
+            object: row.try_get("obj")?,
+            kind: row
+                .try_get::<String, _>("kind")?
+                .parse::<ActivityKind>()
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            created_at: row.try_get("created_at")?,
+            app_id: row.try_get("app_id")?,
+            app_slug: row.try_get("app_slug")?,
+        })
+    }
 }
 
 impl FromRow<'_, sqlx::postgres::PgRow> for DbActivity {
@@ -205,7 +507,227 @@ impl FromRow<'_, sqlx::postgres::PgRow> for DbActivity {
             ap_id: ObjectId::parse(row.try_get("activitypub_id")?).unwrap(),
             actor: ObjectId::parse(actor).unwrap(),
             object: ObjectId::parse(object).unwrap(),
-            kind: row.try_get("kind")?,
+            kind: row
+                .try_get::<String, _>("kind")?
+                .parse()
+                .map_err(|e: anyhow::Error| sqlx::Error::Decode(e.into()))?,
+            raw: row.try_get("raw")?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use activitypub_federation::traits::Object;
+
+    use super::*;
+    use crate::activitypub::apps::SOURCE_FEDERATION;
+    use crate::activitypub::db::get_app_by_id;
+    use crate::test_support::{self, SiteFiles};
+
+    /// An app of a relay served at `site`, stored locally as `name`, and an Update for it
+    async fn remote_app_update(data: &Data<AppState>, site: &str, name: &str) -> (i32, Update) {
+        let ap_id = format!("{}/relay/beacon/{}", site, test_support::unique("app"));
+        let url = format!("https://{}.test", test_support::unique("world"));
+        let app_id = test_support::insert_app(data, &url, name).await;
+        sqlx::query("UPDATE apps SET activitypub_id = $1 WHERE id = $2")
+            .bind(&ap_id)
+            .bind(app_id)
+            .execute(&data.db)
+            .await
+            .unwrap();
+        let update = Update {
+            actor: Url::parse(&format!("{}/relay", site)).unwrap().into(),
+            object: Url::parse(&ap_id).unwrap().into(),
+            kind: UpdateType::Update,
+            id: Url::parse(&format!(
+                "{}/activities/{}",
+                site,
+                test_support::unique("update")
+            ))
+            .unwrap(),
+            published: None,
+        };
+        (app_id, update)
+    }
+
+    /// The stored app as its relay would serve it, after `edit`
+    async fn app_json(data: &Data<AppState>, app_id: i32, edit: impl FnOnce(&mut DbApp)) -> String {
+        let mut app = get_app_by_id(app_id, data).await.unwrap();
+        edit(&mut app);
+        serde_json::to_string(&app.into_json(data).await.unwrap()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn update_is_applied_by_a_later_retry_when_the_first_fetch_fails() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let files: SiteFiles = Arc::new(Mutex::new(HashMap::new()));
+        let site = test_support::serve_peer(files.clone());
+        let (app_id, update) = remote_app_update(&data, &site, "Before").await;
+        let path = update.object.inner().path().to_string();
+
+        // The peer answers with something that isn't the app
+        files
+            .lock()
+            .unwrap()
+            .insert(path.clone(), "Service unavailable".into());
+        update.clone().receive(&data).await.unwrap();
+        assert_eq!(get_app_by_id(app_id, &data).await.unwrap().name, "Before");
+
+        let json = app_json(&data, app_id, |app| app.name = "After".to_string()).await;
+        files.lock().unwrap().insert(path, json.into());
+        retry_due_updates(&data).await;
+        assert_eq!(get_app_by_id(app_id, &data).await.unwrap().name, "After");
+        assert!(data.update_retries.take_due().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn update_of_a_missing_object_is_not_retried() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let site = test_support::serve_peer(Arc::new(Mutex::new(HashMap::new())));
+        let (_, update) = remote_app_update(&data, &site, "Gone").await;
+        update.receive(&data).await.unwrap();
+        assert!(data.update_retries.take_due().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn federated_updates_only_change_the_app_they_name() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let files: SiteFiles = Arc::new(Mutex::new(HashMap::new()));
+        let site = test_support::serve_peer(files.clone());
+        let url = format!("https://{}.test", test_support::unique("local"));
+        let local_id = test_support::insert_app(&data, &url, "Local").await;
+        let (app_id, update) = remote_app_update(&data, &site, "Remote").await;
+
+        // The peer's object carries the address of one of our worlds
+        let json = app_json(&data, app_id, |app| {
+            app.name = "Taken over".to_string();
+            app.url = url.clone();
+        })
+        .await;
+        let path = update.object.inner().path().to_string();
+        files.lock().unwrap().insert(path, json.into());
+        update.receive(&data).await.unwrap();
+
+        let local = get_app_by_id(local_id, &data).await.unwrap();
+        assert_eq!(local.name, "Local");
+        assert_eq!(local.last_source, None);
+        let remote = get_app_by_id(app_id, &data).await.unwrap();
+        assert_eq!(remote.name, "Taken over");
+        assert_eq!(remote.last_source.as_deref(), Some(SOURCE_FEDERATION));
+    }
+
+    #[actix_web::test]
+    async fn admin_edits_are_kept_when_protected() {
+        let Some(config) = test_support::db_config_with(|state| {
+            state.features.write().unwrap().protect_admin_edits = true;
+        })
+        .await
+        else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let files: SiteFiles = Arc::new(Mutex::new(HashMap::new()));
+        let site = test_support::serve_peer(files.clone());
+        let (app_id, update) = remote_app_update(&data, &site, "Edited here").await;
+        sqlx::query("UPDATE apps SET last_source = $1 WHERE id = $2")
+            .bind(SOURCE_ADMIN)
+            .bind(app_id)
+            .execute(&data.db)
+            .await
+            .unwrap();
+
+        let json = app_json(&data, app_id, |app| app.name = "Edited there".to_string()).await;
+        let path = update.object.inner().path().to_string();
+        files.lock().unwrap().insert(path, json.into());
+        update.clone().receive(&data).await.unwrap();
+
+        let app = get_app_by_id(app_id, &data).await.unwrap();
+        assert_eq!(app.name, "Edited here");
+        assert_eq!(app.last_source.as_deref(), Some(SOURCE_ADMIN));
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM activities WHERE activitypub_id = $1")
+            .bind(update.id.as_str())
+            .fetch_one(&data.db)
+            .await
+            .unwrap();
+        assert_eq!(stored, 0);
+    }
+
+    #[actix_web::test]
+    async fn claimed_and_owner_edited_apps_ignore_federated_updates() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let files: SiteFiles = Arc::new(Mutex::new(HashMap::new()));
+        let site = test_support::serve_peer(files.clone());
+        for edit in [
+            "UPDATE apps SET claimed_at = NOW() WHERE id = $1",
+            "UPDATE apps SET last_source = 'owner' WHERE id = $1",
+        ] {
+            let (app_id, update) = remote_app_update(&data, &site, "Owner's").await;
+            sqlx::query(edit).bind(app_id).execute(&data.db).await.unwrap();
+            let json = app_json(&data, app_id, |app| app.name = "Origin's".to_string()).await;
+            let path = update.object.inner().path().to_string();
+            files.lock().unwrap().insert(path, json.into());
+            update.receive(&data).await.unwrap();
+            assert_eq!(get_app_by_id(app_id, &data).await.unwrap().name, "Owner's");
+        }
+    }
+
+    #[actix_web::test]
+    async fn inbound_follows_are_followed_back_once() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        data.features.write().unwrap().set("auto_follow_back", true);
+        let files: SiteFiles = Arc::new(Mutex::new(HashMap::new()));
+        let site = test_support::serve_peer(files.clone());
+        let actor = format!("{}/{}", site, test_support::unique("relay"));
+        let inbox = format!("{}/inbox", actor);
+        files
+            .lock()
+            .unwrap()
+            .insert(Url::parse(&inbox).unwrap().path().to_string(), "ok".into());
+        create_relay(&data, "peer", &actor, &inbox, &inbox, "unused")
+            .await
+            .unwrap();
+        let system_user = get_system_user(&data).await.unwrap();
+        let follow = || {
+            Follow::new(
+                Url::parse(&actor).unwrap().into(),
+                system_user.ap_id.clone(),
+                Url::parse(&format!("{}/{}", actor, test_support::unique("follow"))).unwrap(),
+            )
+        };
+        let follows_back = || {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM activities WHERE actor = $1 AND obj = $2 AND kind = 'Follow'",
+            )
+            .bind(system_user.ap_id.inner().as_str())
+            .bind(&actor)
+            .fetch_one(&data.db)
+        };
+
+        follow().receive(&data).await.unwrap();
+        assert_eq!(follows_back().await.unwrap(), 1);
+        let peer_id = get_relay_follower_id_by_ap_id(&data, &actor).await.unwrap();
+        assert!(is_following_relay(&data, peer_id).await.unwrap());
+        // A repeated Follow from a relay that already follows us isn't answered again
+        follow().receive(&data).await.unwrap();
+        assert_eq!(follows_back().await.unwrap(), 1);
+    }
+}
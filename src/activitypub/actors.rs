@@ -1,11 +1,13 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use activitypub_federation::activity_queue::queue_activity;
 use activitypub_federation::activity_sending::SendActivityTask;
 use activitypub_federation::config::Data;
 use activitypub_federation::fetch::webfinger::webfinger_resolve_actor;
-use activitypub_federation::protocol::context::WithContext;
 use activitypub_federation::protocol::verification::verify_domains_match;
 use activitypub_federation::traits::{ActivityHandler, Actor};
 use activitypub_federation::{
@@ -18,11 +20,17 @@ use sqlx::postgres::PgRow;
 use sqlx::{self, FromRow, Row};
 use url::Url;
 
-use super::activities::Follow;
-use super::db::{create_activity, get_activities_count, get_relay_by_ap_id};
+use super::activities::{with_activity_context, ActivityKind, Follow};
+use super::db::{
+    add_following_to_relay, create_local_activity, create_relay, get_relay_by_ap_id,
+    get_relay_follower_id_by_ap_id, is_following_relay, store_activity_raw,
+};
 use super::error::Error;
 use crate::AppState;
 
+/// Username of this relay's actor, the one WebFinger resolves as `acct:relay@DOMAIN`
+pub const RELAY_USERNAME: &str = "relay";
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Relay {
@@ -34,6 +42,16 @@ pub struct Relay {
     pub inbox: Url,
     pub outbox: Url,
     pub public_key: PublicKey,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<Endpoints>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub followers: Option<Url>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Endpoints {
+    pub shared_inbox: Url,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -48,6 +66,8 @@ pub struct DbRelay {
     private_key: Option<String>,
     pub last_refreshed_at: DateTime<Utc>,
     pub local: bool,
+    // when we last received an activity from this relay
+    pub last_activity_at: Option<DateTime<Utc>>,
 }
 
 impl DbRelay {
@@ -69,6 +89,7 @@ impl DbRelay {
             private_key,
             last_refreshed_at: Utc::now(),
             local,
+            last_activity_at: None,
         }
     }
 
@@ -83,7 +104,16 @@ impl DbRelay {
         Activity: ActivityHandler + Serialize + Debug + Send + Sync,
         <Activity as ActivityHandler>::Error: From<Error> + From<serde_json::Error>,
     {
-        let activity = WithContext::new_default(activity);
+        let activity = with_activity_context(activity, data);
+        match serde_json::to_value(&activity) {
+            Ok(raw) => {
+                let id = activity.inner().id().as_str();
+                if let Err(e) = store_activity_raw(data, id, &raw).await {
+                    log_error!("Error storing sent activity {}: {}", id, e);
+                }
+            }
+            Err(e) => log_error!("Error serializing sent activity: {}", e),
+        }
         // Send through queue in some cases and bypass it in others to test both code paths
         if use_queue {
             queue_activity(&activity, self, recipients, data).await?;
@@ -96,37 +126,75 @@ impl DbRelay {
         Ok(())
     }
 
+    /// Shared inbox advertised by local relays, accepting activities for any local actor
+    pub fn local_shared_inbox(&self) -> Option<Url> {
+        if !self.local {
+            return None;
+        }
+        self.ap_id.inner().join("/inbox").ok()
+    }
+
+    /// Followers collection advertised by local relays
+    pub fn local_followers_url(&self) -> Option<Url> {
+        if !self.local {
+            return None;
+        }
+        self.followers_url().ok()
+    }
+
     pub fn followers_url(&self) -> Result<Url, Error> {
         Ok(Url::parse(&format!("{}/followers", self.ap_id.inner()))?)
     }
 
     pub async fn follow(&self, other: &str, data: &Data<AppState>) -> Result<(), Error> {
         let other: DbRelay = webfinger_resolve_actor(other, data).await?;
-        let activities_count = get_activities_count(data).await?;
-        let follow = Follow::new(
-            self.ap_id.clone(),
-            other.ap_id.clone(),
-            Url::from_str(&format!(
-                "{}/activities/{}",
-                self.ap_id.inner().as_str(),
-                activities_count
-            ))?,
-        );
-        create_activity(
+        self.follow_relay(&other, data).await
+    }
+
+    /// Sends a Follow to an already resolved relay and records it as followed once it was
+    /// delivered, so a failed delivery can be retried. Following ourselves is a validation
+    /// error and following a relay twice is a duplicate.
+    pub async fn follow_relay(&self, other: &DbRelay, data: &Data<AppState>) -> Result<(), Error> {
+        if other.ap_id.inner() == self.ap_id.inner() {
+            return Err(Error::Validation("A relay can't follow itself".to_string()));
+        }
+        match get_relay_follower_id_by_ap_id(data, other.ap_id.inner().as_str()).await {
+            Ok(known_id) if is_following_relay(data, known_id).await? => {
+                return Err(Error::Duplicate("following".to_string()));
+            }
+            Ok(_) | Err(Error::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        let activity_id = create_local_activity(
             data,
-            format!(
-                "{}/activities/{}",
-                self.ap_id.inner().as_str(),
-                activities_count
-            ),
             self.ap_id.inner().as_str(),
             other.ap_id.inner().as_str(),
-            "Follow",
+            ActivityKind::Follow,
         )
         .await?;
+        let follow = Follow::new(
+            self.ap_id.clone(),
+            other.ap_id.clone(),
+            Url::from_str(&activity_id)?,
+        );
 
+        // Track the outbound follow so mutual-only relaying can tell who we follow
+        let other_ap_id = other.ap_id.inner().as_str();
+        if get_relay_by_ap_id(other_ap_id.to_string(), data).await?.is_none() {
+            create_relay(
+                data,
+                &other.name,
+                other_ap_id,
+                other.inbox.as_str(),
+                other.outbox.as_str(),
+                other.public_key_pem(),
+            )
+            .await?;
+        }
         self.send(follow, vec![other.shared_inbox_or_inbox()], false, data)
             .await?;
+        let following_id = get_relay_follower_id_by_ap_id(data, other_ap_id).await?;
+        add_following_to_relay(data, following_id).await?;
         Ok(())
     }
 }
@@ -144,10 +212,87 @@ impl FromRow<'_, sqlx::postgres::PgRow> for DbRelay {
             private_key: row.try_get("private_key")?,
             last_refreshed_at: Utc::now(),
             local: row.try_get("is_local")?,
+            last_activity_at: row.try_get("last_activity_at")?,
         })
     }
 }
 
+struct CachedKey {
+    relay: DbRelay,
+    inserted_at: Instant,
+    last_used: Instant,
+}
+
+/// In-memory LRU cache of remote actors fetched for signature verification.
+/// Relays we follow or are followed by live in the database, but every other
+/// signer would otherwise be re-fetched on each inbound activity.
+pub struct PublicKeyCache {
+    entries: Mutex<HashMap<String, CachedKey>>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl PublicKeyCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        PublicKeyCache {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+            ttl,
+        }
+    }
+
+    pub fn get(&self, ap_id: &str) -> Option<DbRelay> {
+        let mut entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let entry = entries.get_mut(ap_id)?;
+        if entry.inserted_at.elapsed() > self.ttl {
+            entries.remove(ap_id);
+            return None;
+        }
+        entry.last_used = Instant::now();
+        Some(entry.relay.clone())
+    }
+
+    pub fn insert(&self, relay: DbRelay) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let key = relay.ap_id.to_string();
+        if !entries.contains_key(&key) && entries.len() >= self.capacity {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(ap_id, _)| ap_id.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        let now = Instant::now();
+        entries.insert(
+            key,
+            CachedKey {
+                relay,
+                inserted_at: now,
+                last_used: now,
+            },
+        );
+    }
+
+    pub fn invalidate(&self, ap_id: &str) {
+        let mut entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        entries.remove(ap_id);
+    }
+}
+
 #[async_trait::async_trait]
 impl Object for DbRelay {
     type DataType = AppState;
@@ -160,17 +305,30 @@ impl Object for DbRelay {
         object_id: Url,
         data: &Data<Self::DataType>,
     ) -> Result<Option<Self>, Self::Error> {
-        get_relay_by_ap_id(object_id.to_string(), data).await
+        let relay = get_relay_by_ap_id(object_id.to_string(), data).await?;
+        if relay.is_some() {
+            return Ok(relay);
+        }
+        Ok(data.public_key_cache.get(object_id.as_str()))
     }
 
     async fn into_json(self, _data: &Data<Self::DataType>) -> Result<Self::Kind, Self::Error> {
         let name = self.name.clone();
         let owner = self.ap_id.inner().clone();
         let public_key_pem = self.public_key.clone();
+        let endpoints = self
+            .local_shared_inbox()
+            .map(|shared_inbox| Endpoints { shared_inbox });
+        let followers = self.local_followers_url();
+        let preferred_username = if self.local {
+            RELAY_USERNAME.to_string()
+        } else {
+            name.clone()
+        };
         Ok(Relay {
             id: self.ap_id,
             kind: ServiceType::Service,
-            preferred_username: "".to_string(),
+            preferred_username,
             name: name.clone(),
             inbox: self.inbox,
             outbox: self.outbox,
@@ -179,6 +337,8 @@ impl Object for DbRelay {
                 owner,
                 public_key_pem,
             },
+            endpoints,
+            followers,
         })
     }
 
@@ -193,7 +353,7 @@ impl Object for DbRelay {
 
     async fn from_json(
         json: Self::Kind,
-        _data: &Data<Self::DataType>,
+        data: &Data<Self::DataType>,
     ) -> Result<Self, Self::Error> {
         let user = DbRelay {
             name: json.preferred_username,
@@ -204,7 +364,9 @@ impl Object for DbRelay {
             private_key: None,
             last_refreshed_at: Utc::now(),
             local: false,
+            last_activity_at: None,
         };
+        data.public_key_cache.insert(user.clone());
         Ok(user)
     }
 }
@@ -1,19 +1,50 @@
 use std::env;
+use std::fs::File;
+use std::io::Read;
 
 use activitypub_federation::config::Data;
 use activitypub_federation::fetch::object_id::ObjectId;
 use activitypub_federation::protocol::helpers::{deserialize_one_or_many, deserialize_skip_error};
 use activitypub_federation::protocol::verification::verify_domains_match;
-use activitypub_federation::{kinds::object::PageType, traits::Object};
+use activitypub_federation::kinds::object::{PageType, TombstoneType};
+use activitypub_federation::protocol::context::WithContext;
+use activitypub_federation::traits::Object;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use sqlx::postgres::PgRow;
 use sqlx::{self, FromRow, Row};
 use url::Url;
 
+use super::activities::activity_context;
 use super::db::get_app_by_ap_id;
 use super::error::Error;
-use crate::AppState;
+use crate::{public_protocol, AppState};
+
+/// Transports a client can use to launch an app
+pub const APP_PROTOCOLS: &[&str] = &["webxr", "deeplink", "native"];
+
+/// Protocol assumed when a beacon or federated app doesn't specify one
+pub const DEFAULT_APP_PROTOCOL: &str = "webxr";
+
+/// Validates a protocol against the known set, normalizing its case
+pub fn parse_app_protocol(protocol: &str) -> Option<String> {
+    let protocol = protocol.trim().to_lowercase();
+    APP_PROTOCOLS.contains(&protocol.as_str()).then_some(protocol)
+}
+
+/// Values of `apps.last_source`, recording which write path last changed an app
+pub const SOURCE_BEACON: &str = "beacon";
+pub const SOURCE_FEDERATION: &str = "federation";
+pub const SOURCE_ADMIN: &str = "admin";
+pub const SOURCE_OWNER: &str = "owner";
+
+/// Stored as an app's image when its beacon didn't send one
+pub const NO_IMAGE: &str = "#";
+
+fn default_app_protocol() -> String {
+    DEFAULT_APP_PROTOCOL.to_string()
+}
 
 /// The internal representation of App data
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -23,16 +54,33 @@ pub struct DbApp {
     pub url: String,
     pub name: String,
     pub description: String,
+    /// Reported by the beacon; inactive worlds are left out of public listings
     pub active: bool,
     pub image: String,
     pub adult: bool,
     pub tags: String,
+    /// Set by the relay admin; hidden worlds are left out of public listings
     pub visible: bool,
     pub created_at: DateTime<Utc>,
+    pub protocol: String,
     // New fields for world management features
     pub slug: Option<String>,
     pub verification_code: Option<String>,
     pub verified_at: Option<DateTime<Utc>>,
+    /// Write path that last changed this app; `None` for rows predating tracking
+    pub last_source: Option<String>,
+    /// Result of the last beacon health check; `None` if never checked
+    pub reachable: Option<bool>,
+    pub last_checked_at: Option<DateTime<Utc>>,
+    /// Consecutive failed health checks
+    pub failed_checks: i32,
+    /// Relay the app was first received from; 0 for beacons registered here
+    pub origin_relay_id: Option<i32>,
+    /// Key whose HTTP signature was verified on the last Create/Update received for this app
+    pub signature_key_id: Option<String>,
+    pub signature_verified_at: Option<DateTime<Utc>>,
+    /// When the app's site was found listing this relay (`REQUIRE_DOMAIN_VERIFICATION`)
+    pub domain_verified_at: Option<DateTime<Utc>>,
 }
 
 impl FromRow<'_, sqlx::postgres::PgRow> for DbApp {
@@ -50,9 +98,18 @@ impl FromRow<'_, sqlx::postgres::PgRow> for DbApp {
             tags: row.try_get("tags")?,
             visible: row.try_get("visible")?,
             created_at: row.try_get("created_at")?,
+            protocol: row.try_get("protocol")?,
             slug: row.try_get("slug")?,
             verification_code: row.try_get("verification_code")?,
             verified_at: row.try_get("verified_at")?,
+            last_source: row.try_get("last_source")?,
+            reachable: row.try_get("reachable")?,
+            last_checked_at: row.try_get("last_checked_at")?,
+            failed_checks: row.try_get("failed_checks")?,
+            origin_relay_id: row.try_get("origin_relay_id")?,
+            signature_key_id: row.try_get("signature_key_id")?,
+            signature_verified_at: row.try_get("signature_verified_at")?,
+            domain_verified_at: row.try_get("domain_verified_at")?,
         })
     }
 }
@@ -70,6 +127,7 @@ impl DbApp {
         tags: String,
         visible: bool,
         created_at: DateTime<Utc>,
+        protocol: String,
     ) -> Self {
         Self {
             id,
@@ -83,37 +141,69 @@ impl DbApp {
             tags,
             visible,
             created_at,
+            protocol,
             slug: None,
             verification_code: None,
             verified_at: None,
+            last_source: None,
+            reachable: None,
+            last_checked_at: None,
+            failed_checks: 0,
+            origin_relay_id: None,
+            signature_key_id: None,
+            signature_verified_at: None,
+            domain_verified_at: None,
         }
     }
 
     /// Returns the page URL using slug if available, otherwise falls back to ID
     pub fn page_url(&self) -> String {
         let domain = env::var("DOMAIN").expect("DOMAIN must be set");
-        let protocol = env::var("PROTOCOL").expect("PROTOCOL must be set");
-        let full_domain = format!("{}{}", protocol, domain);
+        format!("{}{}{}", public_protocol(), domain, self.page_path())
+    }
 
-        match &self.slug {
-            Some(s) if !s.is_empty() => format!("{}/world/{}", full_domain, s),
-            _ => {
-                let ap_id = self.ap_id.clone().into_inner();
-                let idx = ap_id
-                    .as_str()
-                    .split("/")
-                    .last()
-                    .expect("This app should have an index!");
-                format!("{}/world/{}", full_domain, idx)
-            }
+    /// Host (and port, if any) of the relay that published this app
+    pub fn origin_host(&self) -> String {
+        let ap_id = self.ap_id.inner();
+        match (ap_id.host_str(), ap_id.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => String::new(),
         }
     }
 
+    /// Whether this app was registered through a beacon on this relay rather than federated
+    pub fn is_local(&self) -> bool {
+        let domain = env::var("DOMAIN").expect("DOMAIN must be set");
+        self.origin_host() == domain
+    }
+
+    /// Whether the app arrived signed by a key belonging to the domain it claims to come from
+    pub fn signature_verified(&self) -> bool {
+        let key_host = self
+            .signature_key_id
+            .as_deref()
+            .and_then(|key_id| Url::parse(key_id).ok())
+            .and_then(|key_id| key_id.host_str().map(str::to_string));
+        key_host.is_some() && key_host.as_deref() == self.ap_id.inner().host_str()
+    }
+
+    /// Whether the app has an image to show or federate, as opposed to nothing or the
+    /// [`NO_IMAGE`] placeholder
+    pub fn has_real_image(&self) -> bool {
+        !self.image.trim().is_empty() && self.image != NO_IMAGE
+    }
+
+    /// Whether the app's URL is served over https
+    pub fn uses_https(&self) -> bool {
+        Url::parse(&self.url).is_ok_and(|url| url.scheme() == "https")
+    }
+
     /// Returns the relative page path (for internal links)
     pub fn page_path(&self) -> String {
         match &self.slug {
             Some(s) if !s.is_empty() => format!("/world/{}", s),
-            _ => format!("/world/{}", self.id - 1),
+            _ => format!("/world/{}", self.id),
         }
     }
 }
@@ -128,15 +218,215 @@ pub struct APImage {
 }
 
 impl APImage {
-    pub fn new(href: String) -> Self {
+    /// Labels the image with its real media type: images stored by this relay are
+    /// sniffed from their contents, others go by their extension, falling back to PNG.
+    /// The file is read on the blocking pool, since this runs for every serialized app.
+    pub async fn new(href: String) -> Self {
+        let sniffed = match local_image_file(&href) {
+            Some(file) => {
+                let file = file.to_string();
+                tokio::task::spawn_blocking(move || sniff_image_media_type(&file))
+                    .await
+                    .ok()
+                    .flatten()
+            }
+            None => None,
+        };
+        let media_type = sniffed
+            .or_else(|| image_media_type(Url::parse(&href).ok()?.path()))
+            .unwrap_or("image/png");
         Self {
             kind: "Image".to_string(),
             href,
-            media_type: "image/png".to_string(),
+            media_type: media_type.to_string(),
+        }
+    }
+}
+
+/// Media type for an image path by its extension
+pub fn image_media_type(path: &str) -> Option<&'static str> {
+    let (_, ext) = path.rsplit_once('.')?;
+    match ext.to_ascii_lowercase().as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+/// The file under `images/` an image URL served by this relay points at
+fn local_image_file(href: &str) -> Option<&str> {
+    let domain = env::var("DOMAIN").ok()?;
+    let file = href.strip_prefix(&format!("{}{}/images/", public_protocol(), domain))?;
+    (!file.is_empty() && !file.contains('/') && !file.contains("..")).then_some(file)
+}
+
+/// Media type of a stored image from its first bytes, since older files were all
+/// named `.png` whatever they contained
+fn sniff_image_media_type(file: &str) -> Option<&'static str> {
+    let mut header = [0u8; 64];
+    let read = File::open(format!("images/{}", file)).ok()?.read(&mut header).ok()?;
+    let header = &header[..read];
+    match image::guess_format(header) {
+        Ok(image::ImageFormat::Png) => Some("image/png"),
+        Ok(image::ImageFormat::Jpeg) => Some("image/jpeg"),
+        Ok(image::ImageFormat::Gif) => Some("image/gif"),
+        Ok(image::ImageFormat::WebP) => Some("image/webp"),
+        _ => {
+            let text = String::from_utf8_lossy(header);
+            let text = text.trim_start();
+            (text.starts_with("<svg") || text.starts_with("<?xml")).then_some("image/svg+xml")
         }
     }
 }
 
+/// Splits a comma-separated tags string into trimmed, non-empty tags
+pub fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Joins tags back into the stored comma-separated form
+pub fn join_tags(tags: &[String]) -> String {
+    tags.join(", ")
+}
+
+/// How tags compare: case-insensitively, for any script (like `lower()` in SQL)
+fn tag_key(tag: &str) -> String {
+    tag.to_lowercase()
+}
+
+/// Drops later case-insensitive repeats of a tag, keeping the first spelling
+fn dedup_tags(tags: Vec<String>) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    tags.into_iter()
+        .filter(|tag| seen.insert(tag_key(tag)))
+        .collect()
+}
+
+/// Bulk tag edits an admin can apply to many apps. Tags compare case-insensitively and
+/// each edit is idempotent, so reapplying one changes nothing.
+#[derive(Clone, Debug)]
+pub enum TagEdit {
+    Add(String),
+    Remove(String),
+    Rename { from: String, to: String },
+}
+
+impl TagEdit {
+    pub fn apply(&self, tags: Vec<String>) -> Vec<String> {
+        match self {
+            TagEdit::Add(tag) => dedup_tags(tags.into_iter().chain([tag.clone()]).collect()),
+            TagEdit::Remove(tag) => {
+                let tag = tag_key(tag);
+                tags.into_iter()
+                    .filter(|existing| tag_key(existing) != tag)
+                    .collect()
+            }
+            TagEdit::Rename { from, to } => {
+                let from = tag_key(from);
+                dedup_tags(
+                    tags.into_iter()
+                        .map(|existing| {
+                            if tag_key(&existing) == from {
+                                to.clone()
+                            } else {
+                                existing
+                            }
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+/// Longest `summary` sent for an app, in characters
+pub const SUMMARY_MAX_CHARS: usize = 200;
+
+/// Cuts `text` to at most `max_chars` characters, ending with an ellipsis if shortened
+fn shorten(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(max_chars - 1).collect();
+    short.push('…');
+    short
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Drops tags and decodes the basic entities, recovering the description from `content`
+fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// JSON-LD namespace for the non-standard fields of [`App`]
+pub const APP_NAMESPACE: &str = "https://docs.zesty.xyz/graph/ns#";
+
+/// Term definitions for the non-standard fields of [`App`], so LD processors keep them
+fn app_terms() -> Value {
+    json!({
+        "dsig": APP_NAMESPACE,
+        "sensitive": "as:sensitive",
+        "appId": "dsig:appId",
+        "tags": "dsig:tags",
+        "tagsList": { "@id": "dsig:tagsList", "@container": "@set" },
+        "protocol": "dsig:protocol",
+    })
+}
+
+/// Builds the `@context` for app objects: the activity context, the terms for our
+/// own fields, then any namespace aliases configured through `JSONLD_CONTEXT_ALIASES`
+pub fn app_context(data: &Data<AppState>) -> Value {
+    let mut context = match activity_context(data) {
+        Value::Array(context) => context,
+        context => vec![context],
+    };
+    context.push(app_terms());
+    if !data.context_aliases.is_empty() {
+        let aliases: Map<String, Value> = data
+            .context_aliases
+            .iter()
+            .map(|(term, iri)| (term.clone(), Value::String(iri.clone())))
+            .collect();
+        context.push(Value::Object(aliases));
+    }
+    Value::Array(context)
+}
+
+/// Wraps an app object in the context produced by [`app_context`]
+pub fn with_app_context(app: App, data: &Data<AppState>) -> WithContext<App> {
+    WithContext::new(app, app_context(data))
+}
+
 /// How the experiencce is serialized and represented as Activitypub JSON
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -148,14 +438,24 @@ pub struct App {
     pub(crate) attributed_to: String,
     #[serde(deserialize_with = "deserialize_one_or_many")]
     pub(crate) to: Vec<String>,
+    /// The world's address. Older relays only send it in `content`.
+    #[serde(default)]
+    url: Option<String>,
+    /// The address too, unless [`App::with_html_content`] put the description here
     content: String,
     name: String,
+    /// Plain-text description, shortened to [`SUMMARY_MAX_CHARS`] along with HTML `content`
     summary: String,
     #[serde(deserialize_with = "deserialize_skip_error", default)]
     image: Option<APImage>,
     sensitive: bool,
-    // Non-standard field
+    // Non-standard fields
     tags: String,
+    /// `tags` split into a list for consumers; derived, so ignored when received
+    #[serde(default, skip_deserializing)]
+    tags_list: Vec<String>,
+    #[serde(default = "default_app_protocol")]
+    protocol: String,
 }
 
 impl App {
@@ -164,12 +464,13 @@ impl App {
         id: ObjectId<DbApp>,
         attributed_to: String,
         to: Vec<String>,
-        content: String,
+        url: String,
         name: String,
-        summary: String,
+        description: String,
         image: Option<APImage>,
         sensitive: bool,
         tags: String,
+        protocol: String,
     ) -> Self {
         Self {
             app_id,
@@ -177,12 +478,45 @@ impl App {
             id,
             attributed_to,
             to,
-            content,
+            content: url.clone(),
+            summary: description,
+            url: Some(url),
             name,
-            summary,
             image,
             sensitive,
+            tags_list: split_tags(&tags),
             tags,
+            protocol,
+        }
+    }
+
+    /// Moves the description into `content` as HTML and shortens `summary`.
+    /// Relays that predate `url` would take that HTML for the address (`APP_HTML_CONTENT`).
+    pub fn with_html_content(mut self) -> Self {
+        self.content = format!("<p>{}</p>", escape_html(&self.summary));
+        self.summary = shorten(&self.summary, SUMMARY_MAX_CHARS);
+        self
+    }
+}
+
+/// Served in place of an app that has been deleted
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+    #[serde(rename = "type")]
+    kind: TombstoneType,
+    id: Url,
+    former_type: PageType,
+    deleted: DateTime<Utc>,
+}
+
+impl Tombstone {
+    pub fn new(id: Url, deleted: DateTime<Utc>) -> Self {
+        Self {
+            kind: TombstoneType::Tombstone,
+            id,
+            former_type: PageType::Page,
+            deleted,
         }
     }
 }
@@ -200,20 +534,26 @@ impl Object for DbApp {
         get_app_by_ap_id(data, object_id.as_str()).await
     }
 
-    async fn into_json(self, _data: &Data<Self::DataType>) -> Result<Self::Kind, Error> {
-        Ok(App {
-            app_id: self.id,
-            id: self.ap_id,
-            kind: PageType::Page,
-            attributed_to: "".to_string(),
-            to: vec![],
-            name: self.name,
-            summary: self.description,
-            content: self.url,
-            image: Some(APImage::new(self.image)),
-            sensitive: self.adult,
-            tags: self.tags,
-        })
+    async fn into_json(self, data: &Data<Self::DataType>) -> Result<Self::Kind, Error> {
+        let image = if self.has_real_image() {
+            Some(APImage::new(self.image.clone()).await)
+        } else {
+            None
+        };
+        let app = App::new(
+            self.id,
+            self.ap_id,
+            "".to_string(),
+            vec![],
+            self.url,
+            self.name,
+            self.description,
+            image,
+            self.adult,
+            self.tags,
+            self.protocol,
+        );
+        Ok(if data.app_html_content { app.with_html_content() } else { app })
     }
 
     async fn verify(
@@ -229,23 +569,192 @@ impl Object for DbApp {
         json: Self::Kind,
         _data: &Data<Self::DataType>,
     ) -> Result<Self, Self::Error> {
-        let image = json.image.map(|i| i.href);
+        // Peers that predate `image: null` send the placeholder as the href
+        let image = json.image.map(|i| i.href).filter(|href| href != NO_IMAGE);
+        // The full description is in `summary` unless `content` holds it as HTML.
+        // Relays that predate the `url` property only send the address in `content`.
+        let (url, description) = match json.url {
+            Some(url) if json.content != url => (url, html_to_text(&json.content)),
+            Some(url) => (url, json.summary),
+            None => (json.content, json.summary),
+        };
         let app = DbApp {
             id: json.app_id,
             ap_id: json.id,
-            url: json.content,
+            url,
             name: json.name,
-            description: json.summary,
+            description,
             active: true,
             image: image.unwrap_or_default(),
             adult: json.sensitive,
             tags: json.tags,
             visible: true,
             created_at: Utc::now(),
+            protocol: parse_app_protocol(&json.protocol).unwrap_or_else(default_app_protocol),
             slug: None,
             verification_code: None,
             verified_at: None,
+            last_source: Some(SOURCE_FEDERATION.to_string()),
+            reachable: None,
+            last_checked_at: None,
+            failed_checks: 0,
+            origin_relay_id: None,
+            signature_key_id: None,
+            signature_verified_at: None,
+            domain_verified_at: None,
         };
         Ok(app)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn app(description: &str) -> App {
+        App::new(
+            1,
+            Url::parse("https://relay.test/relay/beacon/1")
+                .unwrap()
+                .into(),
+            String::new(),
+            vec![],
+            "https://world.test/".to_string(),
+            "World".to_string(),
+            description.to_string(),
+            None,
+            false,
+            String::new(),
+            "webxr".to_string(),
+        )
+    }
+
+    async fn received(json: Value) -> DbApp {
+        let data = test_support::data(&test_support::offline_config().await);
+        DbApp::from_json(serde_json::from_value(json).unwrap(), &data)
+            .await
+            .unwrap()
+    }
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn only_known_protocols_parse() {
+        assert_eq!(parse_app_protocol("webxr").as_deref(), Some("webxr"));
+        assert_eq!(parse_app_protocol(" DeepLink ").as_deref(), Some("deeplink"));
+        assert_eq!(parse_app_protocol("NATIVE").as_deref(), Some("native"));
+        for protocol in ["https", "", "web xr", "vr"] {
+            assert_eq!(parse_app_protocol(protocol), None, "{}", protocol);
+        }
+    }
+
+    #[test]
+    fn adding_a_tag_skips_existing_spellings() {
+        let add = TagEdit::Add("VR".to_string());
+        assert_eq!(add.apply(tags(&["social"])), tags(&["social", "VR"]));
+        assert_eq!(add.apply(tags(&["vr", "social"])), tags(&["vr", "social"]));
+        // Case folding isn't limited to ASCII
+        let add = TagEdit::Add("ÉTÉ".to_string());
+        assert_eq!(add.apply(tags(&["été"])), tags(&["été"]));
+    }
+
+    #[test]
+    fn removing_a_tag_drops_every_spelling() {
+        let remove = TagEdit::Remove("Été".to_string());
+        assert_eq!(remove.apply(tags(&["ÉTÉ", "vr", "été"])), tags(&["vr"]));
+        assert_eq!(remove.apply(tags(&["vr"])), tags(&["vr"]));
+    }
+
+    #[test]
+    fn renaming_a_tag_merges_it_with_the_target() {
+        let rename = TagEdit::Rename {
+            from: "Games".to_string(),
+            to: "gaming".to_string(),
+        };
+        assert_eq!(
+            rename.apply(tags(&["games", "vr"])),
+            tags(&["gaming", "vr"])
+        );
+        // The first spelling wins when the new name is already there
+        assert_eq!(
+            rename.apply(tags(&["Gaming", "vr", "GAMES"])),
+            tags(&["Gaming", "vr"])
+        );
+        let renamed = rename.apply(tags(&["games"]));
+        assert_eq!(rename.apply(renamed.clone()), renamed);
+    }
+
+    #[actix_web::test]
+    async fn content_keeps_the_address_unless_html_content_is_on() {
+        let description = "A <big> world ".repeat(30);
+        let json = serde_json::to_value(app(&description)).unwrap();
+        assert_eq!(json["content"], "https://world.test/");
+        assert_eq!(json["url"], "https://world.test/");
+        assert_eq!(json["summary"], description.as_str());
+        let stored = received(json).await;
+        assert_eq!(stored.url, "https://world.test/");
+        assert_eq!(stored.description, description);
+
+        let json = serde_json::to_value(app(&description).with_html_content()).unwrap();
+        assert!(json["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("<p>A &lt;big&gt; world"));
+        assert_eq!(
+            json["summary"].as_str().unwrap().chars().count(),
+            SUMMARY_MAX_CHARS
+        );
+        let stored = received(json).await;
+        assert_eq!(stored.url, "https://world.test/");
+        assert_eq!(stored.description, description.trim());
+    }
+
+    #[actix_web::test]
+    async fn objects_without_url_are_read_the_old_way() {
+        let mut json = serde_json::to_value(app("Old relay")).unwrap();
+        json.as_object_mut().unwrap().remove("url");
+        let stored = received(json).await;
+        assert_eq!(stored.url, "https://world.test/");
+        assert_eq!(stored.description, "Old relay");
+    }
+
+    #[actix_web::test]
+    async fn images_are_labelled_by_content_when_stored_here() {
+        // Sets DOMAIN, which decides which images are stored by this relay
+        test_support::offline_config().await;
+        let name = format!("{}.png", test_support::unique("gif"));
+        std::fs::write(format!("images/{}", name), b"GIF89a\x01\x00\x01\x00").unwrap();
+        let local = APImage::new(format!(
+            "https://{}/images/{}",
+            test_support::TEST_DOMAIN,
+            name
+        ));
+        assert_eq!(local.await.media_type, "image/gif");
+        std::fs::remove_file(format!("images/{}", name)).unwrap();
+
+        let remote = APImage::new("https://world.test/cover.JPG".to_string()).await;
+        assert_eq!(remote.media_type, "image/jpeg");
+        let unknown = APImage::new("https://world.test/cover".to_string()).await;
+        assert_eq!(unknown.media_type, "image/png");
+    }
+
+    #[actix_web::test]
+    async fn app_objects_carry_the_extended_context() {
+        let data = test_support::data(&test_support::offline_config().await);
+        let json = serde_json::to_value(with_app_context(app(""), &data)).unwrap();
+        let context = json["@context"].as_array().unwrap();
+        assert_eq!(context[0], "https://www.w3.org/ns/activitystreams");
+        let terms = context.last().unwrap();
+        assert_eq!(terms["dsig"], APP_NAMESPACE);
+        // Every field outside ActivityStreams is defined by the terms
+        for field in ["appId", "tags", "tagsList", "protocol", "sensitive"] {
+            assert!(json.get(field).is_some(), "{}", field);
+            assert!(terms.get(field).is_some(), "{}", field);
+        }
+        assert_eq!(terms["tagsList"]["@container"], "@set");
+        assert_eq!(json["protocol"], "webxr");
+    }
+}
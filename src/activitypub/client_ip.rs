@@ -0,0 +1,235 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use actix_web::http::header;
+use actix_web::HttpRequest;
+
+/// An address or CIDR range listed in `TRUSTED_PROXIES`
+#[derive(Clone, Copy, Debug)]
+struct TrustedProxy {
+    network: IpAddr,
+    prefix_len: u32,
+}
+
+impl TrustedProxy {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+        let network = address
+            .parse::<IpAddr>()
+            .map_err(|_| format!("'{}' is not an IP address or CIDR range", s))?
+            .to_canonical();
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u32>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("'{}' has an invalid prefix length", s))?,
+            None => max_len,
+        };
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+/// Reverse proxies whose `Forwarded`/`X-Forwarded-For` headers are believed. With none
+/// configured the headers are ignored, since any client could set them.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Vec<TrustedProxy>);
+
+impl TrustedProxies {
+    /// Parses a comma-separated list of addresses and CIDR ranges
+    pub fn parse(list: &str) -> Result<Self, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
+    }
+
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|proxy| proxy.contains(ip))
+    }
+
+    /// The address of the client behind `request`. Forwarded hops are walked from the
+    /// nearest one back, and the first address that isn't a trusted proxy is the client.
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let peer = request.peer_addr()?.ip().to_canonical();
+        if !self.trusts(peer) {
+            return Some(peer);
+        }
+        let mut hops = forwarded_hops(request);
+        let mut client = peer;
+        while let Some(hop) = hops.pop() {
+            client = hop;
+            if !self.trusts(hop) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+/// Client addresses from `Forwarded`, or `X-Forwarded-For` if that's absent, farthest
+/// hop first. Obfuscated identifiers and `unknown` are skipped.
+fn forwarded_hops(request: &HttpRequest) -> Vec<IpAddr> {
+    let headers = request.headers();
+    let forwarded: Vec<&str> = headers
+        .get_all(header::FORWARDED)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .flat_map(|element| element.split(';'))
+        .filter_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            name.eq_ignore_ascii_case("for").then_some(value)
+        })
+        .collect();
+    let hops: Vec<&str> = if forwarded.is_empty() {
+        headers
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect()
+    } else {
+        forwarded
+    };
+    hops.into_iter().filter_map(parse_hop).collect()
+}
+
+/// Reads `1.2.3.4`, `1.2.3.4:80`, `2001:db8::1` or `"[2001:db8::1]:80"`
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim().trim_matches('"');
+    if let Ok(ip) = hop.parse::<IpAddr>() {
+        return Some(ip.to_canonical());
+    }
+    let host = match hop.strip_prefix('[') {
+        Some(rest) => rest.split_once(']')?.0,
+        None => hop.rsplit_once(':')?.0,
+    };
+    host.parse::<IpAddr>().ok().map(|ip| ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn client_ip(proxies: &str, peer: &str, headers: &[(&str, &str)]) -> IpAddr {
+        let mut request = TestRequest::default().peer_addr(SocketAddr::new(ip(peer), 4000));
+        for header in headers {
+            request = request.append_header(*header);
+        }
+        TrustedProxies::parse(proxies)
+            .unwrap()
+            .client_ip(&request.to_http_request())
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_addresses_and_ranges() {
+        let proxies = TrustedProxies::parse(" 10.0.0.0/8, ,192.168.1.1,fd00::/8 ").unwrap();
+        assert_eq!(proxies.0.len(), 3);
+        assert_eq!(proxies.0[1].prefix_len, 32);
+        assert!(TrustedProxies::parse("").unwrap().0.is_empty());
+        for invalid in ["proxy.local", "10.0.0.0/33", "fd00::/129", "10.0.0.0/x"] {
+            assert!(TrustedProxies::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn ranges_contain_their_addresses() {
+        let range: TrustedProxy = "10.1.0.0/16".parse().unwrap();
+        assert!(range.contains(ip("10.1.255.7")));
+        assert!(!range.contains(ip("10.2.0.1")));
+        assert!(!range.contains(ip("::1")));
+        let everything: TrustedProxy = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains(ip("203.0.113.9")));
+        let v6: TrustedProxy = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+        // IPv4-mapped addresses are compared as IPv4
+        let mapped: TrustedProxy = "::ffff:127.0.0.1".parse().unwrap();
+        assert!(mapped.contains(ip("127.0.0.1")));
+    }
+
+    #[test]
+    fn forwarded_is_preferred_over_x_forwarded_for() {
+        let headers = [
+            (
+                "Forwarded",
+                "for=198.51.100.4;proto=https, for=\"[2001:db8::7]:443\"",
+            ),
+            ("X-Forwarded-For", "203.0.113.1"),
+        ];
+        assert_eq!(
+            client_ip("127.0.0.1", "127.0.0.1", &headers),
+            ip("2001:db8::7")
+        );
+        let headers = [("X-Forwarded-For", "203.0.113.1, 10.0.0.2:8080")];
+        assert_eq!(
+            client_ip("127.0.0.1,10.0.0.0/8", "127.0.0.1", &headers),
+            ip("203.0.113.1")
+        );
+    }
+
+    #[test]
+    fn hops_are_walked_back_to_the_first_untrusted_address() {
+        // The client put a fake address in front of the real one
+        let headers = [("X-Forwarded-For", "1.1.1.1, 203.0.113.1, 10.0.0.2")];
+        assert_eq!(
+            client_ip("127.0.0.1,10.0.0.0/8", "127.0.0.1", &headers),
+            ip("203.0.113.1")
+        );
+        // Only trusted hops and unreadable ones leave the farthest proxy
+        let headers = [("Forwarded", "for=unknown, for=10.0.0.2")];
+        assert_eq!(
+            client_ip("127.0.0.1,10.0.0.0/8", "127.0.0.1", &headers),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn untrusted_peers_cannot_spoof_their_address() {
+        let headers = [
+            ("Forwarded", "for=198.51.100.4"),
+            ("X-Forwarded-For", "198.51.100.4"),
+        ];
+        assert_eq!(
+            client_ip("127.0.0.1", "203.0.113.50", &headers),
+            ip("203.0.113.50")
+        );
+        // Without trusted proxies the headers are never read
+        assert_eq!(client_ip("", "127.0.0.1", &headers), ip("127.0.0.1"));
+    }
+}
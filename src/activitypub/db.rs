@@ -1,12 +1,32 @@
+use std::future::Future;
+use std::time::Instant;
+
 use activitypub_federation::config::Data;
-use sqlx::Row;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgExecutor, Row};
 
-use super::activities::DbActivity;
+use super::activities::{ActivityFilter, ActivityKind, ActivityLogEntry, DbActivity};
 use super::actors::DbRelay;
-use super::apps::DbApp;
+use super::apps::{
+    join_tags, split_tags, DbApp, TagEdit, SOURCE_ADMIN, SOURCE_FEDERATION, SOURCE_OWNER,
+};
 use super::error::Error;
 use crate::AppState;
 
+/// Awaits a query, logging a warning when it runs longer than SLOW_QUERY_MS
+async fn timed<F, T>(data: &Data<AppState>, label: &str, query: F) -> T
+where
+    F: Future<Output = T>,
+{
+    let start = Instant::now();
+    let result = query.await;
+    let elapsed = start.elapsed();
+    if elapsed.as_millis() >= data.slow_query_ms as u128 {
+        log_error!("Slow query '{}' took {}ms", label, elapsed.as_millis());
+    }
+    result
+}
+
 pub async fn get_system_user(data: &Data<AppState>) -> Result<DbRelay, Error> {
     let db = &data.db;
     let user = sqlx::query_as::<_, DbRelay>("SELECT * FROM relays WHERE id = 0 LIMIT 1")
@@ -16,6 +36,10 @@ pub async fn get_system_user(data: &Data<AppState>) -> Result<DbRelay, Error> {
 }
 
 pub async fn get_app_by_id(id: i32, data: &Data<AppState>) -> Result<DbApp, Error> {
+    // Id 0 belongs to the relay system user, never to an app
+    if id <= 0 {
+        return Err(Error::NotFound);
+    }
     let db = &data.db;
     let app = sqlx::query_as::<_, DbApp>("SELECT * FROM apps WHERE id = $1")
         .bind(id)
@@ -46,14 +70,211 @@ pub async fn get_app_by_base_url(data: &Data<AppState>, base_url: &str) -> Resul
     Ok(app)
 }
 
-pub async fn get_all_apps(data: &Data<AppState>) -> Result<Vec<DbApp>, Error> {
+/// Count apps whose URL is served from the given host
+pub async fn count_apps_by_host(data: &Data<AppState>, host: &str) -> Result<i64, Error> {
+    let db = &data.db;
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM apps WHERE lower(substring(url from '^[^:]+://([^/:?#]+)')) = lower($1)",
+    )
+    .bind(host)
+    .fetch_one(db)
+    .await?;
+    Ok(count)
+}
+
+/// Image URLs of every app, used to find image files that are no longer referenced
+pub async fn get_all_app_images(data: &Data<AppState>) -> Result<Vec<String>, Error> {
     let db = &data.db;
-    let apps = sqlx::query_as::<_, DbApp>("SELECT * FROM apps ORDER BY id ASC")
+    let images = sqlx::query_scalar::<_, String>("SELECT image FROM apps")
+        .fetch_all(db)
+        .await?;
+    Ok(images)
+}
+
+/// (id, url) of every app, for the beacon health check
+pub async fn get_app_urls(data: &Data<AppState>) -> Result<Vec<(i32, String)>, Error> {
+    let db = &data.db;
+    let apps = sqlx::query_as::<_, (i32, String)>("SELECT id, url FROM apps ORDER BY id")
         .fetch_all(db)
         .await?;
     Ok(apps)
 }
 
+/// Stores a health check result. An app is hidden once it reaches `hide_after`
+/// consecutive failures (`0` never hides); returns whether this check hid it.
+pub async fn record_app_health(
+    data: &Data<AppState>,
+    id: i32,
+    reachable: bool,
+    hide_after: i32,
+) -> Result<bool, Error> {
+    let db = &data.db;
+    let failed_checks = sqlx::query_scalar::<_, i32>(
+        "UPDATE apps SET reachable = $2, last_checked_at = NOW(), \
+         failed_checks = CASE WHEN $2 THEN 0 ELSE failed_checks + 1 END \
+         WHERE id = $1 RETURNING failed_checks",
+    )
+    .bind(id)
+    .bind(reachable)
+    .fetch_one(db)
+    .await?;
+    // Only hide on the check that crosses the threshold so an admin can show it again
+    if hide_after > 0 && failed_checks == hide_after {
+        sqlx::query("UPDATE apps SET visible = FALSE WHERE id = $1")
+            .bind(id)
+            .execute(db)
+            .await?;
+        data.index_cache.invalidate();
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Records which key signed the Create/Update `activity_id`, once the inbox has verified it
+pub async fn record_app_signature(
+    data: &Data<AppState>,
+    activity_id: &str,
+    key_id: &str,
+) -> Result<(), Error> {
+    let db = &data.db;
+    sqlx::query(
+        "UPDATE apps SET signature_key_id = $2, signature_verified_at = NOW() \
+         WHERE activitypub_id = (SELECT obj FROM activities \
+         WHERE activitypub_id = $1 AND kind IN ('Create', 'Update') LIMIT 1)",
+    )
+    .bind(activity_id)
+    .bind(key_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// A page of apps received from a relay, oldest first, with the total count.
+/// Hidden and inactive apps are only included when `include_hidden` is set.
+pub async fn get_apps_by_origin_relay(
+    data: &Data<AppState>,
+    relay_id: i32,
+    include_hidden: bool,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<DbApp>, i64), Error> {
+    let db = &data.db;
+    let filter = "WHERE origin_relay_id = $1 AND ($2 OR (visible = TRUE AND is_active = TRUE))";
+    let apps = sqlx::query_as::<_, DbApp>(&format!(
+        "SELECT * FROM apps {} ORDER BY id LIMIT $3 OFFSET $4",
+        filter
+    ))
+    .bind(relay_id)
+    .bind(include_hidden)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await?;
+    let total = sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM apps {}", filter))
+        .bind(relay_id)
+        .bind(include_hidden)
+        .fetch_one(db)
+        .await?;
+    Ok((apps, total))
+}
+
+pub async fn get_all_apps(data: &Data<AppState>) -> Result<Vec<DbApp>, Error> {
+    let db = &data.db;
+    let query = sqlx::query_as::<_, DbApp>("SELECT * FROM apps ORDER BY id ASC").fetch_all(db);
+    let apps = timed(data, "get_all_apps", query).await?;
+    Ok(apps)
+}
+
+/// Get apps that are safe to advertise to search engines (visible, active and not adult)
+pub async fn get_public_apps(data: &Data<AppState>) -> Result<Vec<DbApp>, Error> {
+    let db = &data.db;
+    let apps = sqlx::query_as::<_, DbApp>(
+        "SELECT * FROM apps WHERE visible = TRUE AND is_active = TRUE AND is_adult = FALSE \
+         ORDER BY id ASC",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(apps)
+}
+
+/// One page of the apps listed in `/relay/catalog` (visible and active), oldest first
+pub async fn get_catalog_apps_page(
+    data: &Data<AppState>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<DbApp>, Error> {
+    let db = &data.db;
+    let apps = sqlx::query_as::<_, DbApp>(
+        "SELECT * FROM apps WHERE visible = TRUE AND is_active = TRUE \
+         ORDER BY id ASC LIMIT $1 OFFSET $2",
+    )
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await?;
+    Ok(apps)
+}
+
+/// Number of apps listed in `/relay/catalog`
+pub async fn get_catalog_apps_count(data: &Data<AppState>) -> Result<i64, Error> {
+    let db = &data.db;
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM apps WHERE visible = TRUE AND is_active = TRUE",
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(count)
+}
+
+/// Visible, active apps sharing tags with app `id`, most shared tags first, with the
+/// number shared. Tags are compared trimmed and case-insensitively.
+pub async fn get_similar_apps(
+    data: &Data<AppState>,
+    id: i32,
+    limit: i64,
+) -> Result<Vec<(DbApp, i64)>, Error> {
+    let db = &data.db;
+    let rows = sqlx::query(
+        "WITH target AS ( \
+           SELECT ARRAY( \
+             SELECT DISTINCT lower(trim(tag)) FROM unnest(string_to_array(tags, ',')) tag \
+             WHERE trim(tag) <> '' \
+           ) AS tags FROM apps WHERE id = $1 \
+         ), scored AS ( \
+           SELECT a.*, ( \
+             SELECT COUNT(DISTINCT lower(trim(tag))) FROM unnest(string_to_array(a.tags, ',')) tag \
+             WHERE lower(trim(tag)) = ANY(target.tags) \
+           ) AS shared_tags \
+           FROM apps a, target \
+           WHERE a.id <> $1 AND a.visible = TRUE AND a.is_active = TRUE \
+         ) \
+         SELECT * FROM scored WHERE shared_tags > 0 \
+         ORDER BY shared_tags DESC, created_at DESC, id DESC LIMIT $2",
+    )
+    .bind(id)
+    .bind(limit)
+    .fetch_all(db)
+    .await?;
+    let apps = rows
+        .iter()
+        .map(|row| Ok((DbApp::from_row(row)?, row.try_get("shared_tags")?)))
+        .collect::<Result<Vec<_>, sqlx::Error>>()?;
+    Ok(apps)
+}
+
+/// Most recently created visible, active, non-adult apps, newest first
+pub async fn get_recent_apps(data: &Data<AppState>, limit: i64) -> Result<Vec<DbApp>, Error> {
+    let db = &data.db;
+    let apps = sqlx::query_as::<_, DbApp>(
+        "SELECT * FROM apps WHERE visible = TRUE AND is_active = TRUE AND is_adult = FALSE \
+         ORDER BY created_at DESC, id DESC LIMIT $1",
+    )
+    .bind(limit)
+    .fetch_all(db)
+    .await?;
+    Ok(apps)
+}
+
 pub async fn get_apps_count(data: &Data<AppState>) -> Result<i64, Error> {
     let db = &data.db;
     let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM apps")
@@ -62,76 +283,467 @@ pub async fn get_apps_count(data: &Data<AppState>) -> Result<i64, Error> {
     Ok(count)
 }
 
-pub async fn create_app(
+/// Reserves the next app id so the AP id can be built from it before inserting
+pub async fn reserve_app_id(data: &Data<AppState>) -> Result<i32, Error> {
+    let db = &data.db;
+    let id = sqlx::query_scalar::<_, i32>("SELECT nextval(pg_get_serial_sequence('apps', 'id'))::INT")
+        .fetch_one(db)
+        .await?;
+    Ok(id)
+}
+
+pub async fn update_app(
     data: &Data<AppState>,
     activitypub_id: String,
-    url: String,
     name: String,
     description: String,
     is_active: bool,
     image_url: String,
     is_adult: bool,
     tags: String,
+    protocol: String,
+    last_source: &str,
 ) -> Result<(), Error> {
     let db = &data.db;
-    sqlx::query("INSERT INTO apps (activitypub_id, url, name, description, is_active, image, is_adult, tags) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
-        .bind(activitypub_id)
-        .bind(url)
-        .bind(name)
-        .bind(description)
-        .bind(is_active)
-        .bind(image_url)
-        .bind(is_adult)
-        .bind(tags)
-        .execute(db)
-        .await?;
+    let updated = update_app_by_ap_id(
+        db,
+        &activitypub_id,
+        &name,
+        &description,
+        is_active,
+        &image_url,
+        is_adult,
+        &tags,
+        &protocol,
+        last_source,
+    )
+    .await?;
+    if updated == 0 {
+        return Err(Error::NotFound);
+    }
+    data.index_cache.invalidate();
     Ok(())
 }
 
-pub async fn update_app(
+/// Inserts an app and the activity that created it in a single transaction, so an app
+/// never exists without its activity. Pass `None` as the id to use the next serial id.
+/// The app's `last_source` is stored as given, defaulting to federation.
+/// `activitypub_id` is the received Create's id; with `None` the activity is this
+/// relay's own and gets an id from its row. Returns the activity's AP id.
+pub async fn create_app_with_activity(
     data: &Data<AppState>,
-    url: String,
-    name: String,
-    description: String,
+    id: Option<i32>,
+    app: &DbApp,
+    activitypub_id: Option<&str>,
+    actor: &str,
+) -> Result<String, Error> {
+    let mut tx = data.db.begin().await?;
+    insert_app(
+        &mut *tx,
+        id,
+        app.ap_id.inner().as_str(),
+        &app.url,
+        &app.name,
+        &app.description,
+        app.active,
+        &app.image,
+        app.adult,
+        &app.tags,
+        &app.protocol,
+        app.last_source.as_deref().unwrap_or(SOURCE_FEDERATION),
+        app.visible,
+        app.domain_verified_at,
+    )
+    .await?;
+    let activitypub_id = match activitypub_id {
+        Some(activitypub_id) => {
+            insert_activity(
+                &mut *tx,
+                activitypub_id,
+                actor,
+                app.ap_id.inner().as_str(),
+                ActivityKind::Create,
+            )
+            .await?;
+            activitypub_id.to_string()
+        }
+        None => {
+            insert_local_activity(&mut *tx, actor, app.ap_id.inner().as_str(), ActivityKind::Create)
+                .await?
+        }
+    };
+    sqlx::query(
+        "UPDATE apps SET origin_relay_id = (SELECT id FROM relays WHERE activitypub_id = $1) \
+         WHERE activitypub_id = $2",
+    )
+    .bind(actor)
+    .bind(app.ap_id.inner().as_str())
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    data.index_cache.invalidate();
+    Ok(activitypub_id)
+}
+
+/// Updates a federated app (matched by AP id) and records the Update activity in a single
+/// transaction. Claimed apps and apps whose `last_source` is one of `protected_sources`
+/// are left as they are, returning `false`; an app that isn't stored is `NotFound` and
+/// nothing is written.
+pub async fn update_app_with_activity(
+    data: &Data<AppState>,
+    app: &DbApp,
+    activitypub_id: &str,
+    actor: &str,
+    protected_sources: &[&str],
+) -> Result<bool, Error> {
+    let mut tx = data.db.begin().await?;
+    // Locks the row the update below writes, so the check and the write see the same app
+    let current = sqlx::query_as::<_, (Option<String>, bool)>(
+        "SELECT last_source, claimed_at IS NOT NULL FROM apps WHERE activitypub_id = $1 \
+         FOR UPDATE",
+    )
+    .bind(app.ap_id.inner().as_str())
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some((last_source, claimed)) = current {
+        // A claimed app is edited by its owner here, not by the relay it came from
+        if claimed || last_source.is_some_and(|source| protected_sources.contains(&source.as_str()))
+        {
+            return Ok(false);
+        }
+    }
+    let updated = update_app_by_ap_id(
+        &mut *tx,
+        app.ap_id.inner().as_str(),
+        &app.name,
+        &app.description,
+        app.active,
+        &app.image,
+        app.adult,
+        &app.tags,
+        &app.protocol,
+        SOURCE_FEDERATION,
+    )
+    .await?;
+    // Dropping the transaction rolls it back, so no Update is recorded for a missing app
+    if updated == 0 {
+        return Err(Error::NotFound);
+    }
+    insert_activity(
+        &mut *tx,
+        activitypub_id,
+        actor,
+        app.ap_id.inner().as_str(),
+        ActivityKind::Update,
+    )
+    .await?;
+    tx.commit().await?;
+    data.index_cache.invalidate();
+    Ok(true)
+}
+
+async fn insert_app<'e, E: PgExecutor<'e>>(
+    executor: E,
+    id: Option<i32>,
+    activitypub_id: &str,
+    url: &str,
+    name: &str,
+    description: &str,
     is_active: bool,
-    image_url: String,
+    image_url: &str,
     is_adult: bool,
-    tags: String,
+    tags: &str,
+    protocol: &str,
+    last_source: &str,
+    visible: bool,
+    domain_verified_at: Option<DateTime<Utc>>,
 ) -> Result<(), Error> {
-    let db = &data.db;
     sqlx::query(
-        "UPDATE apps SET name = $1, description = $2, is_active = $3, image = $4, is_adult = $5, tags = $6 WHERE url = $7",
+        "INSERT INTO apps (id, activitypub_id, url, name, description, is_active, image, is_adult, tags, protocol, last_source, visible, domain_verified_at) \
+         VALUES (COALESCE($1, nextval(pg_get_serial_sequence('apps', 'id'))), $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
     )
+    .bind(id)
+    .bind(activitypub_id)
+    .bind(url)
     .bind(name)
     .bind(description)
     .bind(is_active)
     .bind(image_url)
     .bind(is_adult)
     .bind(tags)
-    .bind(url)
+    .bind(protocol)
+    .bind(last_source)
+    .bind(visible)
+    .bind(domain_verified_at)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Returns the number of rows changed, 0 when no app has the AP id
+async fn update_app_by_ap_id<'e, E: PgExecutor<'e>>(
+    executor: E,
+    activitypub_id: &str,
+    name: &str,
+    description: &str,
+    is_active: bool,
+    image_url: &str,
+    is_adult: bool,
+    tags: &str,
+    protocol: &str,
+    last_source: &str,
+) -> Result<u64, Error> {
+    let result = sqlx::query(
+        "UPDATE apps SET name = $1, description = $2, is_active = $3, image = $4, is_adult = $5, tags = $6, protocol = $7, last_source = $8 WHERE activitypub_id = $9",
+    )
+    .bind(name)
+    .bind(description)
+    .bind(is_active)
+    .bind(image_url)
+    .bind(is_adult)
+    .bind(tags)
+    .bind(protocol)
+    .bind(last_source)
+    .bind(activitypub_id)
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Shows a hidden app or hides a visible one, returning whether it is now visible
+pub async fn toggle_app_visibility(id: i32, data: &Data<AppState>) -> Result<bool, Error> {
+    let db = &data.db;
+    let visible = sqlx::query_scalar::<_, bool>(
+        "UPDATE apps SET visible = NOT visible WHERE id = $1 RETURNING visible",
+    )
+    .bind(id)
+    .fetch_one(db)
+    .await?;
+    data.index_cache.invalidate();
+    Ok(visible)
+}
+
+/// Flips an app's adult flag as an admin edit, returning the new value
+pub async fn toggle_app_adult(id: i32, data: &Data<AppState>) -> Result<bool, Error> {
+    let db = &data.db;
+    let adult = sqlx::query_scalar::<_, bool>(
+        "UPDATE apps SET is_adult = NOT is_adult, last_source = $2 WHERE id = $1 RETURNING is_adult",
+    )
+    .bind(id)
+    .bind(SOURCE_ADMIN)
+    .fetch_one(db)
+    .await?;
+    data.index_cache.invalidate();
+    Ok(adult)
+}
+
+/// Persists a feature flag override so it survives restarts
+pub async fn set_feature(name: &str, enabled: bool, data: &Data<AppState>) -> Result<(), Error> {
+    let db = &data.db;
+    sqlx::query(
+        "INSERT INTO features (name, enabled) VALUES ($1, $2) \
+         ON CONFLICT (name) DO UPDATE SET enabled = EXCLUDED.enabled",
+    )
+    .bind(name)
+    .bind(enabled)
     .execute(db)
     .await?;
     Ok(())
 }
 
-pub async fn toggle_app_visibility(id: i32, data: &Data<AppState>) -> Result<(), Error> {
+/// Applies `edit` to the tags of the given apps, or of every app when `app_ids` is `None`,
+/// in one transaction. Apps whose tags come out the same aren't written; the changed
+/// ones are returned.
+pub async fn edit_app_tags(
+    data: &Data<AppState>,
+    app_ids: Option<&[i32]>,
+    edit: &TagEdit,
+) -> Result<Vec<DbApp>, Error> {
+    let mut tx = data.db.begin().await?;
+    let apps = match app_ids {
+        Some(app_ids) => {
+            sqlx::query_as::<_, DbApp>(
+                "SELECT * FROM apps WHERE id = ANY($1) ORDER BY id FOR UPDATE",
+            )
+            .bind(app_ids)
+            .fetch_all(&mut *tx)
+            .await?
+        }
+        None => {
+            sqlx::query_as::<_, DbApp>("SELECT * FROM apps ORDER BY id FOR UPDATE")
+                .fetch_all(&mut *tx)
+                .await?
+        }
+    };
+    let mut changed = Vec::new();
+    for mut app in apps {
+        let tags = split_tags(&app.tags);
+        let edited = edit.apply(tags.clone());
+        if edited == tags {
+            continue;
+        }
+        app.tags = join_tags(&edited);
+        sqlx::query("UPDATE apps SET tags = $1, last_source = $2 WHERE id = $3")
+            .bind(&app.tags)
+            .bind(SOURCE_ADMIN)
+            .bind(app.id)
+            .execute(&mut *tx)
+            .await?;
+        changed.push(app);
+    }
+    tx.commit().await?;
+    if !changed.is_empty() {
+        data.index_cache.invalidate();
+    }
+    Ok(changed)
+}
+
+/// How long a claim challenge can be completed after it was issued
+const CLAIM_CHALLENGE_TTL_HOURS: i32 = 24;
+
+/// Starts a claim on an app: the site has to publish `nonce`, and the claim can then only
+/// be completed with the key hashed into `key_hash`
+pub async fn create_claim_challenge(
+    data: &Data<AppState>,
+    app_id: i32,
+    nonce: &str,
+    key_hash: &str,
+) -> Result<(), Error> {
     let db = &data.db;
-    sqlx::query("UPDATE apps SET visible = NOT visible WHERE id = $1")
-        .bind(id)
+    sqlx::query("INSERT INTO claim_challenges (app_id, nonce, key_hash) VALUES ($1, $2, $3)")
+        .bind(app_id)
+        .bind(nonce)
+        .bind(key_hash)
         .execute(db)
         .await?;
     Ok(())
 }
 
-pub async fn delete_app(id: i32, data: &Data<AppState>) -> Result<(), Error> {
+/// Whether an app was claimed with the key hashed into `key_hash`
+pub async fn is_app_claim_key(
+    data: &Data<AppState>,
+    app_id: i32,
+    key_hash: &str,
+) -> Result<bool, Error> {
+    let db = &data.db;
+    let matches = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM apps WHERE id = $1 AND claim_key_hash = $2)",
+    )
+    .bind(app_id)
+    .bind(key_hash)
+    .fetch_one(db)
+    .await?;
+    Ok(matches)
+}
+
+/// The nonce of an unexpired claim challenge on an app issued for the given key
+pub async fn get_claim_challenge(
+    data: &Data<AppState>,
+    app_id: i32,
+    key_hash: &str,
+) -> Result<Option<String>, Error> {
     let db = &data.db;
+    let nonce = sqlx::query_scalar::<_, String>(
+        "SELECT nonce FROM claim_challenges \
+         WHERE app_id = $1 AND key_hash = $2 AND created_at > NOW() - make_interval(hours => $3) \
+         ORDER BY id DESC LIMIT 1",
+    )
+    .bind(app_id)
+    .bind(key_hash)
+    .bind(CLAIM_CHALLENGE_TTL_HOURS)
+    .fetch_optional(db)
+    .await?;
+    Ok(nonce)
+}
+
+/// Records a claim on an app by whoever controls its site, which also verifies its domain,
+/// and drops its pending challenges. Returns false, changing nothing, if the app is already
+/// claimed under a different key.
+pub async fn claim_app(
+    data: &Data<AppState>,
+    app_id: i32,
+    claimed_by: &str,
+    key_hash: &str,
+) -> Result<bool, Error> {
+    let mut tx = data.db.begin().await?;
+    let claimed = sqlx::query_scalar::<_, i32>(
+        "UPDATE apps SET claimed_at = NOW(), claimed_by = $2, claim_key_hash = $3, \
+         domain_verified_at = NOW() \
+         WHERE id = $1 AND (claim_key_hash IS NULL OR claim_key_hash = $3) RETURNING id",
+    )
+    .bind(app_id)
+    .bind(claimed_by)
+    .bind(key_hash)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if claimed.is_none() {
+        return Ok(false);
+    }
+    sqlx::query("DELETE FROM claim_challenges WHERE app_id = $1")
+        .bind(app_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    data.index_cache.invalidate();
+    Ok(true)
+}
+
+/// Deletes an app, leaving a tombstone for its AP id so peers learn it is gone
+pub async fn delete_app(id: i32, data: &Data<AppState>) -> Result<(), Error> {
+    let mut tx = data.db.begin().await?;
+    sqlx::query(
+        "INSERT INTO tombstones (activitypub_id, app_id, slug) \
+         SELECT activitypub_id, id, slug FROM apps WHERE id = $1 ON CONFLICT DO NOTHING",
+    )
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
     sqlx::query("DELETE FROM apps WHERE id = $1")
         .bind(id)
-        .execute(db)
+        .execute(&mut *tx)
         .await?;
+    tx.commit().await?;
+    data.index_cache.invalidate();
     Ok(())
 }
 
+/// AP id and deletion time of a removed app, looked up by its former local id or slug
+/// the same way `/app/{id_or_slug}` resolves live apps
+pub async fn get_app_tombstone(
+    data: &Data<AppState>,
+    id_or_slug: &str,
+) -> Result<Option<(String, DateTime<Utc>)>, Error> {
+    let db = &data.db;
+    let query = match id_or_slug.parse::<i32>() {
+        Ok(id) => sqlx::query_as(
+            "SELECT activitypub_id, deleted_at FROM tombstones WHERE app_id = $1 LIMIT 1",
+        )
+        .bind(id),
+        Err(_) => sqlx::query_as(
+            "SELECT activitypub_id, deleted_at FROM tombstones WHERE slug = $1 \
+             ORDER BY deleted_at DESC LIMIT 1",
+        )
+        .bind(id_or_slug),
+    };
+    let tombstone = query.fetch_optional(db).await?;
+    Ok(tombstone)
+}
+
+/// Returns when the app with this AP id was deleted, if it ever existed and was removed
+pub async fn get_tombstone(
+    data: &Data<AppState>,
+    ap_id: &str,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    let db = &data.db;
+    let deleted_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+        "SELECT deleted_at FROM tombstones WHERE activitypub_id = $1",
+    )
+    .bind(ap_id)
+    .fetch_optional(db)
+    .await?;
+    Ok(deleted_at)
+}
+
 pub async fn get_activity_by_id(id: i32, data: &Data<AppState>) -> Result<DbActivity, Error> {
     let db = &data.db;
     let activity = sqlx::query_as::<_, DbActivity>("SELECT * FROM activities WHERE id = $1")
@@ -141,6 +753,65 @@ pub async fn get_activity_by_id(id: i32, data: &Data<AppState>) -> Result<DbActi
     Ok(activity)
 }
 
+/// A page of stored activities matching `filter`, newest first, with the total count
+pub async fn list_activities(
+    data: &Data<AppState>,
+    filter: &ActivityFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<ActivityLogEntry>, i64), Error> {
+    let db = &data.db;
+    let conditions = "WHERE ($1::TEXT IS NULL OR ac.kind = $1) \
+         AND ($2::TEXT IS NULL OR split_part(ac.actor, '/', 3) = $2) \
+         AND ($3::TIMESTAMPTZ IS NULL OR ac.created_at >= $3) \
+         AND ($4::TIMESTAMPTZ IS NULL OR ac.created_at < $4)";
+    let kind = filter.kind.map(|kind| kind.as_str());
+    let activities = sqlx::query_as::<_, ActivityLogEntry>(&format!(
+        "SELECT ac.id, ac.activitypub_id, ac.actor, ac.obj, ac.kind, ac.created_at, \
+         a.id AS app_id, a.slug AS app_slug \
+         FROM activities ac LEFT JOIN apps a ON a.activitypub_id = ac.obj \
+         {} ORDER BY ac.id DESC LIMIT $5 OFFSET $6",
+        conditions
+    ))
+    .bind(kind)
+    .bind(&filter.actor_host)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await?;
+    let total = sqlx::query_scalar::<_, i64>(&format!(
+        "SELECT COUNT(*) FROM activities ac {}",
+        conditions
+    ))
+    .bind(kind)
+    .bind(&filter.actor_host)
+    .bind(filter.since)
+    .bind(filter.until)
+    .fetch_one(db)
+    .await?;
+    Ok((activities, total))
+}
+
+/// Every stored activity whose object is `obj`, oldest first
+pub async fn get_activities_for_object(
+    data: &Data<AppState>,
+    obj: &str,
+) -> Result<Vec<ActivityLogEntry>, Error> {
+    let db = &data.db;
+    let activities = sqlx::query_as::<_, ActivityLogEntry>(
+        "SELECT ac.id, ac.activitypub_id, ac.actor, ac.obj, ac.kind, ac.created_at, \
+         a.id AS app_id, a.slug AS app_slug \
+         FROM activities ac LEFT JOIN apps a ON a.activitypub_id = ac.obj \
+         WHERE ac.obj = $1 ORDER BY ac.id ASC",
+    )
+    .bind(obj)
+    .fetch_all(db)
+    .await?;
+    Ok(activities)
+}
+
 pub async fn get_activities_count(data: &Data<AppState>) -> Result<i64, Error> {
     let db = &data.db;
     let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM activities")
@@ -154,21 +825,179 @@ pub async fn create_activity(
     activitypub_id: String,
     actor: &str,
     obj: &str,
-    kind: &str,
+    kind: ActivityKind,
+) -> Result<i32, Error> {
+    let db = &data.db;
+    insert_activity(db, &activitypub_id, actor, obj, kind).await
+}
+
+/// Records an activity sent by this relay as `actor` and returns its AP id
+pub async fn create_local_activity(
+    data: &Data<AppState>,
+    actor: &str,
+    obj: &str,
+    kind: ActivityKind,
+) -> Result<String, Error> {
+    let db = &data.db;
+    insert_local_activity(db, actor, obj, kind).await
+}
+
+/// Keeps the full document of a stored activity so it can be served back exactly as it
+/// was sent. The first copy wins; activities without a row are ignored.
+pub async fn store_activity_raw(
+    data: &Data<AppState>,
+    activitypub_id: &str,
+    raw: &serde_json::Value,
 ) -> Result<(), Error> {
     let db = &data.db;
-    sqlx::query(
-        "INSERT INTO activities (activitypub_id, actor, obj, kind) VALUES ($1, $2, $3, $4)",
+    sqlx::query("UPDATE activities SET raw = $2 WHERE activitypub_id = $1 AND raw IS NULL")
+        .bind(activitypub_id)
+        .bind(raw)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+async fn insert_activity<'e, E: PgExecutor<'e>>(
+    executor: E,
+    activitypub_id: &str,
+    actor: &str,
+    obj: &str,
+    kind: ActivityKind,
+) -> Result<i32, Error> {
+    let id = sqlx::query_scalar::<_, i32>(
+        "INSERT INTO activities (activitypub_id, actor, obj, kind) VALUES ($1, $2, $3, $4) \
+         RETURNING id",
     )
     .bind(activitypub_id)
     .bind(actor)
     .bind(obj)
-    .bind(kind)
-    .execute(db)
+    .bind(kind.as_str())
+    .fetch_one(executor)
+    .await?;
+    Ok(id)
+}
+
+/// Inserts an activity of this relay's with an AP id built from the row's own id, so
+/// concurrent inserts can't collide and `/relay/activities/{id}` serves it back
+async fn insert_local_activity<'e, E: PgExecutor<'e>>(
+    executor: E,
+    actor: &str,
+    obj: &str,
+    kind: ActivityKind,
+) -> Result<String, Error> {
+    let activitypub_id = sqlx::query_scalar::<_, String>(
+        "INSERT INTO activities (id, activitypub_id, actor, obj, kind) \
+         SELECT next.id, $1 || '/activities/' || next.id, $1, $2, $3 \
+         FROM (SELECT nextval(pg_get_serial_sequence('activities', 'id'))::INT AS id) next \
+         RETURNING activitypub_id",
+    )
+    .bind(actor)
+    .bind(obj)
+    .bind(kind.as_str())
+    .fetch_one(executor)
+    .await?;
+    Ok(activitypub_id)
+}
+
+/// Apps (id, AP id) that have no Create activity recorded
+pub async fn get_apps_without_create(data: &Data<AppState>) -> Result<Vec<(i32, String)>, Error> {
+    let db = &data.db;
+    let apps = sqlx::query_as::<_, (i32, String)>(
+        "SELECT a.id, a.activitypub_id FROM apps a \
+         WHERE NOT EXISTS (SELECT 1 FROM activities ac WHERE ac.obj = a.activitypub_id AND ac.kind = 'Create') \
+         ORDER BY a.id",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(apps)
+}
+
+/// Create/Update activities (id, AP id, object) whose app is gone without a tombstone.
+/// A deleted app's activities are kept as the history behind its tombstone.
+pub async fn get_orphaned_activities(
+    data: &Data<AppState>,
+) -> Result<Vec<(i32, String, String)>, Error> {
+    let db = &data.db;
+    let activities = sqlx::query_as::<_, (i32, String, String)>(
+        "SELECT ac.id, ac.activitypub_id, ac.obj FROM activities ac \
+         WHERE ac.kind IN ('Create', 'Update') \
+         AND NOT EXISTS (SELECT 1 FROM apps a WHERE a.activitypub_id = ac.obj) \
+         AND NOT EXISTS (SELECT 1 FROM tombstones t WHERE t.activitypub_id = ac.obj) \
+         ORDER BY ac.id",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(activities)
+}
+
+/// Follower and following rows (relay id, other relay id) pointing at a missing relay row.
+/// The foreign keys should prevent these, but databases restored from dumps may lack them.
+pub async fn get_orphaned_follows(data: &Data<AppState>) -> Result<Vec<(i32, i32)>, Error> {
+    let db = &data.db;
+    let follows = sqlx::query_as::<_, (i32, i32)>(
+        "SELECT relay_id, follower_id FROM followers \
+         WHERE relay_id NOT IN (SELECT id FROM relays) OR follower_id NOT IN (SELECT id FROM relays) \
+         UNION ALL \
+         SELECT relay_id, following_id FROM following \
+         WHERE relay_id NOT IN (SELECT id FROM relays) OR following_id NOT IN (SELECT id FROM relays)",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(follows)
+}
+
+/// Deletes the given activities and any follow rows pointing at missing relays
+pub async fn prune_orphans(data: &Data<AppState>, activity_ids: &[i32]) -> Result<(), Error> {
+    let mut tx = data.db.begin().await?;
+    sqlx::query("DELETE FROM activities WHERE id = ANY($1)")
+        .bind(activity_ids)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "DELETE FROM followers \
+         WHERE relay_id NOT IN (SELECT id FROM relays) OR follower_id NOT IN (SELECT id FROM relays)",
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "DELETE FROM following \
+         WHERE relay_id NOT IN (SELECT id FROM relays) OR following_id NOT IN (SELECT id FROM relays)",
+    )
+    .execute(&mut *tx)
     .await?;
+    tx.commit().await?;
     Ok(())
 }
 
+/// Follower ids of the system user with no matching relay row. `get_relay_followers`
+/// joins these away, so their inboxes never get deliveries.
+pub async fn get_dangling_followers(data: &Data<AppState>) -> Result<Vec<i32>, Error> {
+    let db = &data.db;
+    let ids = sqlx::query_scalar::<_, i32>(
+        "SELECT f.follower_id FROM followers f \
+         LEFT JOIN relays r ON f.follower_id = r.id \
+         WHERE f.relay_id = 0 AND r.id IS NULL \
+         ORDER BY f.follower_id",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(ids)
+}
+
+/// Deletes the system user's follower rows with no matching relay row, returning
+/// how many went
+pub async fn delete_dangling_followers(data: &Data<AppState>) -> Result<u64, Error> {
+    let db = &data.db;
+    let result = sqlx::query(
+        "DELETE FROM followers f \
+         WHERE f.relay_id = 0 AND NOT EXISTS (SELECT 1 FROM relays r WHERE r.id = f.follower_id)",
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
 pub async fn get_relay_by_id(id: i32, data: &Data<AppState>) -> Result<DbRelay, Error> {
     let db = &data.db;
     let relay = sqlx::query_as::<_, DbRelay>("SELECT * FROM relays WHERE id = $1")
@@ -190,12 +1019,35 @@ pub async fn get_relay_by_ap_id(
     Ok(relay)
 }
 
-pub async fn get_all_relays(data: &Data<AppState>) -> Result<Vec<DbRelay>, Error> {
+/// A page of relays, most recently active first, with the total count
+pub async fn get_relays_page(
+    data: &Data<AppState>,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<DbRelay>, i64), Error> {
     let db = &data.db;
-    let relays = sqlx::query_as::<_, DbRelay>("SELECT * FROM relays")
-        .fetch_all(db)
+    let relays = sqlx::query_as::<_, DbRelay>(
+        "SELECT * FROM relays ORDER BY last_activity_at DESC NULLS LAST, id ASC \
+         LIMIT $1 OFFSET $2",
+    )
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await?;
+    let total = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM relays")
+        .fetch_one(db)
+        .await?;
+    Ok((relays, total))
+}
+
+/// Records that an activity was just received from the given relay
+pub async fn touch_relay_activity(data: &Data<AppState>, ap_id: &str) -> Result<(), Error> {
+    let db = &data.db;
+    sqlx::query("UPDATE relays SET last_activity_at = NOW() WHERE activitypub_id = $1")
+        .bind(ap_id)
+        .execute(db)
         .await?;
-    Ok(relays)
+    Ok(())
 }
 
 pub async fn create_relay(
@@ -234,17 +1086,45 @@ pub async fn get_relay_follower_id_by_ap_id(
 
 pub async fn get_relay_followers(data: &Data<AppState>) -> Result<Vec<DbRelay>, Error> {
     let db = &data.db;
-    let followers = sqlx::query_as(
-        "SELECT r.id, r.activitypub_id, r.relay_name, r.inbox, r.outbox, r.public_key, r.private_key, r.is_local \
+    let query = sqlx::query_as(
+        "SELECT r.id, r.activitypub_id, r.relay_name, r.inbox, r.outbox, r.public_key, r.private_key, r.is_local, r.last_activity_at \
          FROM followers f \
          JOIN relays r ON f.follower_id = r.id \
          WHERE f.relay_id = 0"
     )
-        .fetch_all(db)
-        .await?;
+        .fetch_all(db);
+    let followers = timed(data, "get_relay_followers", query).await?;
     Ok(followers)
 }
 
+/// AP ids of a page of the system user's followers, oldest follow first
+pub async fn get_relay_follower_ids_page(
+    data: &Data<AppState>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<String>, Error> {
+    let db = &data.db;
+    let ids = sqlx::query_scalar::<_, String>(
+        "SELECT r.activitypub_id FROM followers f \
+         JOIN relays r ON f.follower_id = r.id \
+         WHERE f.relay_id = 0 ORDER BY r.id LIMIT $1 OFFSET $2",
+    )
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await?;
+    Ok(ids)
+}
+
+/// Counts followers of the system user without loading their rows
+pub async fn get_followers_count(data: &Data<AppState>) -> Result<i64, Error> {
+    let db = &data.db;
+    let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM followers WHERE relay_id = 0")
+        .fetch_one(db)
+        .await?;
+    Ok(count)
+}
+
 pub async fn add_follower_to_relay(data: &Data<AppState>, follower_id: i32) -> Result<(), Error> {
     let db = &data.db;
     sqlx::query("INSERT INTO followers (relay_id, follower_id) VALUES ($1, $2)")
@@ -255,6 +1135,57 @@ pub async fn add_follower_to_relay(data: &Data<AppState>, follower_id: i32) -> R
     Ok(())
 }
 
+/// Get followers of the system user that the system user also follows back
+pub async fn get_mutual_relay_followers(data: &Data<AppState>) -> Result<Vec<DbRelay>, Error> {
+    let db = &data.db;
+    let query = sqlx::query_as(
+        "SELECT r.id, r.activitypub_id, r.relay_name, r.inbox, r.outbox, r.public_key, r.private_key, r.is_local, r.last_activity_at \
+         FROM followers f \
+         JOIN following g ON g.following_id = f.follower_id AND g.relay_id = f.relay_id \
+         JOIN relays r ON f.follower_id = r.id \
+         WHERE f.relay_id = 0"
+    )
+        .fetch_all(db);
+    let followers = timed(data, "get_mutual_relay_followers", query).await?;
+    Ok(followers)
+}
+
+/// Whether the given relay follows the system user
+pub async fn is_relay_follower(data: &Data<AppState>, follower_id: i32) -> Result<bool, Error> {
+    let db = &data.db;
+    let follower = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM followers WHERE relay_id = 0 AND follower_id = $1)",
+    )
+    .bind(follower_id)
+    .fetch_one(db)
+    .await?;
+    Ok(follower)
+}
+
+/// Whether the system user already follows the given relay
+pub async fn is_following_relay(data: &Data<AppState>, following_id: i32) -> Result<bool, Error> {
+    let db = &data.db;
+    let following = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM following WHERE relay_id = 0 AND following_id = $1)",
+    )
+    .bind(following_id)
+    .fetch_one(db)
+    .await?;
+    Ok(following)
+}
+
+pub async fn add_following_to_relay(data: &Data<AppState>, following_id: i32) -> Result<(), Error> {
+    let db = &data.db;
+    sqlx::query(
+        "INSERT INTO following (relay_id, following_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+    )
+    .bind(0) // Only relay system user can follow others
+    .bind(following_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
 // ============================================================================
 // Slug Management
 // ============================================================================
@@ -287,6 +1218,7 @@ pub async fn set_app_slug(data: &Data<AppState>, app_id: i32, slug: &str) -> Res
         .bind(app_id)
         .execute(db)
         .await?;
+    data.index_cache.invalidate();
     Ok(())
 }
 
@@ -331,20 +1263,23 @@ pub async fn update_app_details(
 ) -> Result<(), Error> {
     let db = &data.db;
     sqlx::query(
-        "UPDATE apps SET name = $1, description = $2, image = $3, tags = $4, is_adult = $5 WHERE id = $6",
+        "UPDATE apps SET name = $1, description = $2, image = $3, tags = $4, is_adult = $5, last_source = $6 WHERE id = $7",
     )
     .bind(name)
     .bind(description)
     .bind(image_url)
     .bind(tags)
     .bind(adult)
+    .bind(SOURCE_OWNER)
     .bind(app_id)
     .execute(db)
     .await?;
+    data.index_cache.invalidate();
     Ok(())
 }
 
 /// Get apps without slugs (for migration script)
+#[allow(dead_code)]
 pub async fn get_apps_without_slugs(data: &Data<AppState>) -> Result<Vec<DbApp>, Error> {
     let db = &data.db;
     let apps = sqlx::query_as::<_, DbApp>(
@@ -354,3 +1289,393 @@ pub async fn get_apps_without_slugs(data: &Data<AppState>) -> Result<Vec<DbApp>,
     .await?;
     Ok(apps)
 }
+
+// ============================================================================
+// Reports
+// ============================================================================
+
+pub async fn create_report(
+    data: &Data<AppState>,
+    app_id: i32,
+    reason: &str,
+    reporter_ip: &str,
+) -> Result<(), Error> {
+    let db = &data.db;
+    sqlx::query("INSERT INTO reports (app_id, reason, reporter_ip) VALUES ($1, $2, $3)")
+        .bind(app_id)
+        .bind(reason)
+        .bind(reporter_ip)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Reports submitted from an IP in the last hour
+pub async fn count_recent_reports_by_ip(
+    data: &Data<AppState>,
+    reporter_ip: &str,
+) -> Result<i64, Error> {
+    let db = &data.db;
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM reports WHERE reporter_ip = $1 AND created_at > NOW() - INTERVAL '1 hour'",
+    )
+    .bind(reporter_ip)
+    .fetch_one(db)
+    .await?;
+    Ok(count)
+}
+
+/// Apps with open reports (id, name, url, open report count, latest reason), most reported first
+pub async fn get_open_report_counts(
+    data: &Data<AppState>,
+) -> Result<Vec<(i32, String, String, i64, String)>, Error> {
+    let db = &data.db;
+    let reports = sqlx::query_as::<_, (i32, String, String, i64, String)>(
+        "SELECT a.id, a.name, a.url, COUNT(*), \
+         (ARRAY_AGG(r.reason ORDER BY r.created_at DESC))[1] \
+         FROM reports r JOIN apps a ON a.id = r.app_id \
+         WHERE NOT r.resolved \
+         GROUP BY a.id, a.name, a.url \
+         ORDER BY COUNT(*) DESC, a.id",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(reports)
+}
+
+pub async fn resolve_reports(data: &Data<AppState>, app_id: i32) -> Result<(), Error> {
+    let db = &data.db;
+    sqlx::query("UPDATE reports SET resolved = TRUE WHERE app_id = $1 AND NOT resolved")
+        .bind(app_id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use activitypub_federation::fetch::object_id::ObjectId;
+
+    use super::*;
+    use crate::test_support;
+
+    #[actix_web::test]
+    async fn orphaned_activities_exclude_deleted_apps() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let actor = format!("https://{}/relay", test_support::TEST_DOMAIN);
+
+        let deleted_id = test_support::insert_app(&data, "https://deleted.test", "Deleted").await;
+        let deleted = get_app_by_id(deleted_id, &data).await.unwrap();
+        let deleted_ap_id = deleted.ap_id.inner().to_string();
+        let deleted_activity =
+            create_local_activity(&data, &actor, &deleted_ap_id, ActivityKind::Create)
+                .await
+                .unwrap();
+        delete_app(deleted_id, &data).await.unwrap();
+
+        // An app row lost without going through delete_app, e.g. removed by hand
+        let lost_ap_id = format!(
+            "https://{}/relay/beacon/{}",
+            test_support::TEST_DOMAIN,
+            test_support::unique("lost")
+        );
+        let lost_activity = create_local_activity(&data, &actor, &lost_ap_id, ActivityKind::Update)
+            .await
+            .unwrap();
+
+        let orphans: Vec<String> = get_orphaned_activities(&data)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, ap_id, _)| ap_id)
+            .collect();
+        assert!(orphans.contains(&lost_activity));
+        assert!(!orphans.contains(&deleted_activity));
+    }
+
+    #[actix_web::test]
+    async fn duplicate_ap_ids_are_kept_aside_when_removed() {
+        let Some((db, database)) = test_support::fresh_database().await else {
+            return;
+        };
+        let migrator = sqlx::migrate!("./migrations");
+        let before_unique_index = sqlx::migrate::Migrator {
+            migrations: migrator
+                .migrations
+                .iter()
+                .filter(|migration| migration.version < 20261016001650)
+                .cloned()
+                .collect(),
+            ..sqlx::migrate!("./migrations")
+        };
+        before_unique_index.run(&db).await.unwrap();
+        let mut ids = Vec::new();
+        for ap_id in ["https://peer.test/beacon/1", "https://peer.test/beacon/2"] {
+            for url in ["https://first.test/", "https://second.test/"] {
+                let id: i32 = sqlx::query_scalar(
+                    "INSERT INTO apps (activitypub_id, url, name, is_active) \
+                     VALUES ($1, $2, 'World', true) RETURNING id",
+                )
+                .bind(ap_id)
+                .bind(url)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+                ids.push(id);
+            }
+        }
+        migrator.run(&db).await.unwrap();
+
+        let kept: Vec<i32> = sqlx::query_scalar("SELECT id FROM apps ORDER BY id")
+            .fetch_all(&db)
+            .await
+            .unwrap();
+        assert_eq!(kept, [ids[0], ids[2]]);
+        let removed: Vec<(i32, String)> =
+            sqlx::query_as("SELECT id, url FROM removed_duplicate_apps ORDER BY id")
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        assert_eq!(
+            removed,
+            [
+                (ids[1], "https://second.test/".to_string()),
+                (ids[3], "https://second.test/".to_string())
+            ]
+        );
+        db.close().await;
+        test_support::drop_database(&database).await;
+    }
+
+    #[actix_web::test]
+    async fn inserting_a_duplicate_ap_id_fails_without_an_activity() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let actor = format!("https://{}/relay", test_support::TEST_DOMAIN);
+        let id = test_support::insert_app(&data, "https://original.test/", "Original").await;
+        let mut copy = get_app_by_id(id, &data).await.unwrap();
+        copy.url = "https://copy.test/".to_string();
+
+        let result = create_app_with_activity(&data, None, &copy, None, &actor).await;
+        assert!(
+            matches!(&result, Err(Error::Duplicate(constraint)) if constraint == "idx_apps_activitypub_id"),
+            "{:?}",
+            result
+        );
+        let activities: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM activities WHERE obj = $1")
+            .bind(copy.ap_id.inner().as_str())
+            .fetch_one(&data.db)
+            .await
+            .unwrap();
+        assert_eq!(activities, 0);
+    }
+
+    #[actix_web::test]
+    async fn updates_follow_the_ap_id_and_skip_missing_apps() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let actor = "https://peer.test/relay";
+        let id = test_support::insert_app(&data, "https://moved.test/", "Before").await;
+        let mut app = get_app_by_id(id, &data).await.unwrap();
+        // The peer's copy has a new address; it's still the same app
+        app.url = "https://moved.test/new".to_string();
+        app.name = "After".to_string();
+        let update_id = format!("{}/activities/{}", actor, test_support::unique("update"));
+        assert!(update_app_with_activity(&data, &app, &update_id, actor, &[]).await.unwrap());
+        assert_eq!(get_app_by_id(id, &data).await.unwrap().name, "After");
+
+        let unknown_update = format!("{}/activities/{}", actor, test_support::unique("update"));
+        app.ap_id = ObjectId::parse(&format!("{}/beacon/{}", actor, test_support::unique("app")))
+            .unwrap();
+        let result = update_app_with_activity(&data, &app, &unknown_update, actor, &[]).await;
+        assert!(matches!(result, Err(Error::NotFound)), "{:?}", result);
+        let activities: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM activities WHERE activitypub_id = $1")
+            .bind(&unknown_update)
+            .fetch_one(&data.db)
+            .await
+            .unwrap();
+        assert_eq!(activities, 0);
+    }
+
+    #[actix_web::test]
+    async fn local_urls_are_canonicalized_once_without_creating_duplicates() {
+        let Some((db, database)) = test_support::fresh_database().await else {
+            return;
+        };
+        let migrator = sqlx::migrate!("./migrations");
+        let before_canonicalization = sqlx::migrate::Migrator {
+            migrations: migrator
+                .migrations
+                .iter()
+                .filter(|migration| migration.version < 20261017000000)
+                .cloned()
+                .collect(),
+            ..sqlx::migrate!("./migrations")
+        };
+        before_canonicalization.run(&db).await.unwrap();
+        sqlx::query(
+            "INSERT INTO relays (id, activitypub_id, relay_name, inbox, outbox, public_key, \
+             is_local) VALUES (0, 'https://relay.test/relay', 'relay', '', '', '', true), \
+             (1, 'https://peer.test/relay', 'peer', '', '', '', false)",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        for (id, url, origin) in [
+            (1, "https://Old.test/?utm_source=feed&page=2", 0),
+            (2, "https://taken.test", 0),
+            (3, "https://TAKEN.test/", 0),
+            (4, "https://twice.test/", 0),
+            (5, "https://twice.test/?fbclid=abc", 0),
+            (6, "https://Peer.test/", 1),
+        ] {
+            sqlx::query(
+                "INSERT INTO apps (id, activitypub_id, url, name, is_active, origin_relay_id) \
+                 VALUES ($1, $2, $3, '', true, $4)",
+            )
+            .bind(id)
+            .bind(format!("https://relay.test/relay/beacon/{}", id))
+            .bind(url)
+            .bind(origin)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+        migrator.run(&db).await.unwrap();
+
+        let urls: Vec<String> = sqlx::query_scalar("SELECT url FROM apps ORDER BY id")
+            .fetch_all(&db)
+            .await
+            .unwrap();
+        assert_eq!(
+            urls,
+            [
+                "https://old.test?page=2",
+                "https://taken.test",
+                "https://TAKEN.test/",
+                "https://twice.test",
+                "https://twice.test/?fbclid=abc",
+                // Federated URLs are left as their relay sent them
+                "https://Peer.test/",
+            ]
+        );
+        let conflicts: Vec<(i32, String, i32)> = sqlx::query_as(
+            "SELECT app_id, canonical_url, conflicting_app_id FROM app_url_conflicts \
+             ORDER BY app_id",
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        assert_eq!(
+            conflicts,
+            [
+                (3, "https://taken.test".to_string(), 2),
+                (5, "https://twice.test".to_string(), 4),
+            ]
+        );
+        db.close().await;
+        test_support::drop_database(&database).await;
+    }
+
+    #[actix_web::test]
+    async fn followers_count_matches_the_follower_rows() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        assert_eq!(get_followers_count(&data).await.unwrap(), 0);
+
+        let mut relay_ids = Vec::new();
+        for peer in ["one", "two", "three", "followed"] {
+            let ap_id = format!("https://{}.test/relay", peer);
+            let inbox = format!("{}/inbox", ap_id);
+            create_relay(&data, peer, &ap_id, &inbox, &inbox, "unused")
+                .await
+                .unwrap();
+            relay_ids.push(get_relay_follower_id_by_ap_id(&data, &ap_id).await.unwrap());
+        }
+        for follower_id in &relay_ids[..3] {
+            add_follower_to_relay(&data, *follower_id).await.unwrap();
+        }
+        // Follows between other relays aren't followers of this one
+        sqlx::query("INSERT INTO followers (relay_id, follower_id) VALUES ($1, $2)")
+            .bind(relay_ids[3])
+            .bind(relay_ids[0])
+            .execute(&data.db)
+            .await
+            .unwrap();
+
+        let count = get_followers_count(&data).await.unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(count as usize, get_relay_followers(&data).await.unwrap().len());
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn activities_only_store_known_kinds() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let insert = |kind: &str| {
+            let ap_id = format!(
+                "https://peer.test/activities/{}",
+                test_support::unique("kind")
+            );
+            sqlx::query(
+                "INSERT INTO activities (activitypub_id, actor, obj, kind) \
+                 VALUES ($1, 'https://peer.test/relay', 'https://peer.test/relay/beacon/1', $2)",
+            )
+            .bind(ap_id)
+            .bind(kind.to_string())
+            .execute(&data.db)
+        };
+
+        for kind in ActivityKind::INBOX {
+            insert(kind.as_str()).await.unwrap();
+        }
+        for kind in ["Like", "Announce", "follow", ""] {
+            let error = insert(kind).await.unwrap_err();
+            let constraint = error
+                .as_database_error()
+                .and_then(|e| e.constraint().map(str::to_string));
+            assert_eq!(constraint.as_deref(), Some("activities_kind_check"), "{}", kind);
+        }
+    }
+
+    #[actix_web::test]
+    async fn activity_log_refuses_rows_of_unknown_kind() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        // A row written before the kinds were checked
+        sqlx::query("ALTER TABLE activities DROP CONSTRAINT activities_kind_check")
+            .execute(&data.db)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO activities (activitypub_id, actor, obj, kind) \
+             VALUES ('https://peer.test/activities/1', 'https://peer.test/relay', \
+             'https://peer.test/relay/beacon/1', 'Like')",
+        )
+        .execute(&data.db)
+        .await
+        .unwrap();
+
+        let result = list_activities(&data, &ActivityFilter::default(), 10, 0).await;
+        assert!(
+            matches!(result, Err(Error::Database(sqlx::Error::Decode(_)))),
+            "{:?}",
+            result
+        );
+        test_support::drop_database(&name).await;
+    }
+}
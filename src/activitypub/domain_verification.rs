@@ -0,0 +1,110 @@
+use std::str::FromStr;
+
+use activitypub_federation::config::Data;
+use url::Url;
+
+use super::outbound::read_body_capped;
+use crate::AppState;
+
+/// Where a site lists the relays allowed to index it, one domain per line
+pub const DOMAIN_VERIFICATION_PATH: &str = "/.well-known/spatial-relay";
+
+/// Largest verification file read, in bytes
+const MAX_FILE_BYTES: usize = 16 * 1024;
+
+/// What `REQUIRE_DOMAIN_VERIFICATION` does with a new beacon whose site doesn't list
+/// this relay in [`DOMAIN_VERIFICATION_PATH`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DomainVerification {
+    /// Don't check
+    Off,
+    /// Check and record the result, but list the world either way
+    Flag,
+    /// Register unverified worlds hidden, for an admin to review
+    Hide,
+    /// Refuse unverified worlds
+    Reject,
+}
+
+impl DomainVerification {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainVerification::Off => "off",
+            DomainVerification::Flag => "flag",
+            DomainVerification::Hide => "hide",
+            DomainVerification::Reject => "reject",
+        }
+    }
+}
+
+impl FromStr for DomainVerification {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" | "false" => Ok(DomainVerification::Off),
+            "flag" => Ok(DomainVerification::Flag),
+            "hide" => Ok(DomainVerification::Hide),
+            "reject" | "true" => Ok(DomainVerification::Reject),
+            _ => Err(anyhow::anyhow!(
+                "Unknown domain verification mode '{}', expected off, flag, hide or reject",
+                s
+            )),
+        }
+    }
+}
+
+/// Checks that the site at `app_url` lists this relay's domain (or `*`) in its
+/// verification file. The error says why it doesn't, for the beacon's submitter.
+pub async fn verify_app_domain(data: &Data<AppState>, app_url: &str) -> Result<(), String> {
+    let relay_domain = std::env::var("DOMAIN").expect("DOMAIN must be set");
+    let (file_url, body) = fetch_verification_file(data, app_url).await?;
+    if listed_lines(&body).any(|line| line == "*" || line.eq_ignore_ascii_case(&relay_domain)) {
+        Ok(())
+    } else {
+        Err(format!("{} does not list {}", file_url, relay_domain))
+    }
+}
+
+/// Checks that the site at `app_url` publishes the claim challenge `nonce` on a line of its
+/// verification file. Unlike the domain, only the claim's requester knows the nonce.
+pub async fn verify_claim_challenge(
+    data: &Data<AppState>,
+    app_url: &str,
+    nonce: &str,
+) -> Result<(), String> {
+    let (file_url, body) = fetch_verification_file(data, app_url).await?;
+    if listed_lines(&body).any(|line| line == nonce) {
+        Ok(())
+    } else {
+        Err(format!("{} does not contain the claim challenge", file_url))
+    }
+}
+
+/// Lines of a verification file, without blanks and `#` comments
+fn listed_lines(body: &str) -> impl Iterator<Item = &str> {
+    body.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+async fn fetch_verification_file(
+    data: &Data<AppState>,
+    app_url: &str,
+) -> Result<(Url, String), String> {
+    let file_url = Url::parse(app_url)
+        .and_then(|url| url.join(DOMAIN_VERIFICATION_PATH))
+        .map_err(|_| format!("'{}' is not a valid URL", app_url))?;
+    data.outbound_guard.check(&file_url).await?;
+    let response = data
+        .http_client
+        .get(file_url.as_str())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Could not fetch {}: {}", file_url, e))?;
+    let body = read_body_capped(response, MAX_FILE_BYTES)
+        .await
+        .map_err(|e| format!("Could not read {}: {}", file_url, e))?;
+    Ok((file_url, String::from_utf8_lossy(&body).into_owned()))
+}
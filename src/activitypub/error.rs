@@ -1,20 +1,114 @@
 use std::fmt::{Display, Formatter};
 
-/// Necessary because of this issue: https://github.com/actix/actix-web/issues/1711
+use activitypub_federation::error::Error as FederationError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+
+/// Errors from the database, federation and request handling, split so callers can tell a
+/// missing row from a failed query or a bad input and answer with the matching status.
 #[derive(Debug)]
-pub struct Error(pub(crate) anyhow::Error);
+pub enum Error {
+    /// The requested row or object doesn't exist
+    NotFound,
+    Database(sqlx::Error),
+    /// An insert clashed with an existing row on the named unique constraint
+    Duplicate(String),
+    /// Input that was understood but isn't acceptable; the message is safe to show
+    Validation(String),
+    Federation(FederationError),
+    Io(std::io::Error),
+    /// Anything else, e.g. `anyhow!` errors raised while handling an activity
+    Other(anyhow::Error),
+}
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Display::fmt(&self.0, f)
+        match self {
+            Error::NotFound => f.write_str("Not found"),
+            Error::Database(e) => write!(f, "Database error: {}", e),
+            Error::Duplicate(constraint) => write!(f, "Already exists ({})", constraint),
+            Error::Validation(message) => f.write_str(message),
+            Error::Federation(e) => Display::fmt(e, f),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Other(e) => Display::fmt(e, f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => Error::NotFound,
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                Error::Duplicate(e.constraint().unwrap_or("unique").to_string())
+            }
+            e => Error::Database(e),
+        }
+    }
+}
+
+impl From<FederationError> for Error {
+    fn from(e: FederationError) -> Self {
+        match e {
+            FederationError::NotFound => Error::NotFound,
+            e => Error::Federation(e),
+        }
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Self {
+        Error::Validation(format!("Invalid URL: {}", e))
     }
 }
 
-impl<T> From<T> for Error
-where
-    T: Into<anyhow::Error>,
-{
-    fn from(t: T) -> Self {
-        Error(t.into())
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Validation(format!("Invalid JSON: {}", e))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        Error::Other(e)
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::NotFound => StatusCode::NOT_FOUND,
+            Error::Validation(_) => StatusCode::BAD_REQUEST,
+            Error::Duplicate(_) => StatusCode::CONFLICT,
+            Error::Federation(e) => match e {
+                FederationError::ActivityBodyDigestInvalid
+                | FederationError::ActivitySignatureInvalid => StatusCode::UNAUTHORIZED,
+                FederationError::ParseReceivedActivity(..)
+                | FederationError::UrlVerificationError(_) => StatusCode::BAD_REQUEST,
+                _ => StatusCode::BAD_GATEWAY,
+            },
+            Error::Database(_) | Error::Io(_) | Error::Other(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// Server-side failures are logged and answered generically so query and path
+    /// details don't reach clients
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() && status != StatusCode::BAD_GATEWAY {
+            log_error!("Internal error: {}", self);
+            return HttpResponse::build(status).body("Internal server error");
+        }
+        HttpResponse::build(status).body(self.to_string())
     }
 }
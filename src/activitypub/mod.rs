@@ -1,6 +1,13 @@
 pub mod activities;
 pub mod actors;
 pub mod apps;
+pub mod client_ip;
 pub mod db;
+pub mod domain_verification;
 pub mod error;
+pub mod outbound;
+pub mod page_cache;
+pub mod pagination;
+pub mod retry_queue;
 pub mod services;
+pub mod throttle;
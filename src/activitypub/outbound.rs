@@ -0,0 +1,283 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use activitypub_federation::config::UrlVerifier;
+use activitypub_federation::error::Error as FederationError;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use url::{Host, Url};
+
+/// Refuses outbound requests to loopback, private and link-local addresses so
+/// attacker-supplied ids can't be used to reach the relay's internal network.
+#[derive(Clone)]
+pub struct OutboundGuard {
+    enabled: bool,
+    allowed_hosts: Vec<String>,
+}
+
+impl OutboundGuard {
+    pub fn new(enabled: bool, allowed_hosts: Vec<String>) -> Self {
+        OutboundGuard {
+            enabled,
+            allowed_hosts: allowed_hosts
+                .into_iter()
+                .map(|host| host.trim().to_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Resolves the URL's host and errors if any of its addresses are internal
+    pub async fn check(&self, url: &Url) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let host = match url.host_str() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']').to_lowercase(),
+            None => return Err(format!("Blocked outbound request to {}: no host", url)),
+        };
+        if self.allowed_hosts.contains(&host) {
+            return Ok(());
+        }
+        let port = url.port_or_known_default().unwrap_or(443);
+        let addrs: Vec<SocketAddr> = match tokio::net::lookup_host((host.as_str(), port)).await {
+            Ok(addrs) => addrs.collect(),
+            Err(e) => return Err(format!("Could not resolve {}: {}", host, e)),
+        };
+        if let Some(ip) = find_internal(&addrs) {
+            log_error!("Blocked outbound request to {} ({} resolves to {})", url, host, ip);
+            return Err(format!("Blocked outbound request to internal address {}", host));
+        }
+        Ok(())
+    }
+
+    /// Cheap synchronous check for redirect targets, which can't be resolved
+    /// from inside reqwest's redirect policy. Only literal IPs and localhost
+    /// are caught here; host names are checked by [`GuardedResolver`] on connect.
+    fn blocks_literal(&self, url: &Url) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if let Some(host) = url.host_str() {
+            if self.allowed_hosts.contains(&host.to_lowercase()) {
+                return false;
+            }
+        }
+        match url.host() {
+            Some(Host::Ipv4(ip)) => is_internal_ip(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => is_internal_ip(IpAddr::V6(ip)),
+            Some(Host::Domain(domain)) => domain.eq_ignore_ascii_case("localhost"),
+            None => true,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl UrlVerifier for OutboundGuard {
+    async fn verify(&self, url: &Url) -> Result<(), FederationError> {
+        self.check(url).await.map_err(FederationError::Other)
+    }
+}
+
+/// Resolves host names for the HTTP client and refuses those with internal addresses.
+/// The addresses checked are the ones connected to, so a host can't pass
+/// [`OutboundGuard::check`] and then rebind to an internal address before the request,
+/// and every redirect hop is covered too.
+struct GuardedResolver {
+    guard: OutboundGuard,
+}
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let guard = self.guard.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_lowercase();
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if guard.enabled && !guard.allowed_hosts.contains(&host) {
+                if let Some(ip) = find_internal(&addrs) {
+                    log_error!("Blocked outbound connection to {} ({})", host, ip);
+                    return Err(format!("Blocked connection to internal address {}", host).into());
+                }
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Builds the HTTP client used for all outbound fetches, optionally routed
+/// through `proxy` and refusing connections and redirects into internal addresses.
+/// Through a proxy only the proxy's own address is resolved here, so targets are
+/// covered by [`OutboundGuard::check`] alone.
+pub fn build_http_client(
+    proxy: Option<&str>,
+    guard: &OutboundGuard,
+) -> Result<reqwest::Client, reqwest::Error> {
+    let redirect_guard = guard.clone();
+    // The proxy is configured by the admin and may well be on the internal network
+    let mut resolver_guard = guard.clone();
+    if let Some(host) =
+        proxy.and_then(|proxy| Url::parse(proxy).ok()?.host_str().map(str::to_lowercase))
+    {
+        resolver_guard.allowed_hosts.push(host);
+    }
+    let mut builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::custom(
+        move |attempt| {
+            if attempt.previous().len() >= 10 {
+                attempt.error("too many redirects")
+            } else if redirect_guard.blocks_literal(attempt.url()) {
+                log_error!("Blocked redirect to internal address {}", attempt.url());
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        },
+    ));
+    builder = builder.dns_resolver(Arc::new(GuardedResolver { guard: resolver_guard }));
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    builder.build()
+}
+
+fn find_internal(addrs: &[SocketAddr]) -> Option<IpAddr> {
+    addrs.iter().map(SocketAddr::ip).find(|ip| is_internal_ip(*ip))
+}
+
+/// Reads a response body of at most `max_bytes`, giving up as soon as the server declares
+/// or sends more instead of buffering whatever it sends
+pub async fn read_body_capped(
+    mut response: reqwest::Response,
+    max_bytes: usize,
+) -> Result<Vec<u8>, String> {
+    let too_large = || format!("larger than {} bytes", max_bytes);
+    if response.content_length().is_some_and(|len| len > max_bytes as u64) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+fn is_internal_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_ipv4(ip),
+        IpAddr::V6(ip) => is_internal_ipv6(ip),
+    }
+}
+
+fn is_internal_ipv4(ip: Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || octets[0] == 0
+        // Carrier-grade NAT (100.64.0.0/10)
+        || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+}
+
+fn is_internal_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(ipv4) = ip.to_ipv4_mapped() {
+        return is_internal_ipv4(ipv4);
+    }
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // Unique local (fc00::/7)
+        || (first & 0xfe00) == 0xfc00
+        // Link-local (fe80::/10)
+        || (first & 0xffc0) == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn blocks_internal_literals() {
+        let guard = OutboundGuard::new(true, vec![]);
+        for internal in [
+            "http://127.0.0.1/",
+            "http://10.1.2.3/",
+            "http://192.168.0.1/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:10.0.0.1]/",
+        ] {
+            assert!(
+                guard.check(&url(internal)).await.is_err(),
+                "{} was allowed",
+                internal
+            );
+            assert!(
+                guard.blocks_literal(&url(internal)),
+                "{} was allowed",
+                internal
+            );
+        }
+        assert!(guard.check(&url("http://93.184.215.14/")).await.is_ok());
+        assert!(!guard.blocks_literal(&url("http://93.184.215.14/")));
+    }
+
+    #[actix_web::test]
+    async fn blocks_host_names_resolving_to_internal_addresses() {
+        let guard = OutboundGuard::new(true, vec![]);
+        assert!(guard
+            .check(&url("http://localhost:8080/actor"))
+            .await
+            .is_err());
+        assert!(OutboundGuard::new(false, vec![])
+            .check(&url("http://localhost:8080/actor"))
+            .await
+            .is_ok());
+    }
+
+    #[actix_web::test]
+    async fn allowlisted_hosts_pass() {
+        let guard = OutboundGuard::new(true, vec![" LocalHost ".to_string()]);
+        assert!(guard
+            .check(&url("http://localhost:8080/actor"))
+            .await
+            .is_ok());
+        assert!(!guard.blocks_literal(&url("http://localhost:8080/actor")));
+        assert!(guard
+            .check(&url("http://127.0.0.1:8080/actor"))
+            .await
+            .is_err());
+    }
+
+    #[actix_web::test]
+    async fn client_refuses_to_connect_to_internal_addresses() {
+        let files = HashMap::from([("/actor".to_string(), b"{}".to_vec())]);
+        let peer = test_support::serve_peer(Arc::new(Mutex::new(files)));
+
+        // Whatever a host resolved to when it was checked, the connection is checked again
+        let guard = OutboundGuard::new(true, vec![]);
+        let client = build_http_client(None, &guard).unwrap();
+        assert!(client.get(format!("{}/actor", peer)).send().await.is_err());
+
+        let guard = OutboundGuard::new(true, vec!["localhost".to_string()]);
+        let client = build_http_client(None, &guard).unwrap();
+        let response = client.get(format!("{}/actor", peer)).send().await.unwrap();
+        assert!(response.status().is_success());
+    }
+}
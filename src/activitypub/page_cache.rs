@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Rendered pages kept for `ttl` (`INDEX_CACHE_TTL`). Entries are keyed by everything
+/// the page depends on besides app data; app changes call [`PageCache::invalidate`].
+pub struct PageCache {
+    ttl: Duration,
+    pages: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl PageCache {
+    pub fn new(ttl: Duration) -> Self {
+        PageCache {
+            ttl,
+            pages: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cached page for `key`, unless caching is off or it has expired
+    pub fn get(&self, key: &str) -> Option<String> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+        let pages = match self.pages.lock() {
+            Ok(pages) => pages,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        pages
+            .get(key)
+            .filter(|(rendered_at, _)| rendered_at.elapsed() < self.ttl)
+            .map(|(_, html)| html.clone())
+    }
+
+    pub fn insert(&self, key: String, html: String) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut pages = match self.pages.lock() {
+            Ok(pages) => pages,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        pages.retain(|_, (rendered_at, _)| rendered_at.elapsed() < self.ttl);
+        pages.insert(key, (Instant::now(), html));
+    }
+
+    /// Drops every cached page, so the next request sees the latest apps
+    pub fn invalidate(&self) {
+        let mut pages = match self.pages.lock() {
+            Ok(pages) => pages,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        pages.clear();
+    }
+}
@@ -0,0 +1,143 @@
+use std::future::{ready, Ready};
+
+use activitypub_federation::config::FederationConfig;
+use actix_web::dev::Payload;
+use actix_web::error::ErrorBadRequest;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+
+/// Page size bounds shared by every paginated endpoint
+/// (`PAGINATION_DEFAULT_PER_PAGE` / `PAGINATION_MAX_PER_PAGE`)
+#[derive(Clone, Copy, Debug)]
+pub struct PaginationLimits {
+    pub default_per_page: i64,
+    pub max_per_page: i64,
+}
+
+impl Default for PaginationLimits {
+    fn default() -> Self {
+        Self {
+            default_per_page: 50,
+            max_per_page: 100,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PaginationQuery {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+/// `?page=` and `?per_page=` of a list request. Pages start at 1; zero or negative
+/// values are rejected with 400 and `per_page` is capped at the configured maximum.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Pagination {
+    pub page: i64,
+    pub per_page: i64,
+    /// Whether the request named a page, as opposed to falling back to the first
+    #[serde(skip)]
+    pub requested: bool,
+}
+
+impl Pagination {
+    fn parse(query: &str, limits: PaginationLimits) -> Result<Self, String> {
+        let query = web::Query::<PaginationQuery>::from_query(query)
+            .map_err(|e| format!("Invalid pagination: {}", e))?;
+        let page = query.page.unwrap_or(1);
+        let per_page = query.per_page.unwrap_or(limits.default_per_page);
+        if page < 1 {
+            return Err("page must be at least 1".to_string());
+        }
+        if per_page < 1 {
+            return Err("per_page must be at least 1".to_string());
+        }
+        Ok(Self {
+            page,
+            per_page: per_page.min(limits.max_per_page),
+            requested: query.page.is_some(),
+        })
+    }
+
+    /// Rows to skip before this page
+    pub fn offset(&self) -> i64 {
+        (self.page - 1).saturating_mul(self.per_page)
+    }
+
+    /// Number of pages needed for `total` items, at least 1
+    pub fn total_pages(&self, total: i64) -> i64 {
+        ((total + self.per_page - 1) / self.per_page).max(1)
+    }
+
+    /// The slice of `items` that falls on this page
+    pub fn apply<T>(&self, items: Vec<T>) -> Vec<T> {
+        let offset = usize::try_from(self.offset()).unwrap_or(usize::MAX);
+        let per_page = usize::try_from(self.per_page).unwrap_or(usize::MAX);
+        items.into_iter().skip(offset).take(per_page).collect()
+    }
+}
+
+impl FromRequest for Pagination {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let limits = req
+            .extensions()
+            .get::<FederationConfig<AppState>>()
+            .map(|config| config.to_request_data().pagination)
+            .unwrap_or_default();
+        ready(Self::parse(req.query_string(), limits).map_err(ErrorBadRequest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: PaginationLimits = PaginationLimits {
+        default_per_page: 20,
+        max_per_page: 50,
+    };
+
+    #[test]
+    fn defaults_to_the_first_page() {
+        let pagination = Pagination::parse("", LIMITS).unwrap();
+        assert_eq!((pagination.page, pagination.per_page), (1, 20));
+        assert!(!pagination.requested);
+        assert!(Pagination::parse("page=1", LIMITS).unwrap().requested);
+    }
+
+    #[test]
+    fn caps_per_page() {
+        let pagination = Pagination::parse("page=3&per_page=500", LIMITS).unwrap();
+        assert_eq!((pagination.page, pagination.per_page), (3, 50));
+    }
+
+    #[test]
+    fn rejects_pages_below_one_and_non_numbers() {
+        for query in [
+            "page=0",
+            "page=-1",
+            "per_page=0",
+            "page=two",
+            "per_page=1.5",
+        ] {
+            assert!(Pagination::parse(query, LIMITS).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn slices_items_and_counts_pages() {
+        let pagination = Pagination::parse("page=2&per_page=3", LIMITS).unwrap();
+        assert_eq!(pagination.offset(), 3);
+        assert_eq!(pagination.apply((1..=8).collect()), vec![4, 5, 6]);
+        assert_eq!(pagination.total_pages(0), 1);
+        assert_eq!(pagination.total_pages(6), 2);
+        assert_eq!(pagination.total_pages(7), 3);
+        let past_the_end = Pagination::parse("page=4&per_page=3", LIMITS).unwrap();
+        assert!(past_the_end.apply((1..=8).collect::<Vec<_>>()).is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::activities::Update;
+
+/// Waits before each retry of an Update whose object couldn't be fetched
+pub const UPDATE_RETRY_DELAYS: [Duration; 4] = [
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(30 * 60),
+];
+
+/// Most Updates waiting for a retry at once; further failures are dropped
+pub const MAX_PENDING_UPDATES: usize = 1000;
+
+struct PendingUpdate {
+    update: Update,
+    /// Retries already made
+    attempts: usize,
+    due: Instant,
+}
+
+/// Updates whose object couldn't be fetched, retried in the background so the inbox
+/// doesn't wait on a peer that is down. Applying an Update refetches the object's
+/// current state, so only the latest Update per object is kept.
+pub struct UpdateRetryQueue {
+    capacity: usize,
+    delays: Vec<Duration>,
+    pending: Mutex<HashMap<String, PendingUpdate>>,
+}
+
+impl UpdateRetryQueue {
+    pub fn new(capacity: usize, delays: Vec<Duration>) -> Self {
+        UpdateRetryQueue {
+            capacity,
+            delays,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Schedules the first retry of `update`, replacing any queued for the same object.
+    /// Returns false when the queue is full.
+    pub fn push(&self, update: Update) -> bool {
+        self.schedule(update, 0, true)
+    }
+
+    /// Schedules the next retry after `attempts` failed ones. Returns false once the
+    /// retries are used up. A newer Update queued for the object meanwhile wins.
+    pub fn retry_again(&self, update: Update, attempts: usize) -> bool {
+        self.schedule(update, attempts, false)
+    }
+
+    /// Removes and returns the Updates whose retry is due, each with its retries so far
+    pub fn take_due(&self) -> Vec<(Update, usize)> {
+        let mut pending = match self.pending.lock() {
+            Ok(pending) => pending,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = Instant::now();
+        let due: Vec<String> = pending
+            .iter()
+            .filter(|(_, entry)| entry.due <= now)
+            .map(|(object, _)| object.clone())
+            .collect();
+        due.into_iter()
+            .filter_map(|object| pending.remove(&object))
+            .map(|entry| (entry.update, entry.attempts))
+            .collect()
+    }
+
+    fn schedule(&self, update: Update, attempts: usize, replace: bool) -> bool {
+        let Some(delay) = self.delays.get(attempts) else {
+            return false;
+        };
+        let mut pending = match self.pending.lock() {
+            Ok(pending) => pending,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let object = update.object.inner().to_string();
+        if pending.contains_key(&object) {
+            if !replace {
+                return true;
+            }
+        } else if pending.len() >= self.capacity {
+            return false;
+        }
+        pending.insert(
+            object,
+            PendingUpdate {
+                update,
+                attempts,
+                due: Instant::now() + *delay,
+            },
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use activitypub_federation::kinds::activity::UpdateType;
+    use url::Url;
+
+    use super::*;
+
+    fn update(id: &str, object: &str) -> Update {
+        Update {
+            actor: Url::parse("https://peer.test/relay").unwrap().into(),
+            object: Url::parse(object).unwrap().into(),
+            kind: UpdateType::Update,
+            id: Url::parse(id).unwrap(),
+            published: None,
+        }
+    }
+
+    #[test]
+    fn keeps_the_latest_update_per_object() {
+        let queue = UpdateRetryQueue::new(10, vec![Duration::ZERO]);
+        assert!(queue.push(update("https://peer.test/a/1", "https://peer.test/beacon/1")));
+        assert!(queue.push(update("https://peer.test/a/2", "https://peer.test/beacon/1")));
+        assert!(queue.push(update("https://peer.test/a/3", "https://peer.test/beacon/2")));
+        let mut due: Vec<String> =
+            queue.take_due().into_iter().map(|(update, _)| update.id.to_string()).collect();
+        due.sort();
+        assert_eq!(due, ["https://peer.test/a/2", "https://peer.test/a/3"]);
+        assert!(queue.take_due().is_empty());
+    }
+
+    #[test]
+    fn refuses_new_objects_when_full() {
+        let queue = UpdateRetryQueue::new(1, vec![Duration::ZERO]);
+        assert!(queue.push(update("https://peer.test/a/1", "https://peer.test/beacon/1")));
+        assert!(!queue.push(update("https://peer.test/a/2", "https://peer.test/beacon/2")));
+        // Replacing a queued object's Update doesn't need room
+        assert!(queue.push(update("https://peer.test/a/3", "https://peer.test/beacon/1")));
+    }
+
+    #[test]
+    fn gives_up_after_the_last_delay_and_yields_to_newer_updates() {
+        let queue = UpdateRetryQueue::new(10, vec![Duration::ZERO, Duration::ZERO]);
+        let first = update("https://peer.test/a/1", "https://peer.test/beacon/1");
+        assert!(queue.retry_again(first.clone(), 1));
+        let (retried, attempts) = queue.take_due().pop().unwrap();
+        assert_eq!(attempts, 1);
+        assert!(!queue.retry_again(retried, 2));
+
+        assert!(queue.retry_again(first, 1));
+        let (stale, _) = queue.take_due().pop().unwrap();
+        assert!(queue.push(update("https://peer.test/a/2", "https://peer.test/beacon/1")));
+        assert!(queue.retry_again(stale, 1));
+        let (kept, attempts) = queue.take_due().pop().unwrap();
+        assert_eq!(kept.id.as_str(), "https://peer.test/a/2");
+        assert_eq!(attempts, 0);
+    }
+
+    #[test]
+    fn holds_updates_until_due() {
+        let queue = UpdateRetryQueue::new(10, vec![Duration::from_secs(60)]);
+        assert!(queue.push(update("https://peer.test/a/1", "https://peer.test/beacon/1")));
+        assert!(queue.take_due().is_empty());
+    }
+}
@@ -7,32 +7,60 @@ use rand::Rng;
 
 use activitypub_federation::actix_web::inbox::receive_activity;
 use activitypub_federation::config::Data;
+use activitypub_federation::error::Error as FederationError;
 use activitypub_federation::fetch::object_id::ObjectId;
 use activitypub_federation::fetch::webfinger::{build_webfinger_response, extract_webfinger_name};
 use activitypub_federation::kinds::activity::{CreateType, UpdateType};
 use activitypub_federation::kinds::actor::ServiceType;
 use activitypub_federation::protocol::context::WithContext;
-use activitypub_federation::traits::{ActivityHandler, Actor};
+use activitypub_federation::traits::{ActivityHandler, Actor, Object};
 use activitypub_federation::FEDERATION_CONTENT_TYPE;
 use actix_web::cookie::{time, Cookie};
+use chrono::{DateTime, Utc};
+use base64::prelude::*;
 use actix_web::web::{self, Bytes};
-use actix_web::{get, post, put, HttpRequest, HttpResponse, Responder};
+use actix_web::{get, post, put, HttpRequest, HttpResponse, Responder, ResponseError};
 use dataurl::DataUrl;
 use jwt_simple::prelude::*;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tera::Context;
 use url::Url;
 
-use super::activities::{Create, Follow, Update};
-use super::actors::{DbRelay, Relay};
-use super::apps::{APImage, App, DbApp};
+use super::activities::{
+    with_activity_context, ActivityFilter, ActivityKind, ActivityLogEntry, Create, DbActivity,
+    Follow, Update,
+};
+use super::actors::{DbRelay, Endpoints, Relay, RELAY_USERNAME};
+use super::apps::{
+    app_context, image_media_type, parse_app_protocol, split_tags, with_app_context, DbApp,
+    TagEdit, Tombstone, APP_PROTOCOLS, DEFAULT_APP_PROTOCOL, NO_IMAGE, SOURCE_ADMIN, SOURCE_BEACON,
+};
 use super::db::{
-    create_activity, create_app, get_activities_count, get_activity_by_id, get_all_apps,
-    get_all_relays, get_app_by_base_url, get_app_by_id, get_app_by_slug, get_apps_count,
-    get_relay_by_id, get_relay_followers, get_system_user, mark_app_verified, set_app_slug,
-    delete_app, set_verification_code, slug_exists, toggle_app_visibility, update_app, update_app_details,
+    claim_app, count_apps_by_host, count_recent_reports_by_ip, create_app_with_activity,
+    create_claim_challenge, create_local_activity, create_report, delete_app,
+    delete_dangling_followers, edit_app_tags, get_activities_count, get_activities_for_object,
+    get_activity_by_id, get_all_app_images, get_all_apps, get_app_by_ap_id, get_app_by_base_url,
+    get_app_by_id, get_app_by_slug, get_app_tombstone, get_app_urls, get_apps_by_origin_relay,
+    get_apps_count, get_apps_without_create, get_catalog_apps_count, get_catalog_apps_page,
+    get_claim_challenge, get_dangling_followers, get_followers_count, get_mutual_relay_followers,
+    get_open_report_counts, get_orphaned_activities, get_orphaned_follows, get_public_apps,
+    get_recent_apps, get_relay_by_ap_id, get_relay_by_id, get_relay_follower_id_by_ap_id,
+    get_relay_follower_ids_page, get_relay_followers, get_relays_page, get_similar_apps,
+    get_system_user, get_tombstone, is_app_claim_key, is_following_relay, is_relay_follower,
+    list_activities, mark_app_verified, prune_orphans, record_app_health, record_app_signature,
+    reserve_app_id, resolve_reports, set_app_slug, set_feature, set_verification_code,
+    slug_exists, store_activity_raw, toggle_app_adult, toggle_app_visibility, touch_relay_activity,
+    update_app, update_app_details,
 };
-use crate::{AppState, NewSessionEvent, SessionInfo};
+use super::domain_verification::{
+    verify_app_domain, verify_claim_challenge, DomainVerification, DOMAIN_VERIFICATION_PATH,
+};
+use super::outbound::read_body_capped;
+use super::pagination::Pagination;
+use super::throttle::BroadcastDecision;
+use crate::features::RUNTIME_FEATURES;
+use crate::{public_protocol, AppState, NewSessionEvent, SessionInfo};
 
 #[derive(Deserialize)]
 pub struct BeaconPayload {
@@ -43,6 +71,7 @@ pub struct BeaconPayload {
     pub image: Option<String>,
     pub adult: Option<bool>,
     pub tags: Option<String>,
+    pub protocol: Option<String>,
 }
 
 
@@ -69,17 +98,43 @@ pub struct SessionPayload {
 }
 
 fn template_fail_screen(e: tera::Error) -> web::Html {
-    eprintln!("Template error: {:?}", e);
+    log_error!("Template error: {:?}", e);
     web::Html::new("Failed to render to template!")
 }
 
+/// Clean 500 for an admin page that failed to render. Only the error is logged: admin
+/// contexts hold every app, so they stay out of both the logs and the response.
+fn admin_template_fail(e: tera::Error) -> HttpResponse {
+    log_error!("Admin template error: {:?}", e);
+    HttpResponse::InternalServerError()
+        .content_type("text/html; charset=utf-8")
+        .body("Failed to render the admin page. Check the relay logs for details.")
+}
+
 fn server_fail_screen(e: super::error::Error) -> web::Html {
-    eprintln!("Server error: {}", e);
+    log_error!("Server error: {}", e);
     web::Html::new("Server has encountered an internal error. Please check again later.")
 }
 
+/// Address of the client behind `request`, for anything keyed by IP. Forwarding headers
+/// only count when they come from one of `TRUSTED_PROXIES`.
+fn client_ip(request: &HttpRequest, data: &Data<AppState>) -> String {
+    data.trusted_proxies
+        .client_ip(request)
+        .map(|ip| ip.to_string())
+        .unwrap_or("unknown".to_string())
+}
+
 /// Validates admin JWT token from request cookie
 async fn validate_admin_token(request: &HttpRequest, data: &Data<AppState>) -> Result<(), HttpResponse> {
+    admin_token_claims(request, data).await.map(|_| ())
+}
+
+/// Claims of the admin JWT in the request cookie, if it is valid and unexpired
+async fn admin_token_claims(
+    request: &HttpRequest,
+    data: &Data<AppState>,
+) -> Result<JWTClaims<NoCustomClaims>, HttpResponse> {
     let cookie = request.cookie("relay-admin-token");
     let token = match cookie {
         Some(c) => c.value().to_string(),
@@ -102,9 +157,62 @@ async fn validate_admin_token(request: &HttpRequest, data: &Data<AppState>) -> R
     };
 
     let public_key = keypair.public_key();
-    match public_key.verify_token::<NoCustomClaims>(&token, None) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(HttpResponse::Unauthorized().body("Invalid or expired token")),
+    public_key
+        .verify_token::<NoCustomClaims>(&token, None)
+        .map_err(|_| HttpResponse::Unauthorized().body("Invalid or expired token"))
+}
+
+/// Signs a new admin token lasting `ADMIN_TOKEN_TTL_HOURS`, as a cookie of the same lifetime
+fn admin_token_cookie(
+    user: &DbRelay,
+    data: &Data<AppState>,
+) -> Result<Cookie<'static>, HttpResponse> {
+    let keypair = match user.private_key_pem().map(|pk| RS256KeyPair::from_pem(&pk)) {
+        Some(Ok(kp)) => kp,
+        _ => return Err(HttpResponse::InternalServerError().body("Invalid system keypair")),
+    };
+    let duration = jwt_simple::prelude::Duration::from_hours(data.admin_token_ttl_hours);
+    let claim = Claims::create(duration);
+    let token = match keypair.sign(claim) {
+        Ok(token) => token,
+        Err(_) => return Err(HttpResponse::InternalServerError().body("Failed to sign token")),
+    };
+    Ok(Cookie::build("relay-admin-token", token)
+        .path("/")
+        .http_only(true)
+        .max_age(time::Duration::hours(data.admin_token_ttl_hours as i64))
+        .finish())
+}
+
+/// Compares two byte strings in constant time with respect to their contents
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Validates HTTP Basic credentials against METRICS_AUTH, if configured
+fn validate_metrics_auth(request: &HttpRequest, data: &Data<AppState>) -> Result<(), HttpResponse> {
+    let expected = match &data.metrics_auth {
+        Some(auth) => auth,
+        None => return Ok(()),
+    };
+    let unauthorized = || {
+        HttpResponse::Unauthorized()
+            .insert_header(("WWW-Authenticate", "Basic realm=\"relay\""))
+            .body("Invalid or missing credentials")
+    };
+
+    let credentials = request
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64_STANDARD.decode(encoded.trim()).ok());
+    match credentials {
+        Some(credentials) if constant_time_eq(&credentials, expected.as_bytes()) => Ok(()),
+        _ => Err(unauthorized()),
     }
 }
 
@@ -122,8 +230,19 @@ struct AppWithCount {
 }
 
 #[get("/")]
-async fn index(data: Data<AppState>) -> impl Responder {
-    let template_path = get_template_path(&data, "index");
+async fn index(request: HttpRequest, data: Data<AppState>) -> impl Responder {
+    let template_path = get_template_path(&data, &request, "index");
+    let features = data.features();
+    let cache_key = format!(
+        "{}:debug={}:adult={}:hide_no_images={}",
+        template_path,
+        features.debug,
+        features.show_adult_content,
+        features.index_hide_apps_with_no_images
+    );
+    if let Some(html) = data.index_cache.get(&cache_key) {
+        return web::Html::new(html);
+    }
     match get_all_apps(&data).await {
         Ok(mut apps) => {
             // Count total unique base URLs in the database (before filtering)
@@ -133,13 +252,13 @@ async fn index(data: Data<AppState>) -> impl Responder {
                 .collect();
 
             // Filter apps for display in the front carousel
-            if !data.debug {
+            if !features.debug {
                 apps.retain(|app| !app.url.contains("localhost"));
             }
-            if data.index_hide_apps_with_no_images {
-                apps.retain(|app| app.image != "#");
+            if features.index_hide_apps_with_no_images {
+                apps.retain(DbApp::has_real_image);
             }
-            apps.retain(|app| app.visible);
+            apps.retain(|app| app.visible && app.active);
 
             // Deduplicate apps by base URL (ignoring query parameters)
             // Keep the first app for each base URL, sum live counts
@@ -147,7 +266,7 @@ async fn index(data: Data<AppState>) -> impl Responder {
             let sessions = match data.sessions.read() {
                 Ok(sessions) => sessions,
                 Err(poisoned) => {
-                    eprintln!("Warning: sessions lock was poisoned. Attempting recovery...");
+                    log_error!("Warning: sessions lock was poisoned. Attempting recovery...");
                     poisoned.into_inner()
                 }
             };
@@ -175,7 +294,8 @@ async fn index(data: Data<AppState>) -> impl Responder {
                 deduplicated_apps.push((app, live_count));
             }
 
-            // Sort by live count and take top 25
+            // Sort by live count and take top 25. The sort is stable over apps already in
+            // id order, so the carousel order is deterministic and needs no shuffle seed.
             deduplicated_apps.sort_by(|a, b| b.1.cmp(&a.1));
             deduplicated_apps.truncate(25);
 
@@ -205,11 +325,25 @@ async fn index(data: Data<AppState>) -> impl Responder {
             ctx.insert("apps_count", &total_unique_apps.len());
             ctx.insert("total_users_online", &total_users_online);
 
+            // A new relay shows how to get listed instead of an empty carousel
+            ctx.insert("is_empty", &apps_to_display.is_empty());
+            ctx.insert(
+                "beacon_url",
+                &format!(
+                    "{}{}/beacon",
+                    public_protocol(),
+                    env::var("DOMAIN").expect("DOMAIN must be set")
+                ),
+            );
+            ctx.insert("beacon_docs_url", BEACON_DOCS_URL);
             ctx.insert("apps", &apps_to_display);
             ctx.insert("google_analytics_id", &data.google_analytics_id);
 
             match data.tera.render(&template_path, &ctx) {
-                Ok(html) => web::Html::new(html),
+                Ok(html) => {
+                    data.index_cache.insert(cache_key, html.clone());
+                    web::Html::new(html)
+                }
                 Err(e) => template_fail_screen(e),
             }
         }
@@ -217,14 +351,35 @@ async fn index(data: Data<AppState>) -> impl Responder {
     }
 }
 
+/// How to integrate a beacon, linked from the index while the relay has no worlds
+const BEACON_DOCS_URL: &str = "https://docs.zesty.xyz/graph/beacon";
+
+/// Version of the JSON shape served under `/api/*`, sent as `X-Relay-Api-Version`.
+/// Bump this whenever a breaking change is made to an API response.
+/// Version 1: initial `/api/apps` response of `apps`, `total_apps` and `total_users_online`.
+pub const API_VERSION: &str = "1";
+
 #[derive(Serialize)]
 struct ApiApp {
     name: String,
     url: String,
     image: String,
+    protocol: String,
+    tags_list: Vec<String>,
+    last_source: Option<String>,
+    signature_verified: bool,
+    signature_key_id: Option<String>,
     live_count: usize,
 }
 
+/// Where an app listed on /apps came from, derived from its AP id
+#[derive(Serialize)]
+struct AppOrigin {
+    origin_host: String,
+    is_local: bool,
+    signature_verified: bool,
+}
+
 #[derive(Serialize)]
 struct ApiAppsResponse {
     apps: Vec<ApiApp>,
@@ -237,13 +392,14 @@ pub async fn api_get_apps(data: Data<AppState>) -> impl Responder {
     match get_all_apps(&data).await {
         Ok(mut apps) => {
             // Filter apps
-            if !data.debug {
+            let features = data.features();
+            if !features.debug {
                 apps.retain(|app| !app.url.contains("localhost"));
             }
-            if data.index_hide_apps_with_no_images {
-                apps.retain(|app| app.image != "#");
+            if features.index_hide_apps_with_no_images {
+                apps.retain(DbApp::has_real_image);
             }
-            apps.retain(|app| app.visible);
+            apps.retain(|app| app.visible && app.active);
 
             // Deduplicate by hostname
             let mut unique_urls = HashSet::new();
@@ -296,9 +452,14 @@ pub async fn api_get_apps(data: Data<AppState>) -> impl Responder {
             let api_apps: Vec<ApiApp> = app_to_live_count
                 .into_iter()
                 .map(|(app, live_count)| ApiApp {
+                    signature_verified: app.signature_verified(),
+                    signature_key_id: app.signature_key_id,
                     name: app.name,
                     url: normalize_app_url(app.url),
                     image: app.image,
+                    protocol: app.protocol,
+                    tags_list: split_tags(&app.tags),
+                    last_source: app.last_source,
                     live_count,
                 })
                 .collect();
@@ -310,7 +471,7 @@ pub async fn api_get_apps(data: Data<AppState>) -> impl Responder {
             })
         }
         Err(e) => {
-            eprintln!("API error: {}", e);
+            log_error!("API error: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": "Failed to fetch apps"
             }))
@@ -318,28 +479,296 @@ pub async fn api_get_apps(data: Data<AppState>) -> impl Responder {
     }
 }
 
+#[derive(Deserialize)]
+pub struct SimilarAppsQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct SimilarApp {
+    id: i32,
+    name: String,
+    url: String,
+    image: String,
+    page: String,
+    tags_list: Vec<String>,
+    shared_tags: i64,
+}
+
+/// Apps sharing the most tags with the given one, for "you might also like" lists
+#[get("/api/apps/{id}/similar")]
+async fn api_get_similar_apps(
+    path: web::Path<i32>,
+    query: web::Query<SimilarAppsQuery>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    let id = path.into_inner();
+    let app = match get_app_by_id(id, &data).await {
+        Ok(app) => app,
+        Err(super::error::Error::NotFound) => {
+            return HttpResponse::NotFound().body("No app found")
+        }
+        Err(e) => {
+            log_error!("Error fetching app {}: {}", id, e);
+            return HttpResponse::InternalServerError().body("Failed to fetch app");
+        }
+    };
+    if split_tags(&app.tags).is_empty() {
+        return HttpResponse::Ok().json(Vec::<SimilarApp>::new());
+    }
+    let limit = query.limit.unwrap_or(6).clamp(1, 24);
+    match get_similar_apps(&data, id, limit).await {
+        Ok(apps) => HttpResponse::Ok().json(
+            apps.into_iter()
+                .map(|(app, shared_tags)| SimilarApp {
+                    id: app.id,
+                    page: app.page_path(),
+                    tags_list: split_tags(&app.tags),
+                    name: app.name,
+                    url: normalize_app_url(app.url),
+                    image: app.image,
+                    shared_tags,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Err(e) => {
+            log_error!("Error fetching similar apps for {}: {}", id, e);
+            HttpResponse::InternalServerError().body("Failed to fetch similar apps")
+        }
+    }
+}
+
+/// Exposes basic relay counters in the Prometheus text format
+#[get("/metrics")]
+async fn get_metrics(request: HttpRequest, data: Data<AppState>) -> HttpResponse {
+    if let Err(response) = validate_metrics_auth(&request, &data) {
+        return response;
+    }
+
+    let apps_count = match get_apps_count(&data).await {
+        Ok(count) => count,
+        Err(e) => {
+            log_error!("Error fetching apps count: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to get apps count");
+        }
+    };
+    let activities_count = match get_activities_count(&data).await {
+        Ok(count) => count,
+        Err(e) => {
+            log_error!("Error fetching activities count: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to get activities count");
+        }
+    };
+    prune_old_sessions(&data);
+    let users_online: usize = match data.sessions.read() {
+        Ok(sessions) => sessions.values().map(|s| s.len()).sum(),
+        Err(poisoned) => poisoned.into_inner().values().map(|s| s.len()).sum(),
+    };
+
+    let body = format!(
+        "# TYPE relay_apps_total gauge\nrelay_apps_total {}\n\
+         # TYPE relay_activities_total gauge\nrelay_activities_total {}\n\
+         # TYPE relay_users_online gauge\nrelay_users_online {}\n",
+        apps_count, activities_count, users_online
+    );
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+#[get("/robots.txt")]
+async fn get_robots_txt(data: Data<AppState>) -> HttpResponse {
+    let robots = match &data.robots_txt {
+        Some(robots) => robots.clone(),
+        None => {
+            let domain = env::var("DOMAIN").expect("DOMAIN must be set");
+            let protocol = public_protocol();
+            format!(
+                "User-agent: *\nDisallow: /admin\nDisallow: /login\nDisallow: /relay\n\nSitemap: {}{}/sitemap.xml\n",
+                protocol, domain
+            )
+        }
+    };
+    HttpResponse::Ok().content_type("text/plain").body(robots)
+}
+
+/// Lists the pages of all visible, active, non-adult apps for search engines
+#[get("/sitemap.xml")]
+async fn get_sitemap(data: Data<AppState>) -> HttpResponse {
+    let apps = match get_public_apps(&data).await {
+        Ok(apps) => apps,
+        Err(e) => {
+            log_error!("Error fetching apps for sitemap: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to build sitemap");
+        }
+    };
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for app in apps {
+        xml.push_str(&format!(
+            "  <url><loc>{}</loc><lastmod>{}</lastmod></url>\n",
+            escape_xml(&app.page_url()),
+            app.created_at.format("%Y-%m-%d")
+        ));
+    }
+    xml.push_str("</urlset>\n");
+    HttpResponse::Ok().content_type("application/xml").body(xml)
+}
+
+/// Number of apps listed in the JSON Feed and RSS feeds
+const FEED_LIMIT: i64 = 50;
+
+/// Image URL for feed items, if the app has one
+fn feed_image(app: &DbApp) -> Option<&str> {
+    app.has_real_image().then_some(app.image.as_str())
+}
+
+/// Recently added apps as a JSON Feed (https://jsonfeed.org/version/1.1)
+#[get("/feed.json")]
+async fn get_feed_json(data: Data<AppState>) -> HttpResponse {
+    let apps = match get_recent_apps(&data, FEED_LIMIT).await {
+        Ok(apps) => apps,
+        Err(e) => {
+            log_error!("Error fetching apps for feed: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to build feed");
+        }
+    };
+
+    let domain = env::var("DOMAIN").expect("DOMAIN must be set");
+    let protocol = public_protocol();
+    let items: Vec<serde_json::Value> = apps
+        .iter()
+        .map(|app| {
+            let mut item = serde_json::json!({
+                "id": app.ap_id,
+                "url": app.page_url(),
+                "title": app.name,
+                "content_text": app.description,
+                "date_published": app.created_at.to_rfc3339(),
+            });
+            if let Some(image) = feed_image(app) {
+                item["image"] = serde_json::Value::String(image.to_string());
+            }
+            item
+        })
+        .collect();
+    let feed = serde_json::json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": format!("New worlds on {}", domain),
+        "home_page_url": format!("{}{}", protocol, domain),
+        "feed_url": format!("{}{}/feed.json", protocol, domain),
+        "items": items,
+    });
+    HttpResponse::Ok()
+        .content_type("application/feed+json")
+        .json(feed)
+}
+
+/// Recently added apps as an RSS 2.0 feed
+#[get("/feed.xml")]
+async fn get_feed_xml(data: Data<AppState>) -> HttpResponse {
+    let apps = match get_recent_apps(&data, FEED_LIMIT).await {
+        Ok(apps) => apps,
+        Err(e) => {
+            log_error!("Error fetching apps for feed: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to build feed");
+        }
+    };
+
+    let domain = env::var("DOMAIN").expect("DOMAIN must be set");
+    let protocol = public_protocol();
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <rss version=\"2.0\">\n<channel>\n\
+         <title>New worlds on {}</title>\n<link>{}{}</link>\n\
+         <description>Recently added worlds</description>\n",
+        escape_xml(&domain),
+        escape_xml(protocol),
+        escape_xml(&domain)
+    );
+    for app in &apps {
+        xml.push_str(&format!(
+            "<item><title>{}</title><link>{}</link><guid isPermaLink=\"false\">{}</guid>\
+             <description>{}</description><pubDate>{}</pubDate>",
+            escape_xml(&app.name),
+            escape_xml(&app.page_url()),
+            escape_xml(app.ap_id.inner().as_str()),
+            escape_xml(&app.description),
+            app.created_at.to_rfc2822()
+        ));
+        if let Some(image) = feed_image(app) {
+            let media_type = match image.rsplit('.').next() {
+                Some("png") => "image/png",
+                Some("gif") => "image/gif",
+                Some("webp") => "image/webp",
+                _ => "image/jpeg",
+            };
+            xml.push_str(&format!(
+                "<enclosure url=\"{}\" length=\"0\" type=\"{}\"/>",
+                escape_xml(image),
+                media_type
+            ));
+        }
+        xml.push_str("</item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    HttpResponse::Ok().content_type("application/rss+xml").body(xml)
+}
+
 #[get("/relay/beacon/{id}")]
-async fn get_beacon(info: web::Path<i32>, data: Data<AppState>) -> impl Responder {
-    match get_app_by_id(info.into_inner() + 1, &data).await {
-        Ok(app) => {
-            let app_image = (!app.image.is_empty()).then(|| APImage::new(app.image));
+async fn get_beacon(
+    request: HttpRequest,
+    info: web::Path<i32>,
+    data: Data<AppState>,
+) -> impl Responder {
+    // Resolve by the stored AP id so this route always agrees with what was federated
+    let system_user = match get_system_user(&data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            return HttpResponse::InternalServerError().body("Relay not initialized");
+        }
+    };
+    let ap_id = format!("{}/beacon/{}", system_user.ap_id.inner(), info.into_inner());
+    match get_app_by_ap_id(&data, &ap_id).await {
+        Ok(Some(app)) => {
+            // Browsers following a federated link get the human-readable page instead
+            if prefers_html(&request) {
+                return HttpResponse::Found()
+                    .append_header(("Location", app.page_path()))
+                    .finish();
+            }
+            // The same object the catalog and other fetches get, in the app context
+            let object = match app.into_json(&data).await {
+                Ok(object) => object,
+                Err(e) => {
+                    log_error!("Error serializing app: {}", e);
+                    return HttpResponse::InternalServerError().body("Failed to serialize world");
+                }
+            };
             HttpResponse::Ok()
                 .content_type(FEDERATION_CONTENT_TYPE)
-                .json(App::new(
-                    app.id,
-                    app.ap_id,
-                    String::new(),
-                    vec![],
-                    app.url,
-                    app.name,
-                    app.description,
-                    app_image,
-                    app.adult,
-                    app.tags,
-                ))
+                .json(with_app_context(object, &data))
         }
+        Ok(None) => match get_tombstone(&data, &ap_id).await {
+            // Deleted apps get 410 so peers purge them rather than retrying
+            Ok(Some(deleted_at)) => match Url::parse(&ap_id) {
+                Ok(id) => HttpResponse::Gone()
+                    .content_type(FEDERATION_CONTENT_TYPE)
+                    .json(WithContext::new_default(Tombstone::new(id, deleted_at))),
+                Err(_) => HttpResponse::Gone().finish(),
+            },
+            Ok(None) => HttpResponse::NotFound().body("No beacon found"),
+            Err(e) => {
+                log_error!("Error fetching tombstone from DB: {}", e);
+                HttpResponse::NotFound().body("No beacon found")
+            }
+        },
         Err(e) => {
-            eprintln!("Error fetching app from DB: {}", e);
+            log_error!("Error fetching app from DB: {}", e);
             HttpResponse::NotFound().body("No beacon found")
         }
     }
@@ -351,21 +780,10 @@ async fn new_beacon(
     data: Data<AppState>,
     req_body: web::Json<BeaconPayload>,
 ) -> impl Responder {
-    // Env vars
-    let relay_domain = env::var("DOMAIN").expect("DOMAIN must be set");
-    let protocol = env::var("PROTOCOL").expect("PROTOCOL must be set");
-
-    // Extract fields from request body
-    let url = req_body.url.clone();
-
-    // Reject beacons from localhost / loopback addresses
-    if let Ok(parsed) = Url::parse(&url) {
-        let host = parsed.host_str().unwrap_or("");
-        if matches!(host, "localhost" | "127.0.0.1" | "::1" | "0.0.0.0") {
-            eprintln!("Beacon rejected: localhost URL '{}'", url);
-            return HttpResponse::Forbidden().body("Localhost URLs are not permitted");
-        }
-    }
+    // Only the host is compared, and the parser already folds it the way
+    // canonicalize_beacon_url does. The scheme is filled in so bare `world.test`
+    // addresses are checked too.
+    let url = normalize_app_url(req_body.url.trim().to_string());
 
     // Validate that the Origin header matches the URL being registered
     // This ensures browsers can only register the domain they're actually running on
@@ -376,74 +794,127 @@ async fn new_beacon(
                 let origin_host = origin_url.host_str().unwrap_or("").trim_start_matches("www.");
                 let payload_host = payload_url.host_str().unwrap_or("").trim_start_matches("www.");
                 if origin_host != payload_host {
-                    eprintln!("Beacon rejected: Origin '{}' does not match URL '{}'", origin_str, url);
+                    log_error!("Beacon rejected: Origin '{}' does not match URL '{}'", origin_str, url);
                     return HttpResponse::Forbidden()
                         .body("Origin header does not match the URL being registered");
                 }
             }
         }
     }
-    println!("Beacon request received for: {}", url);
+    match ingest_beacon(&data, &req_body, SOURCE_BEACON).await {
+        Ok(BeaconOutcome::Unchanged(_)) => HttpResponse::NotModified().finish(),
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(response) => response,
+    }
+}
+
+/// What [`ingest_beacon`] did with a payload, with the id of the affected app
+enum BeaconOutcome {
+    Created(i32),
+    Updated(i32),
+    Unchanged(i32),
+}
+
+/// Creates or updates the app described by a beacon payload and federates the change.
+/// Shared by `PUT /beacon` and `POST /admin/ingest`; `source` is recorded as `last_source`.
+async fn ingest_beacon(
+    data: &Data<AppState>,
+    req_body: &BeaconPayload,
+    source: &str,
+) -> Result<BeaconOutcome, HttpResponse> {
+    // Env vars
+    let relay_domain = env::var("DOMAIN").expect("DOMAIN must be set");
+    let protocol = public_protocol();
+
+    // Extract fields from request body, canonicalizing the URL so the same app
+    // submitted with tracking params or a trailing slash maps to a single row
+    let url = canonicalize_beacon_url(&req_body.url);
+
+    // Reject beacons from localhost / loopback addresses
+    match Url::parse(&url) {
+        Ok(parsed) => {
+            let host = parsed.host_str().unwrap_or("");
+            if matches!(host, "localhost" | "127.0.0.1" | "::1" | "0.0.0.0") {
+                log_error!("Beacon rejected: localhost URL '{}'", url);
+                return Err(HttpResponse::Forbidden().body("Localhost URLs are not permitted"));
+            }
+            if data.require_https_beacons && parsed.scheme() != "https" {
+                log_error!("Beacon rejected: insecure URL '{}'", url);
+                return Err(HttpResponse::BadRequest()
+                    .body("This relay only lists worlds served over https; use an https:// URL"));
+            }
+        }
+        // A URL that doesn't parse can't be shown to be https either
+        Err(e) if data.require_https_beacons => {
+            log_error!("Beacon rejected: unparseable URL '{}': {}", url, e);
+            return Err(HttpResponse::BadRequest()
+                .body("This relay only lists worlds served over https; use an https:// URL"));
+        }
+        Err(_) => {}
+    }
+
+    log_info!("Beacon request received for: {}", url);
     let name = req_body.name.clone();
     let description = req_body.description.clone();
     let active = req_body.active;
-    let image = req_body.image.clone().unwrap_or("#".to_string());
+    let image = req_body.image.clone().unwrap_or(NO_IMAGE.to_string());
     let adult = req_body.adult.unwrap_or(false);
     let tags = req_body.tags.clone().unwrap_or("".to_string());
+    let app_protocol = match &req_body.protocol {
+        Some(p) => match parse_app_protocol(p) {
+            Some(p) => Some(p),
+            None => {
+                return Err(HttpResponse::BadRequest().body(format!(
+                    "Unknown protocol '{}', expected one of: {}",
+                    p,
+                    APP_PROTOCOLS.join(", ")
+                )));
+            }
+        },
+        None => None,
+    };
 
     // Query system user and DB information
-    let system_user = match get_system_user(&data).await {
+    let system_user = match get_system_user(data).await {
         Ok(user) => user,
         Err(e) => {
-            eprintln!("Error fetching system user: {}", e);
-            return HttpResponse::InternalServerError().body("Failed to get system user");
+            log_error!("Error fetching system user: {}", e);
+            return Err(HttpResponse::InternalServerError().body("Failed to get system user"));
         }
     };
     let domain = system_user.ap_id.inner().as_str();
-    let apps_count = match get_apps_count(&data).await {
-        Ok(count) => count,
-        Err(e) => {
-            eprintln!("Error fetching apps count: {}", e);
-            return HttpResponse::InternalServerError().body("Failed to get apps count");
-        }
-    };
-    let activities_count: i64 = match get_activities_count(&data).await {
-        Ok(count) => count,
-        Err(e) => {
-            eprintln!("Error fetching activities count: {}", e);
-            return HttpResponse::InternalServerError().body("Failed to get activities count");
-        }
-    };
 
     // Check if app with same base URL already exists (ignoring query parameters)
     // If it does and nothing changed, return 304
     // Otherwise, update the DB and send the relevant activities
     let base_url = get_base_url(&url).unwrap_or_else(|| url.clone());
-    match get_app_by_base_url(&data, &base_url).await {
+    match get_app_by_base_url(data, &base_url).await {
         Ok(Some(app)) => {
             // Set up references to the latest values for each field
             let app_name = &get_latest_value(app.name.clone(), name.clone());
             let app_description = &get_latest_value(app.description.clone(), description.clone());
             let app_active = get_latest_value(app.active, active);
-            let app_image = if app.image == image || image == "#" {
+            let app_image = if app.image == image || image == NO_IMAGE {
                 &app.image
             } else {
                 &image
             };
             let app_adult = get_latest_value(app.adult, adult);
             let app_tags = get_latest_value(app.tags.clone(), tags.clone());
+            let app_protocol = app_protocol.clone().unwrap_or_else(|| app.protocol.clone());
 
             // Parse optionally attached image to see if we need to save a copy locally
             let image = if app.image != image && app_image.contains("data:") {
-                let image_url = create_local_image(
-                    app.ap_id.clone().into_inner().as_str(),
-                    &protocol,
+                let image_url = store_local_image(
+                    protocol,
                     &relay_domain,
                     app_image,
-                );
+                    data.max_image_dimension,
+                )
+                .await;
                 if image_url.is_empty() {
-                    eprintln!("Error creating local image");
-                    return HttpResponse::BadRequest().finish();
+                    log_error!("Error creating local image");
+                    return Err(HttpResponse::BadRequest().finish());
                 }
 
                 image_url
@@ -451,170 +922,273 @@ async fn new_beacon(
                 app_image.clone()
             };
 
-            // Check if no fields have changed, in which case exit early
-            if app_name == &app.name
-                && app_description == &app.description
+            // Check if no fields have changed, in which case exit early. Text that only
+            // differs in spacing counts as unchanged so resubmissions don't cause Updates
+            if same_text(app_name, &app.name)
+                && same_text(app_description, &app.description)
                 && app_active == app.active
                 && image == app.image
                 && app_adult == app.adult
-                && app_tags == app.tags
+                && same_text(&app_tags, &app.tags)
+                && app_protocol == app.protocol
             {
-                return HttpResponse::NotModified().finish();
+                return Ok(BeaconOutcome::Unchanged(app.id));
             }
 
             match update_app(
-                &data,
-                url.clone(),
+                data,
+                app.ap_id.inner().to_string(),
                 app_name.clone(),
                 app_description.clone(),
                 app_active,
                 image,
                 app_adult,
                 app_tags.clone(),
+                app_protocol,
+                source,
             )
             .await
             {
                 Ok(_) => {
-                    let activity = Update {
-                        actor: system_user.ap_id.clone(),
-                        object: app.ap_id.clone(),
-                        kind: UpdateType::Update,
-                        id: Url::from_str(&format!(
-                            "{}/activities/{}",
-                            domain,
-                            activities_count + 1
-                        ))
-                        .unwrap(),
-                    };
-                    match create_activity(
-                        &data,
-                        format!(
-                            "{}/activities/{}",
-                            system_user.ap_id.inner().as_str(),
-                            activities_count + 1
-                        ),
-                        system_user.ap_id.inner().as_str(),
-                        app.ap_id.inner().as_str(),
-                        "Update",
-                    )
-                    .await
-                    {
-                        Ok(_) => {
-                            let recipients: Vec<DbRelay> = match get_relay_followers(&data).await {
-                                Ok(relays) => relays,
-                                Err(e) => {
-                                    eprintln!("Error fetching relays: {}", e);
-                                    vec![]
-                                }
-                            };
-                            let recipient_inboxes: Vec<Url> =
-                                recipients.iter().map(|relay| relay.inbox.clone()).collect();
-                            let _ = system_user
-                                .send(activity, recipient_inboxes, false, &data)
-                                .await
-                                .map_err(|e| eprintln!("Error sending activity: {}", e));
-                        }
-                        Err(e) => {
-                            eprintln!("Error creating activity: {}", e);
-                            return HttpResponse::InternalServerError().body(e.to_string());
-                        }
+                    if let Err(e) = broadcast_app_update(data, &system_user, &app.ap_id).await {
+                        log_error!("Error creating activity: {}", e);
+                        return Err(e.error_response());
                     }
 
-                    return HttpResponse::Ok().finish();
+                    return Ok(BeaconOutcome::Updated(app.id));
                 }
-                Err(e) => eprintln!("Error updating app: {}", e),
+                Err(e) => log_error!("Error updating app: {}", e),
             }
         }
         Ok(None) => {
             // App doesn't exist, will be created below
         }
-        Err(e) => eprintln!("Error fetching app from DB: {}", e),
+        Err(e) => log_error!("Error fetching app from DB: {}", e),
     }
 
     // At this point, it should be certain that the app doesn't already exist.
+    // Refuse it if its host already has as many apps as allowed
+    if data.max_apps_per_host > 0 {
+        let host = Url::parse(&url)
+            .ok()
+            .and_then(|parsed| parsed.host_str().map(str::to_string))
+            .unwrap_or_default();
+        match count_apps_by_host(data, &host).await {
+            Ok(count) if count >= data.max_apps_per_host => {
+                log_error!("Beacon rejected: host '{}' already has {} apps", host, count);
+                return Err(HttpResponse::Forbidden().body(format!(
+                    "This host has reached the limit of {} registered worlds",
+                    data.max_apps_per_host
+                )));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log_error!("Error counting apps for host: {}", e);
+                return Err(HttpResponse::InternalServerError().body("Failed to check app limit"));
+            }
+        }
+    }
+
+    // Sites can require a relay to be listed in their well-known file before it indexes
+    // them; admins registering worlds by hand skip the check
+    let domain_check = match data.domain_verification {
+        DomainVerification::Off => None,
+        _ if source == SOURCE_ADMIN => None,
+        mode => {
+            let result = verify_app_domain(data, &url).await;
+            if let Err(reason) = &result {
+                log_error!("Domain verification failed for '{}': {}", url, reason);
+                if mode == DomainVerification::Reject {
+                    return Err(HttpResponse::Forbidden().body(format!(
+                        "This relay only indexes sites that list it in {}: {}",
+                        DOMAIN_VERIFICATION_PATH, reason
+                    )));
+                }
+            }
+            Some(result.is_ok())
+        }
+    };
+    // In hide mode an unverified world is stored unlisted and isn't federated until an
+    // admin makes it visible
+    let held = domain_check == Some(false) && data.domain_verification == DomainVerification::Hide;
+
     // Create a new app and send the Create activity to following relays
-    let ap_id = format!("{}/beacon/{}", domain, apps_count);
+    let app_id = match reserve_app_id(data).await {
+        Ok(id) => id,
+        Err(e) => {
+            log_error!("Error reserving app id: {}", e);
+            return Err(HttpResponse::InternalServerError().body("Failed to reserve app id"));
+        }
+    };
+    let ap_id = format!("{}/beacon/{}", domain, app_id);
     let image_url = if image.contains("data:") {
-        let image_url = create_local_image(&ap_id, &protocol, &relay_domain, &image);
+        let image_url = store_local_image(
+            protocol,
+            &relay_domain,
+            &image,
+            data.max_image_dimension,
+        )
+        .await;
         if image_url.is_empty() {
-            eprintln!("Error creating local image");
-            return HttpResponse::BadRequest().finish();
+            log_error!("Error creating local image");
+            return Err(HttpResponse::BadRequest().finish());
         }
         image_url
     } else {
         image
     };
 
-    match create_app(
-        &data,
-        ap_id,
+    let new_app = DbApp {
+        id: app_id,
+        ap_id: ObjectId::parse(&ap_id).unwrap(),
         url,
-        name.clone(),
+        name: name.clone(),
         description,
         active,
-        image_url,
+        image: image_url,
         adult,
-        tags.clone(),
-    )
-    .await
+        tags: tags.clone(),
+        visible: !held,
+        created_at: Utc::now(),
+        protocol: app_protocol.unwrap_or_else(|| DEFAULT_APP_PROTOCOL.to_string()),
+        slug: None,
+        verification_code: None,
+        verified_at: None,
+        last_source: Some(source.to_string()),
+        reachable: None,
+        last_checked_at: None,
+        failed_checks: 0,
+        origin_relay_id: Some(0),
+        signature_key_id: None,
+        signature_verified_at: None,
+        domain_verified_at: (domain_check == Some(true)).then(Utc::now),
+    };
+    // The app and its Create activity are written together; only federate once both are stored
+    let activity_id = match create_app_with_activity(data, Some(app_id), &new_app, None, domain)
+        .await
     {
-        Ok(_) => {
-            // Generate and set a unique slug for the new app
-            let slug = generate_unique_slug(&data, &name).await;
-            // App ID is apps_count + 1 since we just created a new one
-            if let Err(e) = set_app_slug(&data, (apps_count + 1) as i32, &slug).await {
-                eprintln!("Error setting slug for new app: {}", e);
-            }
+        Ok(activity_id) => activity_id,
+        Err(e @ super::error::Error::Duplicate(_)) => {
+            log_error!("Error inserting new beacon: {}", e);
+            return Err(HttpResponse::Conflict().body("This world was registered concurrently"));
+        }
+        Err(e) => {
+            log_error!("Error inserting new beacon: {}", e);
+            return Err(HttpResponse::InternalServerError().body("Failed to create app"));
         }
-        Err(e) => eprintln!("Error inserting new beacon: {}", e),
     };
+    // Generate and set a unique slug for the new app
+    let slug = generate_unique_slug(data, &name).await;
+    if let Err(e) = set_app_slug(data, app_id, &slug).await {
+        log_error!("Error setting slug for new app: {}", e);
+    }
+    if held {
+        log_info!("Holding '{}' for review until an admin makes it visible", new_app.url);
+        return Ok(BeaconOutcome::Created(app_id));
+    }
+    send_app_create(data, &system_user, &new_app.ap_id, &activity_id).await;
+
+    Ok(BeaconOutcome::Created(app_id))
+}
+
+/// Sends the stored Create activity of a local app to following relays
+async fn send_app_create(
+    data: &Data<AppState>,
+    system_user: &DbRelay,
+    app_ap_id: &ObjectId<DbApp>,
+    activity_id: &str,
+) {
     let activity = Create {
-        actor: ObjectId::parse(domain).unwrap(),
-        object: ObjectId::parse(&format!("{}/beacon/{}", domain, apps_count)).unwrap(),
+        actor: system_user.ap_id.clone(),
+        object: app_ap_id.clone(),
         kind: CreateType::Create,
-        id: Url::from_str(&format!("{}/activities/{}", domain, activities_count)).unwrap(),
-    };
-    let recipients: Vec<DbRelay> = match get_relay_followers(&data).await {
-        Ok(relays) => relays,
-        Err(e) => {
-            eprintln!("Error fetching relays: {}", e);
-            vec![]
-        }
+        id: Url::from_str(activity_id).unwrap(),
+        published: Some(Utc::now()),
     };
-    let recipient_inboxes: Vec<Url> = recipients.iter().map(|relay| relay.inbox.clone()).collect();
+    let recipient_inboxes = get_broadcast_inboxes(data).await;
     let _ = system_user
-        .send(activity, recipient_inboxes, false, &data)
+        .send(activity, recipient_inboxes, false, data)
         .await
-        .map_err(|e| eprintln!("Error sending activity: {}", e));
+        .map_err(|e| log_error!("Error sending activity: {}", e));
+}
 
-    HttpResponse::Ok().finish()
+/// Federates a local world that `REQUIRE_DOMAIN_VERIFICATION=hide` held back, now that an
+/// admin has made it visible. Peers that already have the world ignore the repeated Create.
+async fn send_held_app_create(data: &Data<AppState>, app_id: i32) {
+    if data.domain_verification != DomainVerification::Hide {
+        return;
+    }
+    let app = match get_app_by_id(app_id, data).await {
+        Ok(app) if app.origin_relay_id == Some(0) && app.domain_verified_at.is_none() => app,
+        Ok(_) => return,
+        Err(e) => {
+            log_error!("Error fetching approved app: {}", e);
+            return;
+        }
+    };
+    let system_user = match get_system_user(data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            return;
+        }
+    };
+    let ap_id = app.ap_id.inner().as_str();
+    let stored = match get_activities_for_object(data, ap_id).await {
+        Ok(activities) => activities
+            .into_iter()
+            .find(|activity| activity.kind == ActivityKind::Create)
+            .map(|activity| activity.ap_id),
+        Err(e) => {
+            log_error!("Error fetching activities of approved app: {}", e);
+            return;
+        }
+    };
+    let activity_id = match stored {
+        Some(activity_id) => activity_id,
+        None => {
+            let actor = system_user.ap_id.inner().as_str();
+            match create_local_activity(data, actor, ap_id, ActivityKind::Create).await {
+                Ok(activity_id) => activity_id,
+                Err(e) => {
+                    log_error!("Error recording Create activity: {}", e);
+                    return;
+                }
+            }
+        }
+    };
+    send_app_create(data, &system_user, &app.ap_id, &activity_id).await;
 }
 
 #[get("/world/{id_or_slug}")]
-pub async fn get_world(data: Data<AppState>, path: web::Path<String>) -> impl Responder {
-    get_app_handler(data, path).await
+pub async fn get_world(
+    request: HttpRequest,
+    data: Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    get_app_handler(request, data, path).await
 }
 
 #[get("/app/{id_or_slug}")]
-async fn get_app(data: Data<AppState>, path: web::Path<String>) -> impl Responder {
-    get_app_handler(data, path).await
+async fn get_app(
+    request: HttpRequest,
+    data: Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    get_app_handler(request, data, path).await
 }
 
-async fn get_app_handler(data: Data<AppState>, path: web::Path<String>) -> impl Responder {
-    let template_path = get_template_path(&data, "app");
-    let error_path = get_template_path(&data, "error");
+async fn get_app_handler(
+    request: HttpRequest,
+    data: Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let template_path = get_template_path(&data, &request, "app");
+    let error_path = get_template_path(&data, &request, "error");
 
     let id_or_slug = path.into_inner();
 
-    // Try parsing as ID first, otherwise treat as slug
-    let app_result = if let Ok(id) = id_or_slug.parse::<i32>() {
-        get_app_by_id(id + 1, &data).await.ok()
-    } else {
-        match get_app_by_slug(&data, &id_or_slug).await {
-            Ok(Some(app)) => Some(app),
-            _ => None,
-        }
-    };
+    let app_result = find_app_by_id_or_slug(&data, &id_or_slug).await;
 
     match app_result {
         Some(app) => {
@@ -622,7 +1196,7 @@ async fn get_app_handler(data: Data<AppState>, path: web::Path<String>) -> impl
             let sessions = match data.sessions.read() {
                 Ok(sessions) => sessions,
                 Err(poisoned) => {
-                    eprintln!("Warning: sessions lock was poisoned. Attempting recovery...");
+                    log_error!("Warning: sessions lock was poisoned. Attempting recovery...");
                     poisoned.into_inner()
                 }
             };
@@ -636,53 +1210,188 @@ async fn get_app_handler(data: Data<AppState>, path: web::Path<String>) -> impl
                 .map(|(_, session_list)| session_list.len())
                 .sum();
             let url = normalize_app_url(app.url.clone());
+            // Link previews need absolute URLs for both the page and its image
+            let site = format!(
+                "{}{}",
+                public_protocol(),
+                env::var("DOMAIN").expect("DOMAIN must be set")
+            );
+            let og_image = match app.image.as_str() {
+                _ if !app.has_real_image() => None,
+                image if image.starts_with('/') => Some(format!("{}{}", site, image)),
+                image => Some(image.to_string()),
+            };
             let mut ctx = tera::Context::new();
             ctx.insert("name", &app.name);
             ctx.insert("description", &app.description);
             ctx.insert("url", &url);
             ctx.insert("image", &app.image);
+            ctx.insert("has_image", &app.has_real_image());
             ctx.insert("live_count", &live_count);
             ctx.insert("created_at", &app.created_at);
             ctx.insert("slug", &app.slug);
             ctx.insert("app_id", &app.id);
+            ctx.insert("protocol", &app.protocol);
+            ctx.insert("signature_verified", &app.signature_verified());
+            ctx.insert("domain_verified", &app.domain_verified_at.is_some());
+            ctx.insert("signature_key_id", &app.signature_key_id);
+            ctx.insert("page_url", &format!("{}{}", site, app.page_path()));
+            ctx.insert("og_image", &og_image);
             match data.tera.render(&template_path, &ctx) {
-                Ok(html) => web::Html::new(html),
-                Err(e) => template_fail_screen(e),
+                Ok(html) => HttpResponse::Ok().content_type("text/html; charset=utf-8").body(html),
+                Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
             }
         }
         None => {
-            eprintln!("App not found: {}", id_or_slug);
-            match data.tera.render(&error_path, &Context::new()) {
-                Ok(html) => web::Html::new(html),
-                Err(e) => template_fail_screen(e),
+            // A tombstone means the app existed and was removed, so caches should drop it
+            let tombstone = match get_app_tombstone(&data, &id_or_slug).await {
+                Ok(tombstone) => tombstone,
+                Err(e) => {
+                    log_error!("Error fetching tombstone from DB: {}", e);
+                    None
+                }
+            };
+            let mut ctx = Context::new();
+            let mut response = match tombstone {
+                Some((ap_id, deleted_at)) => {
+                    if !prefers_html(&request) {
+                        if let Ok(id) = Url::parse(&ap_id) {
+                            return HttpResponse::Gone()
+                                .content_type(FEDERATION_CONTENT_TYPE)
+                                .json(WithContext::new_default(Tombstone::new(id, deleted_at)));
+                        }
+                    }
+                    ctx.insert("gone", &true);
+                    HttpResponse::Gone()
+                }
+                None => {
+                    log_error!("App not found: {}", id_or_slug);
+                    HttpResponse::NotFound()
+                }
+            };
+            match data.tera.render(&error_path, &ctx) {
+                Ok(html) => response.content_type("text/html; charset=utf-8").body(html),
+                Err(e) => response.body(e.to_string()),
             }
         }
     }
 }
 
+#[derive(Deserialize)]
+pub struct AppsQuery {
+    include_inactive: Option<bool>,
+    sort: Option<String>,
+    /// Search in world names and descriptions, and domains
+    q: Option<String>,
+    /// Also list 18+ worlds, when `SHOW_ADULT_CONTENT` allows them at all
+    adult: Option<bool>,
+}
+
+/// Orderings offered by `/apps?sort=`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppsSort {
+    /// Domains alphabetically, worlds by name
+    Name,
+    /// Domains and worlds with the most recently indexed first
+    Newest,
+    /// Domains and worlds with the earliest indexed first
+    Oldest,
+    /// Domains and worlds with the most people in them right now first
+    Popular,
+}
+
+impl FromStr for AppsSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(AppsSort::Name),
+            "newest" => Ok(AppsSort::Newest),
+            "oldest" => Ok(AppsSort::Oldest),
+            "popular" => Ok(AppsSort::Popular),
+            _ => Err(anyhow::anyhow!(
+                "Unknown sort '{}', expected name, newest, oldest or popular",
+                s
+            )),
+        }
+    }
+}
+
 #[get("/worlds")]
-pub async fn get_worlds(data: Data<AppState>) -> impl Responder {
-    get_apps_handler(data).await
+pub async fn get_worlds(
+    request: HttpRequest,
+    query: web::Query<AppsQuery>,
+    pagination: Pagination,
+    data: Data<AppState>,
+) -> impl Responder {
+    get_apps_handler(request, query, pagination, data).await
 }
 
 #[get("/apps")]
-async fn get_apps(data: Data<AppState>) -> impl Responder {
-    get_apps_handler(data).await
+async fn get_apps(
+    request: HttpRequest,
+    query: web::Query<AppsQuery>,
+    pagination: Pagination,
+    data: Data<AppState>,
+) -> impl Responder {
+    get_apps_handler(request, query, pagination, data).await
 }
 
-async fn get_apps_handler(data: Data<AppState>) -> impl Responder {
-    let template_path = get_template_path(&data, "apps");
-    let error_path = get_template_path(&data, "error");
+async fn get_apps_handler(
+    request: HttpRequest,
+    query: web::Query<AppsQuery>,
+    pagination: Pagination,
+    data: Data<AppState>,
+) -> impl Responder {
+    let template_path = get_template_path(&data, &request, "apps");
+    let error_path = get_template_path(&data, &request, "error");
+    let include_inactive = query.include_inactive.unwrap_or(data.apps_include_inactive);
+    // Unknown values fall back to the default rather than failing the page
+    let sort = query
+        .sort
+        .as_deref()
+        .and_then(|sort| sort.parse::<AppsSort>().ok())
+        .unwrap_or(data.apps_default_sort);
+    let search = query
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(str::to_lowercase);
+    let features = data.features();
+    let show_adult = features.show_adult_content && query.adult.unwrap_or(false);
     match get_all_apps(&data).await {
-        Ok(apps) => {
+        Ok(mut apps) => {
+            // Worlds their beacon reports as inactive are only listed on request;
+            // this is independent of the admin's visibility setting
+            if !include_inactive {
+                apps.retain(|app| app.active);
+            }
+            // Filter before paging so pages are full and the page count is right
+            apps.retain(|app| {
+                app.visible
+                    && (features.debug || !app.url.contains("localhost"))
+                    && (show_adult || !app.adult)
+            });
+
             // First deduplicate by base URL (ignoring query parameters)
             let mut seen_base_urls: HashSet<String> = HashSet::new();
             let mut deduplicated_apps: Vec<DbApp> = Vec::new();
             let mut app_page_urls: HashMap<String, String> = HashMap::new();
+            let mut app_origins: HashMap<String, AppOrigin> = HashMap::new();
 
             for app in apps.into_iter() {
                 let base_url = get_base_url(&app.url).unwrap_or_else(|| app.url.clone());
                 app_page_urls.insert(app.url.clone(), app.page_path());
+                app_origins.insert(
+                    app.url.clone(),
+                    AppOrigin {
+                        origin_host: app.origin_host(),
+                        is_local: app.is_local(),
+                        signature_verified: app.signature_verified(),
+                    },
+                );
 
                 if seen_base_urls.insert(base_url) {
                     // First time seeing this base URL, keep this app
@@ -699,14 +1408,81 @@ async fn get_apps_handler(data: Data<AppState>) -> impl Responder {
                     .or_insert_with(Vec::new)
                     .push(app);
             }
+            // A matching domain lists all its worlds, otherwise only the matching ones
+            if let Some(search) = &search {
+                domain_groups.retain(|domain, apps| {
+                    if !domain.to_lowercase().contains(search.as_str()) {
+                        apps.retain(|app| {
+                            app.name.to_lowercase().contains(search.as_str())
+                                || app.description.to_lowercase().contains(search.as_str())
+                        });
+                    }
+                    !apps.is_empty()
+                });
+            }
+
+            // Live counts by base URL, only needed when sorting by popularity
+            let mut live_counts: HashMap<String, usize> = HashMap::new();
+            if sort == AppsSort::Popular {
+                prune_old_sessions(&data);
+                let sessions = match data.sessions.read() {
+                    Ok(sessions) => sessions,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                for (session_url, session_list) in sessions.iter() {
+                    if let Some(base_url) = get_base_url(session_url) {
+                        *live_counts.entry(base_url).or_default() += session_list.len();
+                    }
+                }
+            }
+            let live_count = |app: &DbApp| {
+                let base_url = get_base_url(&app.url).unwrap_or_else(|| app.url.clone());
+                live_counts.get(&base_url).copied().unwrap_or(0)
+            };
 
-            // Sort groups by domain, and apps within groups by name
+            // Sort apps within groups, then the groups themselves. Ids and domains break
+            // ties so the order doesn't shift between page loads.
             let mut sorted_groups: Vec<(String, Vec<DbApp>)> = domain_groups.into_iter().collect();
-            sorted_groups.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
+            let by_name = |a: &DbApp, b: &DbApp| {
+                a.name.to_lowercase().cmp(&b.name.to_lowercase()).then(a.id.cmp(&b.id))
+            };
             for (_, apps) in sorted_groups.iter_mut() {
-                apps.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+                match sort {
+                    AppsSort::Name => apps.sort_by(by_name),
+                    AppsSort::Newest => apps
+                        .sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id))),
+                    AppsSort::Oldest => apps
+                        .sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id))),
+                    AppsSort::Popular => apps.sort_by(|a, b| {
+                        live_count(b).cmp(&live_count(a)).then_with(|| by_name(a, b))
+                    }),
+                }
+            }
+            let by_domain = |a: &(String, Vec<DbApp>), b: &(String, Vec<DbApp>)| {
+                a.0.to_lowercase().cmp(&b.0.to_lowercase()).then_with(|| a.0.cmp(&b.0))
+            };
+            match sort {
+                AppsSort::Name => sorted_groups.sort_by(by_domain),
+                // Each group is already ordered, so its first app decides its place
+                AppsSort::Newest => sorted_groups.sort_by(|a, b| {
+                    b.1[0].created_at.cmp(&a.1[0].created_at).then_with(|| by_domain(a, b))
+                }),
+                AppsSort::Oldest => sorted_groups.sort_by(|a, b| {
+                    a.1[0].created_at.cmp(&b.1[0].created_at).then_with(|| by_domain(a, b))
+                }),
+                // A domain ranks by everyone in any of its worlds
+                AppsSort::Popular => {
+                    let count = |group: &(String, Vec<DbApp>)| -> usize {
+                        group.1.iter().map(live_count).sum()
+                    };
+                    sorted_groups
+                        .sort_by(|a, b| count(b).cmp(&count(a)).then_with(|| by_domain(a, b)))
+                }
             }
 
+            // Pages are made of whole domain groups so a domain never straddles two pages
+            let total_pages = pagination.total_pages(sorted_groups.len() as i64);
+            let sorted_groups = pagination.apply(sorted_groups);
             let domains: Vec<String> = sorted_groups.iter().map(|(d, _)| d.clone()).collect();
             let app_groups: Vec<Vec<DbApp>> = sorted_groups.into_iter().map(|(_, v)| v).collect();
 
@@ -714,15 +1490,22 @@ async fn get_apps_handler(data: Data<AppState>) -> impl Responder {
             ctx.insert("apps", &app_groups);
             ctx.insert("domains", &domains);
             ctx.insert("app_pages", &app_page_urls);
-            ctx.insert("DEBUG", &data.debug);
-            ctx.insert("SHOW_ADULT_CONTENT", &data.show_adult_content);
+            ctx.insert("app_origins", &app_origins);
+            ctx.insert("sort", &sort);
+            ctx.insert("include_inactive", &include_inactive);
+            ctx.insert("pagination", &pagination);
+            ctx.insert("total_pages", &total_pages);
+            ctx.insert("q", query.q.as_deref().map(str::trim).unwrap_or_default());
+            ctx.insert("adult", &show_adult);
+            ctx.insert("DEBUG", &features.debug);
+            ctx.insert("SHOW_ADULT_CONTENT", &features.show_adult_content);
             match data.tera.render(&template_path, &ctx) {
                 Ok(html) => web::Html::new(html),
                 Err(e) => template_fail_screen(e),
             }
         }
         Err(e) => {
-            eprintln!("Error fetching apps from DB: {}", e);
+            log_error!("Error fetching apps from DB: {}", e);
             match data.tera.render(&error_path, &Context::new()) {
                 Ok(html) => web::Html::new(html),
                 Err(e) => template_fail_screen(e),
@@ -731,21 +1514,49 @@ async fn get_apps_handler(data: Data<AppState>) -> impl Responder {
     }
 }
 
+/// Relay with its liveness for template rendering
+#[derive(Serialize)]
+struct RelayWithStatus {
+    ap_id: String,
+    name: String,
+    local: bool,
+    last_activity_at: Option<DateTime<Utc>>,
+    stale: bool,
+}
+
 #[get("/relays")]
-async fn get_relays(data: Data<AppState>) -> impl Responder {
-    let template_path = get_template_path(&data, "relays");
-    let error_path = get_template_path(&data, "error");
-    match get_all_relays(&data).await {
-        Ok(relays) => {
+async fn get_relays(
+    request: HttpRequest,
+    pagination: Pagination,
+    data: Data<AppState>,
+) -> impl Responder {
+    let template_path = get_template_path(&data, &request, "relays");
+    let error_path = get_template_path(&data, &request, "error");
+    match get_relays_page(&data, pagination.per_page, pagination.offset()).await {
+        Ok((relays, total)) => {
+            let stale_cutoff = Utc::now() - chrono::Duration::days(data.relay_stale_days);
+            let relays: Vec<RelayWithStatus> = relays
+                .into_iter()
+                .map(|relay| RelayWithStatus {
+                    stale: !relay.local
+                        && relay.last_activity_at.is_none_or(|at| at < stale_cutoff),
+                    ap_id: relay.ap_id.inner().to_string(),
+                    name: relay.name,
+                    local: relay.local,
+                    last_activity_at: relay.last_activity_at,
+                })
+                .collect();
             let mut ctx = tera::Context::new();
             ctx.insert("relays", &relays);
+            ctx.insert("pagination", &pagination);
+            ctx.insert("total_pages", &pagination.total_pages(total));
             match data.tera.render(&template_path, &ctx) {
                 Ok(html) => web::Html::new(html),
                 Err(e) => template_fail_screen(e),
             }
         }
         Err(e) => {
-            eprintln!("Error fetching relays from DB: {}", e);
+            log_error!("Error fetching relays from DB: {}", e);
             match data.tera.render(&error_path, &Context::new()) {
                 Ok(html) => web::Html::new(html),
                 Err(e) => template_fail_screen(e),
@@ -754,39 +1565,363 @@ async fn get_relays(data: Data<AppState>) -> impl Responder {
     }
 }
 
+/// `Cache-Control` for the actor and webfinger documents. Peers and shared caches may
+/// keep them for `ACTOR_CACHE_MAX_AGE`, which bounds how long a changed key goes unseen.
+fn actor_cache_control(data: &Data<AppState>) -> String {
+    if data.actor_cache_max_age == 0 {
+        "no-cache".to_string()
+    } else {
+        format!("public, max-age={}", data.actor_cache_max_age)
+    }
+}
+
 /// Handles requests to fetch system user json over HTTP
 #[get("/relay")]
-async fn http_get_system_user(data: Data<AppState>) -> impl Responder {
-    let user = get_relay_by_id(0, &data)
-        .await
-        .expect("Failed to get system user!");
+async fn http_get_system_user(request: HttpRequest, data: Data<AppState>) -> impl Responder {
+    let user = match get_relay_by_id(0, &data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            return HttpResponse::InternalServerError().body("Relay not initialized");
+        }
+    };
+    // Browsers following the webfinger profile-page link get a profile page. The answer
+    // depends on Accept, so caches must not hand the page to federation peers.
+    if prefers_html(&request) {
+        let followers_count = match get_followers_count(&data).await {
+            Ok(count) => count,
+            Err(e) => {
+                log_error!("Error fetching followers count: {}", e);
+                0
+            }
+        };
+        let mut ctx = Context::new();
+        ctx.insert("name", &user.name);
+        ctx.insert("ap_id", user.ap_id.inner().as_str());
+        ctx.insert("followers_count", &followers_count);
+        ctx.insert("key_url", "/relay/key.pem");
+        let template_path = get_template_path(&data, &request, "relay");
+        let (mut response, body) = match data.tera.render(&template_path, &ctx) {
+            Ok(html) => (HttpResponse::Ok(), html),
+            Err(e) => {
+                log_error!("Template error: {:?}", e);
+                let body = "Failed to render to template!".to_string();
+                (HttpResponse::InternalServerError(), body)
+            }
+        };
+        return response
+            .content_type("text/html; charset=utf-8")
+            .insert_header(("Vary", "Accept"))
+            .insert_header(("Cache-Control", "no-cache"))
+            .body(body);
+    }
     let json_user = Relay {
         id: user.ap_id.clone(),
         kind: ServiceType::Service,
-        preferred_username: user.name.clone(),
+        preferred_username: RELAY_USERNAME.to_string(),
         name: user.name.clone(),
         inbox: user.inbox.clone(),
         outbox: user.outbox.clone(),
         public_key: user.public_key(),
+        endpoints: user
+            .local_shared_inbox()
+            .map(|shared_inbox| Endpoints { shared_inbox }),
+        followers: user.local_followers_url(),
+    };
+    HttpResponse::Ok()
+        .content_type(FEDERATION_CONTENT_TYPE)
+        .insert_header(("Vary", "Accept"))
+        .insert_header(("Cache-Control", actor_cache_control(&data)))
+        .json(with_activity_context(json_user, &data))
+}
+
+/// The relay actor's public key on its own, for tools that want PEM rather than JSON.
+/// Only the public half is ever read here.
+#[get("/relay/key.pem")]
+async fn get_relay_public_key(data: Data<AppState>) -> HttpResponse {
+    match get_system_user(&data).await {
+        Ok(user) => HttpResponse::Ok()
+            .content_type("application/x-pem-file")
+            .insert_header(("Cache-Control", actor_cache_control(&data)))
+            .body(user.public_key_pem().to_string()),
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            HttpResponse::InternalServerError().body("Relay not initialized")
+        }
+    }
+}
+
+/// Followers collection of the relay actor. Without `?page=` only `totalItems` and a
+/// link to the first page are exposed, so crawlers reading the count never cause
+/// follower rows to be loaded.
+#[get("/relay/followers")]
+async fn get_relay_followers_collection(
+    pagination: Pagination,
+    data: Data<AppState>,
+) -> HttpResponse {
+    let user = match get_relay_by_id(0, &data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            return HttpResponse::InternalServerError().body("Relay not initialized");
+        }
+    };
+    let followers_url = match user.followers_url() {
+        Ok(url) => url,
+        Err(e) => {
+            log_error!("Error building followers URL: {}", e);
+            return HttpResponse::InternalServerError().body("Relay not initialized");
+        }
+    };
+    let count = match get_followers_count(&data).await {
+        Ok(count) => count,
+        Err(e) => {
+            log_error!("Error fetching followers count: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to get followers count");
+        }
+    };
+    let page_url = |page: i64| format!("{}?page={}", followers_url, page);
+    if !pagination.requested {
+        return HttpResponse::Ok()
+            .content_type(FEDERATION_CONTENT_TYPE)
+            .json(serde_json::json!({
+                "@context": "https://www.w3.org/ns/activitystreams",
+                "id": followers_url,
+                "type": "OrderedCollection",
+                "totalItems": count,
+                "first": page_url(1),
+            }));
+    }
+    let followers =
+        match get_relay_follower_ids_page(&data, pagination.per_page, pagination.offset()).await {
+            Ok(followers) => followers,
+            Err(e) => {
+                log_error!("Error fetching followers page: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to fetch followers");
+            }
+        };
+    let mut collection_page = serde_json::json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": page_url(pagination.page),
+        "type": "OrderedCollectionPage",
+        "partOf": followers_url,
+        "totalItems": count,
+        "orderedItems": followers,
+    });
+    if pagination.page < pagination.total_pages(count) {
+        collection_page["next"] = page_url(pagination.page + 1).into();
+    }
+    if pagination.page > 1 {
+        collection_page["prev"] = page_url(pagination.page - 1).into();
+    }
+    HttpResponse::Ok()
+        .content_type(FEDERATION_CONTENT_TYPE)
+        .json(collection_page)
+}
+
+/// The visible app directory as an `OrderedCollection`, so other relays can crawl and
+/// import it. Like the followers collection, the bare URL only links to the first page;
+/// `?page=` pages embed the full `App` objects.
+#[get("/relay/catalog")]
+async fn get_relay_catalog(pagination: Pagination, data: Data<AppState>) -> HttpResponse {
+    let user = match get_relay_by_id(0, &data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            return HttpResponse::InternalServerError().body("Relay not initialized");
+        }
+    };
+    let catalog_url = format!("{}/catalog", user.ap_id.inner());
+    let count = match get_catalog_apps_count(&data).await {
+        Ok(count) => count,
+        Err(e) => {
+            log_error!("Error counting catalog apps: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to count apps");
+        }
     };
+    let page_url = |page: i64| format!("{}?page={}", catalog_url, page);
+    if !pagination.requested {
+        return HttpResponse::Ok()
+            .content_type(FEDERATION_CONTENT_TYPE)
+            .json(serde_json::json!({
+                "@context": "https://www.w3.org/ns/activitystreams",
+                "id": catalog_url,
+                "type": "OrderedCollection",
+                "totalItems": count,
+                "first": page_url(1),
+            }));
+    }
+    let apps = match get_catalog_apps_page(&data, pagination.per_page, pagination.offset()).await {
+        Ok(apps) => apps,
+        Err(e) => {
+            log_error!("Error fetching catalog page: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch apps");
+        }
+    };
+    let mut items = Vec::with_capacity(apps.len());
+    for app in apps {
+        match app.into_json(&data).await {
+            Ok(app) => items.push(app),
+            Err(e) => log_error!("Error serializing app for catalog: {}", e),
+        }
+    }
+    let mut collection_page = serde_json::json!({
+        "@context": app_context(&data),
+        "id": page_url(pagination.page),
+        "type": "OrderedCollectionPage",
+        "partOf": catalog_url,
+        "totalItems": count,
+        "orderedItems": items,
+    });
+    if pagination.page < pagination.total_pages(count) {
+        collection_page["next"] = page_url(pagination.page + 1).into();
+    }
+    if pagination.page > 1 {
+        collection_page["prev"] = page_url(pagination.page - 1).into();
+    }
     HttpResponse::Ok()
         .content_type(FEDERATION_CONTENT_TYPE)
-        .json(WithContext::new_default(json_user))
+        .json(collection_page)
+}
+
+/// Summary of this relay for dashboards and crawlers
+#[get("/api/relay-info")]
+async fn api_get_relay_info(data: Data<AppState>) -> HttpResponse {
+    let user = match get_relay_by_id(0, &data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            return HttpResponse::InternalServerError().body("Relay not initialized");
+        }
+    };
+    let followers_count = match get_followers_count(&data).await {
+        Ok(count) => count,
+        Err(e) => {
+            log_error!("Error fetching followers count: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to get followers count");
+        }
+    };
+    let apps_count = match get_apps_count(&data).await {
+        Ok(count) => count,
+        Err(e) => {
+            log_error!("Error fetching apps count: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to get apps count");
+        }
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "id": user.ap_id.inner(),
+        "name": user.name,
+        "followers_count": followers_count,
+        "apps_count": apps_count,
+    }))
+}
+
+/// What this relay speaks, so peers and tooling can adapt without probing. Inbox
+/// types come from [`ActivityKind::INBOX`], the same table the inbox routes by.
+#[get("/api/capabilities")]
+async fn api_get_capabilities(data: Data<AppState>) -> HttpResponse {
+    let features = data.features();
+    HttpResponse::Ok().json(serde_json::json!({
+        "api_version": API_VERSION,
+        "inbox": {
+            "supported_activities": ActivityKind::INBOX,
+            "accepted_activities": data.accepted_activities,
+            "requires_signature": true,
+            "max_age_hours": data.activity_max_age.map(|max_age| max_age.num_hours()),
+        },
+        "content_types": [FEDERATION_CONTENT_TYPE, "application/json", "text/html"],
+        "features": {
+            // Actor and object documents are served to unsigned GETs
+            "authorized_fetch": false,
+            "strict_follows": features.strict_follows,
+            "mutual_only": features.mutual_only,
+            "auto_follow_back": features.auto_follow_back,
+        },
+    }))
+}
+
+#[derive(Serialize)]
+struct RelayApp {
+    id: i32,
+    ap_id: String,
+    name: String,
+    url: String,
+    visible: bool,
+    active: bool,
+    created_at: DateTime<Utc>,
+}
+
+/// Apps a relay has contributed, for auditing a peer. Admins also see hidden
+/// and inactive apps.
+#[get("/api/relays/{id}/apps")]
+async fn api_get_relay_apps(
+    request: HttpRequest,
+    path: web::Path<i32>,
+    pagination: Pagination,
+    data: Data<AppState>,
+) -> HttpResponse {
+    let relay_id = path.into_inner();
+    if let Err(e) = get_relay_by_id(relay_id, &data).await {
+        log_error!("Error fetching relay {}: {}", relay_id, e);
+        return HttpResponse::NotFound().body("No relay found");
+    }
+    let is_admin = validate_admin_token(&request, &data).await.is_ok();
+
+    match get_apps_by_origin_relay(
+        &data,
+        relay_id,
+        is_admin,
+        pagination.per_page,
+        pagination.offset(),
+    )
+    .await
+    {
+        Ok((apps, total)) => HttpResponse::Ok().json(serde_json::json!({
+            "relay_id": relay_id,
+            "page": pagination.page,
+            "per_page": pagination.per_page,
+            "total": total,
+            "apps": apps
+                .into_iter()
+                .map(|app| RelayApp {
+                    id: app.id,
+                    ap_id: app.ap_id.inner().to_string(),
+                    name: app.name,
+                    url: app.url,
+                    visible: app.visible,
+                    active: app.active,
+                    created_at: app.created_at,
+                })
+                .collect::<Vec<_>>(),
+        })),
+        Err(e) => {
+            log_error!("Error fetching apps for relay {}: {}", relay_id, e);
+            HttpResponse::InternalServerError().body("Failed to fetch apps")
+        }
+    }
 }
 
 #[get("relay/activities/{id}")]
 async fn get_activity(info: web::Path<i32>, data: Data<AppState>) -> impl Responder {
     match get_activity_by_id(info.into_inner(), &data).await {
+        // Serve the exact stored document when we have one, rebuilding it for older rows
+        Ok(DbActivity { raw: Some(raw), .. }) => HttpResponse::Ok()
+            .content_type(FEDERATION_CONTENT_TYPE)
+            .json(raw),
         Ok(activity) => HttpResponse::Ok()
             .content_type(FEDERATION_CONTENT_TYPE)
             .json(activity),
         Err(e) => {
-            eprintln!("Error fetching activity: {}", e);
+            log_error!("Error fetching activity: {}", e);
             HttpResponse::NotFound().body("No activity found")
         }
     }
 }
 
+/// Inbox activities, tried in order. Untagged is unambiguous because every variant's
+/// `type` field only deserializes from its own name (`FollowType` accepts nothing but
+/// `"Follow"`), so Create and Update can't swallow each other and unknown types fail.
+/// New variants need a distinct `type` too, and an entry in [`ActivityKind`].
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(untagged)]
 #[enum_delegate::implement(ActivityHandler)]
@@ -796,29 +1931,205 @@ pub enum RelayAcceptedActivities {
     Update(Update),
 }
 
+/// The `keyId` named in a request's HTTP `Signature` header
+fn signature_key_id(request: &HttpRequest) -> Option<String> {
+    let header = request.headers().get("Signature")?.to_str().ok()?;
+    header.split(',').find_map(|param| {
+        let (name, value) = param.trim().split_once('=')?;
+        (name == "keyId").then(|| value.trim_matches('"').to_string())
+    })
+}
+
+/// Whether arrays/objects in `body` nest deeper than `max_depth`, scanning the raw
+/// bytes so the check costs no allocation. Brackets inside strings are ignored.
+fn json_depth_exceeds(body: &[u8], max_depth: usize) -> bool {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in body {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth > max_depth {
+                    return true;
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    false
+}
+
 #[post("/relay/inbox")]
 async fn http_post_relay_inbox(
     request: HttpRequest,
     body: Bytes,
     data: Data<AppState>,
 ) -> HttpResponse {
+    handle_inbox(request, body, data).await
+}
+
+/// Shared inbox advertised in the actor's `endpoints`, accepting activities for any local actor
+#[post("/inbox")]
+async fn http_post_shared_inbox(
+    request: HttpRequest,
+    body: Bytes,
+    data: Data<AppState>,
+) -> HttpResponse {
+    handle_inbox(request, body, data).await
+}
+
+async fn handle_inbox(request: HttpRequest, body: Bytes, data: Data<AppState>) -> HttpResponse {
+    // Refuse pathologically nested documents before anything parses them
+    if json_depth_exceeds(&body, data.inbox_max_depth) {
+        log_error!("Rejected inbox activity nested deeper than {}", data.inbox_max_depth);
+        return HttpResponse::BadRequest().body("Activity is nested too deeply");
+    }
+    // Wait briefly for a slot, then shed load so the sender retries later
+    let _permit = match tokio::time::timeout(
+        data.inbox_queue_timeout,
+        data.inbox_permits.clone().acquire_owned(),
+    )
+    .await
+    {
+        Ok(Ok(permit)) => permit,
+        _ => {
+            log_error!("Inbox busy, rejecting activity");
+            return HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", "30"))
+                .body("Inbox is busy, try again later");
+        }
+    };
+    let json = serde_json::from_slice::<serde_json::Value>(&body).ok();
+    // Like/Announce/Delete etc. are valid ActivityPub we simply don't act on, so
+    // answer 202 instead of letting deserialization fail with a 500
+    let kind = json
+        .as_ref()
+        .and_then(|json| json.get("type")?.as_str().map(str::to_string));
+    if let Some(kind) = kind {
+        // Types handled by RelayAcceptedActivities; anything else is acknowledged and dropped
+        match kind.parse::<ActivityKind>().ok().filter(|kind| ActivityKind::INBOX.contains(kind)) {
+            Some(kind) if !data.accepted_activities.contains(&kind) => {
+                log_error!("Rejected {} activity: not in ACCEPTED_ACTIVITIES", kind);
+                return HttpResponse::Forbidden()
+                    .body(format!("This relay does not accept {} activities", kind));
+            }
+            Some(_) => {}
+            None => {
+                if data.features().debug {
+                    log_info!("Ignoring unsupported {} activity", kind);
+                }
+                return HttpResponse::Accepted().finish();
+            }
+        }
+    }
+    // Signed activities can be captured and resent, so stale ones are refused outright.
+    // Peers that don't send `published` yet are let through
+    if let (Some(max_age), Some(published)) = (
+        data.activity_max_age,
+        json.as_ref().and_then(|json| json.get("published")),
+    ) {
+        if let Err(reason) = check_published(published, max_age) {
+            log_error!("Rejected inbox activity: {}", reason);
+            return HttpResponse::BadRequest().body(reason);
+        }
+    }
+    let actor = json
+        .as_ref()
+        .and_then(|json| json.get("actor")?.as_str().map(str::to_string));
+    let activity_id = json
+        .as_ref()
+        .and_then(|json| json.get("id")?.as_str().map(str::to_string));
+    let key_id = signature_key_id(&request);
     match receive_activity::<WithContext<RelayAcceptedActivities>, DbRelay, AppState>(
         request, body, &data,
     )
     .await
     {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        Ok(_) => {
+            // Only record activity once the signature has been verified
+            if let Some(actor) = actor {
+                if let Err(e) = touch_relay_activity(&data, &actor).await {
+                    log_error!("Error recording relay activity: {}", e);
+                }
+            }
+            if let (Some(activity_id), Some(raw)) = (&activity_id, &json) {
+                if let Err(e) = store_activity_raw(&data, activity_id, raw).await {
+                    log_error!("Error storing received activity: {}", e);
+                }
+            }
+            if let (Some(activity_id), Some(key_id)) = (activity_id, key_id) {
+                if let Err(e) = record_app_signature(&data, &activity_id, &key_id).await {
+                    log_error!("Error recording app signature: {}", e);
+                }
+            }
+            HttpResponse::Ok().finish()
+        }
+        Err(e) => {
+            // The signer may have rotated its key, so drop it and re-fetch next time
+            if let (
+                Some(actor),
+                super::error::Error::Federation(FederationError::ActivitySignatureInvalid),
+            ) = (actor, &e)
+            {
+                data.public_key_cache.invalidate(&actor);
+            }
+            // Bad signatures and malformed activities are the sender's problem, so they get
+            // a 4xx rather than the 500 every failure used to produce
+            e.error_response()
+        }
+    }
+}
+
+/// How far ahead of our clock a sender's `published` may be
+const ACTIVITY_CLOCK_SKEW: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Checks an activity's `published` against the replay window (`ACTIVITY_MAX_AGE_HOURS`)
+fn check_published(
+    published: &serde_json::Value,
+    max_age: chrono::Duration,
+) -> Result<(), String> {
+    let published = published
+        .as_str()
+        .and_then(|published| DateTime::parse_from_rfc3339(published).ok())
+        .ok_or("Activity has an invalid published timestamp".to_string())?
+        .with_timezone(&Utc);
+    let now = Utc::now();
+    if published < now - max_age {
+        return Err(format!(
+            "Activity was published at {}, older than the {}h replay window",
+            published.to_rfc3339(),
+            max_age.num_hours()
+        ));
     }
+    if published > now + ACTIVITY_CLOCK_SKEW {
+        return Err(format!(
+            "Activity was published at {}, too far in the future",
+            published.to_rfc3339()
+        ));
+    }
+    Ok(())
 }
 
 pub async fn not_found(request: HttpRequest, data: Data<AppState>) -> impl Responder {
-    println!(
+    log_info!(
         "Got request for unknown route: {} {}",
         request.uri().path(),
         request.method().as_str()
     );
-    let error_path = get_template_path(&data, "error");
+    let error_path = get_template_path(&data, &request, "error");
     match data.tera.render(&error_path, &Context::new()) {
         Ok(html) => web::Html::new(html),
         Err(e) => template_fail_screen(e),
@@ -826,8 +2137,8 @@ pub async fn not_found(request: HttpRequest, data: Data<AppState>) -> impl Respo
 }
 
 #[get("/login")]
-async fn login(_request: HttpRequest, data: Data<AppState>) -> impl Responder {
-    let template_path = get_template_path(&data, "login");
+async fn login(request: HttpRequest, data: Data<AppState>) -> impl Responder {
+    let template_path = get_template_path(&data, &request, "login");
     match data.tera.render(&template_path, &Context::new()) {
         Ok(html) => web::Html::new(html),
         Err(e) => template_fail_screen(e),
@@ -839,31 +2150,73 @@ async fn request_login_token(
     data: Data<AppState>,
     req_body: web::Form<LoginPayload>,
 ) -> impl Responder {
-    let user = get_relay_by_id(0, &data)
-        .await
-        .expect("Failed to get system user!");
+    let user = match get_relay_by_id(0, &data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            return HttpResponse::InternalServerError().body("Relay not initialized");
+        }
+    };
     let password = std::env::var("ADMIN_PASSWORD").expect("ADMIN_PASSWORD must be set");
     if password != req_body.password {
         return HttpResponse::Unauthorized().body("Invalid password");
     }
 
-    let duration = jwt_simple::prelude::Duration::from_days(1);
-    let claim = Claims::create(duration);
-    let keypair = RS256KeyPair::from_pem(&user.private_key_pem().unwrap()).unwrap();
-    let token = keypair.sign(claim).unwrap();
+    let cookie = match admin_token_cookie(&user, &data) {
+        Ok(cookie) => cookie,
+        Err(response) => return response,
+    };
 
     HttpResponse::Found() // HTTP 302 redirect to /admin
         .append_header(("Location", "/admin"))
-        .cookie(
-            Cookie::build("relay-admin-token", token)
-                .path("/")
-                .http_only(true)
-                .max_age(time::Duration::days(1))
-                .finish(),
-        )
+        .cookie(cookie)
         .finish()
 }
 
+#[derive(Serialize)]
+struct AdminTokenRefresh {
+    renewed: bool,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// Swaps a still-valid admin token for a fresh one once less than half of
+/// `ADMIN_TOKEN_TTL_HOURS` remains, so admins who keep using the dashboard stay logged in.
+/// Earlier calls leave the cookie alone. Expired tokens still need a new login.
+#[post("/admin/refresh")]
+async fn admin_refresh_token(request: HttpRequest, data: Data<AppState>) -> HttpResponse {
+    let claims = match admin_token_claims(&request, &data).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let expires_at = |secs: u64| DateTime::<Utc>::from_timestamp(secs as i64, 0);
+    let current_expiry = claims.expires_at.map(|expiry| expiry.as_secs());
+    let remaining = current_expiry
+        .map(|expiry| expiry.saturating_sub(Clock::now_since_epoch().as_secs()))
+        .unwrap_or(0);
+    if remaining > data.admin_token_ttl_hours * 3600 / 2 {
+        return HttpResponse::Ok().json(AdminTokenRefresh {
+            renewed: false,
+            expires_at: current_expiry.and_then(expires_at),
+        });
+    }
+    let user = match get_relay_by_id(0, &data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            return HttpResponse::InternalServerError().body("Relay not initialized");
+        }
+    };
+    let cookie = match admin_token_cookie(&user, &data) {
+        Ok(cookie) => cookie,
+        Err(response) => return response,
+    };
+    let new_expiry = Clock::now_since_epoch().as_secs() + data.admin_token_ttl_hours * 3600;
+    HttpResponse::Ok().cookie(cookie).json(AdminTokenRefresh {
+        renewed: true,
+        expires_at: expires_at(new_expiry),
+    })
+}
+
 #[get("/images/{id}")]
 async fn get_image(request: HttpRequest, _data: Data<AppState>) -> impl Responder {
     let id = request.match_info().get("id").unwrap_or("");
@@ -883,23 +2236,34 @@ async fn get_image(request: HttpRequest, _data: Data<AppState>) -> impl Responde
     let image = match std::fs::read(&image_url) {
         Ok(image_bytes) => image_bytes,
         Err(_) => {
-            eprintln!("Failed to load image at: {}", image_url);
+            log_error!("Failed to load image at: {}", image_url);
             std::fs::read("frontend/images/noimage.png").expect("Failed to load placeholder image")
         }
     };
-    let mime = match image_url.rsplit_once('.').map(|(_, ext)| ext) {
-        Some("png") => "image/png",
-        Some("jpg") | Some("jpeg") => "image/jpeg",
-        Some("gif") => "image/gif",
-        Some("svg") => "image/svg+xml",
-        _ => "image/jpeg",
-    };
-    HttpResponse::Ok().content_type(mime).body(image)
+    let mime = image_media_type(&image_url).unwrap_or("image/jpeg");
+    let mut response = HttpResponse::Ok();
+    response.content_type(mime);
+    // Content-addressed files never change; older id-named ones may still be rewritten
+    let is_hashed = id
+        .split_once('.')
+        .is_some_and(|(stem, _)| stem.len() == 64 && stem.chars().all(|c| c.is_ascii_hexdigit()));
+    if is_hashed {
+        response.insert_header(("Cache-Control", "public, max-age=31536000, immutable"));
+    }
+    // Files are never rendered as documents, so a script in one (e.g. an SVG stored
+    // before those were refused) can't run on our origin
+    response
+        .insert_header(("Content-Security-Policy", "default-src 'none'; sandbox"))
+        .insert_header(("X-Content-Type-Options", "nosniff"));
+    if mime == "image/svg+xml" {
+        response.insert_header(("Content-Disposition", "attachment"));
+    }
+    response.body(image)
 }
 
 #[get("/admin")]
 async fn admin_page(request: HttpRequest, data: Data<AppState>) -> impl Responder {
-    let template_path = get_template_path(&data, "admin");
+    let template_path = get_template_path(&data, &request, "admin");
 
     // Validate JWT token
     if let Err(response) = validate_admin_token(&request, &data).await {
@@ -912,16 +2276,117 @@ async fn admin_page(request: HttpRequest, data: Data<AppState>) -> impl Responde
         return response;
     }
 
+    let reports: Vec<OpenReport> = match get_open_report_counts(&data).await {
+        Ok(reports) => reports
+            .into_iter()
+            .map(|(app_id, name, url, count, latest_reason)| OpenReport {
+                app_id,
+                name,
+                url,
+                count,
+                latest_reason,
+            })
+            .collect(),
+        Err(e) => {
+            log_error!("Error fetching reports: {}", e);
+            Vec::new()
+        }
+    };
+
     match get_all_apps(&data).await {
         Ok(apps) => {
             let mut ctx = tera::Context::new();
             ctx.insert("apps", &apps);
+            ctx.insert("reports", &reports);
             match data.tera.render(&template_path, &ctx) {
                 Ok(html) => HttpResponse::Ok().body(html),
-                Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+                Err(e) => admin_template_fail(e),
+            }
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Open reports for one app, as listed on the admin page
+#[derive(Serialize)]
+struct OpenReport {
+    app_id: i32,
+    name: String,
+    url: String,
+    count: i64,
+    latest_reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReportPayload {
+    reason: String,
+}
+
+/// Longest report reason kept; anything beyond is cut off
+const MAX_REPORT_REASON_CHARS: usize = 1000;
+
+#[post("/app/{id}/report")]
+async fn report_app(
+    request: HttpRequest,
+    path: web::Path<i32>,
+    req_body: web::Form<ReportPayload>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    let app_id = path.into_inner();
+    let reason: String = normalize_whitespace(&req_body.reason)
+        .chars()
+        .take(MAX_REPORT_REASON_CHARS)
+        .collect();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().body("A reason is required");
+    }
+    if get_app_by_id(app_id, &data).await.is_err() {
+        return HttpResponse::NotFound().body("No app found");
+    }
+
+    let reporter_ip = client_ip(&request, &data);
+    if data.reports_per_hour > 0 {
+        match count_recent_reports_by_ip(&data, &reporter_ip).await {
+            Ok(count) if count >= data.reports_per_hour => {
+                return HttpResponse::TooManyRequests()
+                    .insert_header(("Retry-After", "3600"))
+                    .body("Too many reports, please try again later");
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log_error!("Error counting reports: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to save report");
             }
         }
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+
+    match create_report(&data, app_id, &reason, &reporter_ip).await {
+        Ok(_) => HttpResponse::Ok().body("Thanks, your report has been sent to the relay admin"),
+        Err(e) => {
+            log_error!("Error saving report: {}", e);
+            HttpResponse::InternalServerError().body("Failed to save report")
+        }
+    }
+}
+
+#[post("/admin/reports/resolve")]
+async fn admin_resolve_reports(
+    request: HttpRequest,
+    req_body: web::Form<ToggleVisibilityPayload>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
+    }
+
+    match resolve_reports(&data, req_body.app_id).await {
+        Ok(_) => HttpResponse::SeeOther()
+            .append_header(("Location", "/admin"))
+            .finish(),
+        Err(e) => {
+            log_error!("Error resolving reports: {}", e);
+            HttpResponse::InternalServerError().body("Failed to resolve reports")
+        }
     }
 }
 
@@ -935,24 +2400,26 @@ async fn webfinger(query: web::Query<WebfingerQuery>, data: Data<AppState>) -> i
     let name = match extract_webfinger_name(&query.resource, &data) {
         Ok(name) => name,
         Err(e) => {
-            eprintln!("Error during webfinger lookup: {:?}", e);
+            log_error!("Error during webfinger lookup: {:?}", e);
             ""
         }
     };
-    if name != "relay" {
+    if name != RELAY_USERNAME {
         return HttpResponse::NotFound().finish();
     }
     let db_user = match get_system_user(&data).await {
         Ok(user) => user,
         Err(e) => {
-            eprintln!("Error fetching system user: {}", e);
+            log_error!("Error fetching system user: {}", e);
             return HttpResponse::InternalServerError().finish();
         }
     };
-    HttpResponse::Ok().json(build_webfinger_response(
-        query.resource.clone(),
-        db_user.ap_id.into_inner(),
-    ))
+    // Besides the `self` link this includes a text/html profile-page link to the actor
+    // URL, which /relay answers with the homepage for browsers
+    HttpResponse::Ok()
+        .insert_header(("Vary", "Accept"))
+        .insert_header(("Cache-Control", actor_cache_control(&data)))
+        .json(build_webfinger_response(query.resource.clone(), db_user.ap_id.into_inner()))
 }
 
 #[post("/session")]
@@ -970,7 +2437,7 @@ async fn update_session_info(
         let mut sessions = match data.sessions.write() {
             Ok(guard) => guard,
             Err(poisoned) => {
-                eprintln!("Warning: sessions lock was poisoned. Attempting recovery...");
+                log_error!("Warning: sessions lock was poisoned. Attempting recovery...");
                 poisoned.into_inner()
             }
         };
@@ -1069,16 +2536,17 @@ async fn admin_follow(
 
     let mut ctx = tera::Context::new();
     ctx.insert("message", "Successfully followed!");
-    let template_path = get_template_path(&data, "admin");
+    let template_path = get_template_path(&data, &request, "admin");
     match db_user.follow(&req_body.follow_url, &data).await {
-        Ok(_) => match data.tera.render(&template_path, &ctx) {
-            Ok(html) => HttpResponse::Ok().body(html),
-            Err(e) => {
-                eprintln!("Template error: {}", e);
-                HttpResponse::InternalServerError().body(e.to_string())
-            }
-        },
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        Ok(_) => {}
+        Err(super::error::Error::Duplicate(_)) => {
+            ctx.insert("message", "Already following this relay.");
+        }
+        Err(e) => return e.error_response(),
+    }
+    match data.tera.render(&template_path, &ctx) {
+        Ok(html) => HttpResponse::Ok().body(html),
+        Err(e) => admin_template_fail(e),
     }
 }
 
@@ -1094,26 +2562,55 @@ async fn admin_toggle_visible(
     }
 
     match toggle_app_visibility(req_body.app_id, &data).await {
-        Ok(_) => {
-            let template_path = get_template_path(&data, "admin");
+        Ok(visible) => {
+            if visible {
+                send_held_app_create(&data, req_body.app_id).await;
+            }
+            let template_path = get_template_path(&data, &request, "admin");
             match get_all_apps(&data).await {
                 Ok(apps) => {
                     let mut ctx = tera::Context::new();
                     ctx.insert("apps", &apps);
                     match data.tera.render(&template_path, &ctx) {
                         Ok(html) => HttpResponse::Ok().body(html),
-                        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+                        Err(e) => admin_template_fail(e),
                     }
                 }
-                Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+                Err(e) => e.error_response(),
             }
         }
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        Err(e) => e.error_response(),
     }
 }
 
-#[post("/admin/delete-world")]
-pub async fn admin_delete_world(
+/// Registers a world by hand as if its beacon had been received by this relay
+#[post("/admin/ingest")]
+async fn admin_ingest(
+    request: HttpRequest,
+    req_body: web::Json<BeaconPayload>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
+    }
+
+    let (app_id, created) = match ingest_beacon(&data, &req_body, SOURCE_ADMIN).await {
+        Ok(BeaconOutcome::Created(id)) => (id, true),
+        Ok(BeaconOutcome::Updated(id) | BeaconOutcome::Unchanged(id)) => (id, false),
+        Err(response) => return response,
+    };
+    match get_app_by_id(app_id, &data).await {
+        Ok(app) if created => HttpResponse::Created().json(app),
+        Ok(app) => HttpResponse::Ok().json(app),
+        Err(e) => {
+            log_error!("Error fetching ingested app: {}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch ingested app")
+        }
+    }
+}
+
+#[post("/admin/toggle-adult")]
+async fn admin_toggle_adult(
     request: HttpRequest,
     req_body: web::Form<ToggleVisibilityPayload>,
     data: Data<AppState>,
@@ -1122,514 +2619,4270 @@ pub async fn admin_delete_world(
         return response;
     }
 
-    match delete_app(req_body.app_id, &data).await {
-        Ok(_) => {
-            let template_path = get_template_path(&data, "admin");
-            match get_all_apps(&data).await {
-                Ok(apps) => {
-                    let mut ctx = tera::Context::new();
-                    ctx.insert("apps", &apps);
-                    match data.tera.render(&template_path, &ctx) {
-                        Ok(html) => HttpResponse::Ok().body(html),
-                        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
-                    }
+    if let Err(e) = toggle_app_adult(req_body.app_id, &data).await {
+        return e.error_response();
+    }
+    let app = match get_app_by_id(req_body.app_id, &data).await {
+        Ok(app) => app,
+        Err(e) => return e.error_response(),
+    };
+    // Only the relay that owns a world can federate changes to it
+    if app.is_local() {
+        match get_system_user(&data).await {
+            Ok(system_user) => {
+                if let Err(e) = broadcast_app_update(&data, &system_user, &app.ap_id).await {
+                    log_error!("Error federating adult flag change: {}", e);
                 }
-                Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
             }
+            Err(e) => log_error!("Error fetching system user: {}", e),
         }
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
+
+    // Back to the full admin page, which also lists open reports
+    HttpResponse::Found()
+        .append_header(("Location", "/admin"))
+        .finish()
 }
 
-// ============================================================================
-// Owner Verification and Editing Endpoints
-// ============================================================================
+#[derive(Deserialize)]
+pub struct TagsPayload {
+    app_ids: Vec<i32>,
+    tag: String,
+}
 
-#[derive(Serialize, Deserialize)]
-struct OwnerClaims {
-    app_id: i32,
-    slug: String,
+#[derive(Deserialize)]
+pub struct RenameTagPayload {
+    from: String,
+    to: String,
 }
 
-/// Validate owner token from cookie
-async fn validate_owner_token(
-    request: &HttpRequest,
-    data: &Data<AppState>,
-    expected_app_id: i32,
-) -> Result<OwnerClaims, HttpResponse> {
-    let cookie = request.cookie("relay-owner-token");
-    let token = match cookie {
-        Some(c) => c.value().to_string(),
-        None => return Err(HttpResponse::Unauthorized().body("No owner token")),
-    };
+/// A tag as given in a bulk edit: trimmed, and free of the commas tags are stored between
+fn parse_tag(tag: &str) -> Result<String, HttpResponse> {
+    let tag = tag.trim();
+    if tag.is_empty() || tag.contains(',') {
+        return Err(
+            HttpResponse::BadRequest().body("Tags must be non-empty and contain no commas")
+        );
+    }
+    Ok(tag.to_string())
+}
 
-    let user = match get_relay_by_id(0, data).await {
-        Ok(u) => u,
-        Err(_) => {
-            return Err(HttpResponse::InternalServerError().body("Failed to get system user"))
+/// Applies a bulk tag edit and federates an Update for each changed world registered here
+async fn apply_tag_edit(
+    request: HttpRequest,
+    data: Data<AppState>,
+    app_ids: Option<&[i32]>,
+    edit: TagEdit,
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
+    }
+    let changed = match edit_app_tags(&data, app_ids, &edit).await {
+        Ok(changed) => changed,
+        Err(e) => {
+            log_error!("Error editing tags ({:?}): {}", edit, e);
+            return HttpResponse::InternalServerError().body("Failed to update tags");
         }
     };
-
-    let private_key = match user.private_key_pem() {
-        Some(pk) => pk,
-        None => {
-            return Err(HttpResponse::InternalServerError().body("System user has no private key"))
+    if changed.iter().any(DbApp::is_local) {
+        match get_system_user(&data).await {
+            Ok(system_user) => {
+                for app in changed.iter().filter(|app| app.is_local()) {
+                    if let Err(e) = broadcast_app_update(&data, &system_user, &app.ap_id).await {
+                        log_error!("Error federating tag change for app {}: {}", app.id, e);
+                    }
+                }
+            }
+            Err(e) => log_error!("Error fetching system user: {}", e),
         }
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "affected": changed.len() }))
+}
+
+/// Adds a tag to each listed world that lacks it
+#[post("/admin/tags/add")]
+async fn admin_add_tag(
+    request: HttpRequest,
+    req_body: web::Json<TagsPayload>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    let tag = match parse_tag(&req_body.tag) {
+        Ok(tag) => tag,
+        Err(response) => return response,
     };
+    apply_tag_edit(request, data, Some(&req_body.app_ids), TagEdit::Add(tag)).await
+}
 
-    let keypair = match RS256KeyPair::from_pem(&private_key) {
-        Ok(kp) => kp,
-        Err(_) => return Err(HttpResponse::InternalServerError().body("Invalid system keypair")),
+/// Removes a tag from each listed world that has it
+#[post("/admin/tags/remove")]
+async fn admin_remove_tag(
+    request: HttpRequest,
+    req_body: web::Json<TagsPayload>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    let tag = match parse_tag(&req_body.tag) {
+        Ok(tag) => tag,
+        Err(response) => return response,
     };
+    apply_tag_edit(request, data, Some(&req_body.app_ids), TagEdit::Remove(tag)).await
+}
 
-    let public_key = keypair.public_key();
-    let claims = match public_key.verify_token::<OwnerClaims>(&token, None) {
-        Ok(c) => c,
-        Err(_) => return Err(HttpResponse::Unauthorized().body("Invalid or expired token")),
+/// Renames a tag on every world. Worlds that already carry the new tag keep one copy.
+#[post("/admin/tags/rename")]
+async fn admin_rename_tag(
+    request: HttpRequest,
+    req_body: web::Json<RenameTagPayload>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    let (from, to) = match (parse_tag(&req_body.from), parse_tag(&req_body.to)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(response), _) | (_, Err(response)) => return response,
     };
+    apply_tag_edit(request, data, None, TagEdit::Rename { from, to }).await
+}
 
-    // Verify the token is for the expected app
-    if claims.custom.app_id != expected_app_id {
-        return Err(HttpResponse::Unauthorized().body("Token not valid for this world"));
+#[post("/admin/delete-world")]
+pub async fn admin_delete_world(
+    request: HttpRequest,
+    req_body: web::Form<ToggleVisibilityPayload>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
     }
 
-    Ok(claims.custom)
+    match delete_app(req_body.app_id, &data).await {
+        Ok(_) => {
+            let template_path = get_template_path(&data, &request, "admin");
+            match get_all_apps(&data).await {
+                Ok(apps) => {
+                    let mut ctx = tera::Context::new();
+                    ctx.insert("apps", &apps);
+                    match data.tera.render(&template_path, &ctx) {
+                        Ok(html) => HttpResponse::Ok().body(html),
+                        Err(e) => admin_template_fail(e),
+                    }
+                }
+                Err(e) => e.error_response(),
+            }
+        }
+        Err(e) => e.error_response(),
+    }
 }
 
-/// Create owner token (JWT)
-async fn create_owner_token(
-    data: &Data<AppState>,
-    app_id: i32,
-    slug: &str,
-) -> Result<String, HttpResponse> {
-    let user = match get_relay_by_id(0, data).await {
-        Ok(u) => u,
-        Err(_) => {
-            return Err(HttpResponse::InternalServerError().body("Failed to get system user"))
-        }
-    };
+#[derive(Deserialize)]
+pub struct ConsistencyCheckQuery {
+    #[serde(default)]
+    repair: bool,
+}
 
-    let private_key = match user.private_key_pem() {
-        Some(pk) => pk,
-        None => {
-            return Err(HttpResponse::InternalServerError().body("System user has no private key"))
-        }
-    };
+#[derive(Serialize)]
+struct ConsistencyReport {
+    apps_without_create: Vec<serde_json::Value>,
+    orphaned_activities: Vec<serde_json::Value>,
+    orphaned_follows: Vec<serde_json::Value>,
+    repaired: bool,
+}
 
-    let keypair = match RS256KeyPair::from_pem(&private_key) {
-        Ok(kp) => kp,
-        Err(_) => return Err(HttpResponse::InternalServerError().body("Invalid system keypair")),
-    };
+#[derive(Serialize)]
+struct FollowerVerification {
+    followers: i64,
+    deliverable: i64,
+    dangling: Vec<i32>,
+    removed: u64,
+}
 
-    let claims = OwnerClaims {
-        app_id,
-        slug: slug.to_string(),
-    };
+#[derive(Serialize)]
+struct ReachHost {
+    host: String,
+    followers: usize,
+    inboxes: usize,
+}
 
-    // Token valid for 7 days
-    let duration = jwt_simple::prelude::Duration::from_days(7);
-    let jwt_claims = Claims::with_custom_claims(claims, duration);
+#[derive(Serialize)]
+struct ReachReport {
+    mutual_only: bool,
+    followers: usize,
+    inboxes: usize,
+    hosts: Vec<ReachHost>,
+}
 
-    match keypair.sign(jwt_claims) {
-        Ok(token) => Ok(token),
-        Err(_) => Err(HttpResponse::InternalServerError().body("Failed to sign token")),
+/// How many followers and distinct inboxes a broadcast would reach right now, by host
+#[get("/admin/reach")]
+async fn admin_reach(request: HttpRequest, data: Data<AppState>) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
     }
+
+    let recipients = match get_broadcast_recipients(&data).await {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            log_error!("Error fetching followers: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch followers");
+        }
+    };
+    // Grouped by the inbox a broadcast actually posts to, as in get_broadcast_inboxes
+    let mut by_host: HashMap<String, (usize, HashSet<Url>)> = HashMap::new();
+    for relay in &recipients {
+        let inbox = relay.shared_inbox_or_inbox();
+        let host = inbox.host_str().unwrap_or_default().to_string();
+        let entry = by_host.entry(host).or_default();
+        entry.0 += 1;
+        entry.1.insert(inbox);
+    }
+    let mut hosts: Vec<ReachHost> = by_host
+        .into_iter()
+        .map(|(host, (followers, inboxes))| ReachHost {
+            host,
+            followers,
+            inboxes: inboxes.len(),
+        })
+        .collect();
+    hosts.sort_by(|a, b| b.inboxes.cmp(&a.inboxes).then_with(|| a.host.cmp(&b.host)));
+
+    HttpResponse::Ok().json(ReachReport {
+        mutual_only: data.features().mutual_only,
+        followers: recipients.len(),
+        inboxes: hosts.iter().map(|host| host.inboxes).sum(),
+        hosts,
+    })
 }
 
 #[derive(Deserialize)]
-pub struct EditWorldQuery {
-    verified: Option<bool>,
+pub struct AdminRelayQuery {
+    ap_id: String,
 }
 
-/// Show edit page - either verification instructions or edit form
-#[get("/world/{slug}/edit")]
-pub async fn get_world_edit(
+/// What we stored about a peer relay, without its private key
+#[derive(Serialize)]
+struct StoredRelay {
+    id: i32,
+    ap_id: String,
+    name: String,
+    inbox: String,
+    outbox: String,
+    public_key_pem: String,
+    local: bool,
+    last_refreshed_at: DateTime<Utc>,
+    last_activity_at: Option<DateTime<Utc>>,
+    /// Whether the relay follows us
+    follower: bool,
+    /// Whether we follow the relay
+    following: bool,
+}
+
+/// Stored metadata for one relay, for debugging federation with a peer
+#[get("/admin/relay")]
+async fn admin_relay(
     request: HttpRequest,
+    query: web::Query<AdminRelayQuery>,
     data: Data<AppState>,
-    path: web::Path<String>,
-    query: web::Query<EditWorldQuery>,
-) -> impl Responder {
-    let template_path = get_template_path(&data, "edit");
-    let error_path = get_template_path(&data, "error");
-    let slug = path.into_inner();
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
+    }
 
-    // Get app by slug or ID
-    let app = if let Ok(id) = slug.parse::<i32>() {
-        get_app_by_id(id + 1, &data).await.ok()
-    } else {
-        match get_app_by_slug(&data, &slug).await {
-            Ok(Some(app)) => Some(app),
-            _ => None,
+    let relay = match get_relay_by_ap_id(query.ap_id.clone(), &data).await {
+        Ok(Some(relay)) => relay,
+        Ok(None) => return HttpResponse::NotFound().body("No relay stored with that AP id"),
+        Err(e) => {
+            log_error!("Error fetching relay {}: {}", query.ap_id, e);
+            return HttpResponse::InternalServerError().body("Failed to fetch relay");
         }
     };
-
-    let app = match app {
-        Some(a) => a,
-        None => {
-            return match data.tera.render(&error_path, &Context::new()) {
-                Ok(html) => web::Html::new(html),
-                Err(e) => template_fail_screen(e),
-            }
+    let id = match get_relay_follower_id_by_ap_id(&data, &query.ap_id).await {
+        Ok(id) => id,
+        Err(e) => {
+            log_error!("Error fetching relay id for {}: {}", query.ap_id, e);
+            return HttpResponse::InternalServerError().body("Failed to fetch relay");
         }
     };
+    let (follower, following) =
+        match tokio::try_join!(is_relay_follower(&data, id), is_following_relay(&data, id)) {
+            Ok(flags) => flags,
+            Err(e) => {
+                log_error!("Error fetching follow state for {}: {}", query.ap_id, e);
+                return HttpResponse::InternalServerError().body("Failed to fetch follow state");
+            }
+        };
+    HttpResponse::Ok().json(StoredRelay {
+        id,
+        ap_id: relay.ap_id.inner().to_string(),
+        public_key_pem: relay.public_key_pem().to_string(),
+        name: relay.name,
+        inbox: relay.inbox.to_string(),
+        outbox: relay.outbox.to_string(),
+        local: relay.local,
+        last_refreshed_at: relay.last_refreshed_at,
+        last_activity_at: relay.last_activity_at,
+        follower,
+        following,
+    })
+}
 
-    // Check if user has valid owner token
-    let is_verified = validate_owner_token(&request, &data, app.id).await.is_ok();
+/// Federation timeline of one app
+#[derive(Serialize)]
+struct AppHistory {
+    app_id: i32,
+    ap_id: String,
+    activities: Vec<ActivityLogEntry>,
+}
 
-    let mut ctx = tera::Context::new();
-    ctx.insert("name", &app.name);
-    ctx.insert("description", &app.description);
-    ctx.insert("url", &app.url);
-    ctx.insert("image", &app.image);
-    ctx.insert("tags", &app.tags);
-    ctx.insert("adult", &app.adult);
-    ctx.insert("slug", &app.slug);
-    ctx.insert("app_id", &app.id);
-    ctx.insert("is_verified", &is_verified);
-    ctx.insert("verification_code", &app.verification_code);
-    ctx.insert("just_verified", &query.verified.unwrap_or(false));
+/// Who created and updated an app, from the activities stored for its AP id
+#[get("/admin/app/{id}/history")]
+async fn admin_app_history(
+    request: HttpRequest,
+    path: web::Path<i32>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
+    }
 
-    match data.tera.render(&template_path, &ctx) {
-        Ok(html) => web::Html::new(html),
-        Err(e) => template_fail_screen(e),
+    let app = match get_app_by_id(path.into_inner(), &data).await {
+        Ok(app) => app,
+        Err(_) => return HttpResponse::NotFound().body("App not found"),
+    };
+    let ap_id = app.ap_id.inner().to_string();
+    match get_activities_for_object(&data, &ap_id).await {
+        Ok(activities) => HttpResponse::Ok().json(AppHistory {
+            app_id: app.id,
+            ap_id,
+            activities,
+        }),
+        Err(e) => {
+            log_error!("Error fetching history of app {}: {}", app.id, e);
+            HttpResponse::InternalServerError().body("Failed to fetch app history")
+        }
     }
 }
 
-/// Request verification code for a world
-#[post("/world/{slug}/request-verification")]
-pub async fn request_world_verification(
+/// Checks that every follower of the relay resolves to a relay row, since fan-out
+/// skips the ones that don't. With `?repair=true` the dangling rows are deleted.
+#[post("/admin/verify-followers")]
+async fn admin_verify_followers(
+    request: HttpRequest,
+    query: web::Query<ConsistencyCheckQuery>,
     data: Data<AppState>,
-    path: web::Path<String>,
 ) -> HttpResponse {
-    let slug = path.into_inner();
-
-    // Get app by slug or ID
-    let app = if let Ok(id) = slug.parse::<i32>() {
-        get_app_by_id(id + 1, &data).await.ok()
-    } else {
-        match get_app_by_slug(&data, &slug).await {
-            Ok(Some(app)) => Some(app),
-            _ => None,
-        }
-    };
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
+    }
 
-    let app = match app {
-        Some(a) => a,
-        None => return HttpResponse::NotFound().body("World not found"),
-    };
+    let (followers, dangling) =
+        match tokio::try_join!(get_followers_count(&data), get_dangling_followers(&data)) {
+            Ok(results) => results,
+            Err(e) => {
+                log_error!("Error verifying followers: {}", e);
+                return e.error_response();
+            }
+        };
 
-    // Generate verification code if not already set
-    let code = match &app.verification_code {
-        Some(c) if !c.is_empty() => c.clone(),
-        _ => {
-            let new_code = generate_verification_code();
-            if let Err(e) = set_verification_code(&data, app.id, &new_code).await {
-                eprintln!("Error setting verification code: {}", e);
-                return HttpResponse::InternalServerError().body("Failed to set verification code");
+    let removed = if query.repair && !dangling.is_empty() {
+        match delete_dangling_followers(&data).await {
+            Ok(removed) => removed,
+            Err(e) => {
+                log_error!("Error deleting dangling followers: {}", e);
+                return e.error_response();
             }
-            new_code
         }
-    };
-
-    HttpResponse::Ok().json(serde_json::json!({
-        "code": code,
-        "url": app.url,
-        "instruction": format!(
-            "Add this meta tag to the <head> of your site at {}:\n<meta name=\"zesty-verify\" content=\"{}\">",
-            app.url, code
-        )
-    }))
-}
-
-/// Verify world ownership by checking meta tag
-#[post("/world/{slug}/verify")]
-pub async fn verify_world_ownership(data: Data<AppState>, path: web::Path<String>) -> HttpResponse {
-    let slug = path.into_inner();
-
-    // Get app by slug or ID
-    let app = if let Ok(id) = slug.parse::<i32>() {
-        get_app_by_id(id + 1, &data).await.ok()
     } else {
-        match get_app_by_slug(&data, &slug).await {
-            Ok(Some(app)) => Some(app),
-            _ => None,
-        }
-    };
-
-    let app = match app {
-        Some(a) => a,
-        None => return HttpResponse::NotFound().body("World not found"),
+        0
     };
 
-    let verification_code = match &app.verification_code {
-        Some(c) if !c.is_empty() => c.clone(),
-        _ => return HttpResponse::BadRequest().body("No verification code set. Request one first."),
-    };
+    HttpResponse::Ok().json(FollowerVerification {
+        followers,
+        deliverable: followers - dangling.len() as i64,
+        dangling,
+        removed,
+    })
+}
 
-    // Fetch the world's URL and check for the meta tag
-    let url = normalize_app_url(app.url.clone());
-    let response = match reqwest::get(&url).await {
-        Ok(r) => r,
-        Err(e) => {
-            eprintln!("Error fetching URL {}: {}", url, e);
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Could not fetch your site. Make sure it's accessible.",
-                "details": e.to_string()
-            }));
-        }
-    };
+/// Reports apps missing their Create activity, Create/Update activities whose app
+/// is gone and follow rows pointing at missing relays. With `?repair=true`,
+/// missing Create activities are backfilled and the orphans are deleted.
+#[post("/admin/consistency-check")]
+async fn admin_consistency_check(
+    request: HttpRequest,
+    query: web::Query<ConsistencyCheckQuery>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
+    }
 
-    let html = match response.text().await {
-        Ok(h) => h,
+    let (apps_without_create, orphaned_activities, orphaned_follows) = match tokio::try_join!(
+        get_apps_without_create(&data),
+        get_orphaned_activities(&data),
+        get_orphaned_follows(&data)
+    ) {
+        Ok(results) => results,
         Err(e) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Could not read response from your site.",
-                "details": e.to_string()
-            }))
+            log_error!("Error running consistency check: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to run consistency check");
         }
     };
 
-    // Parse HTML and look for zesty-verify meta tag
-    let document = scraper::Html::parse_document(&html);
-    let selector = match scraper::Selector::parse("meta[name=\"zesty-verify\"]") {
-        Ok(s) => s,
-        Err(_) => return HttpResponse::InternalServerError().body("Selector parse error"),
-    };
-
-    let found_code = document
-        .select(&selector)
-        .next()
-        .and_then(|el| el.value().attr("content"))
-        .map(|s| s.to_string());
-
-    match found_code {
-        Some(code) if code == verification_code => {
-            // Verification successful! Mark as verified and create token
-            if let Err(e) = mark_app_verified(&data, app.id).await {
-                eprintln!("Error marking app verified: {}", e);
-                return HttpResponse::InternalServerError().body("Failed to save verification");
+    if query.repair {
+        let system_user = match get_system_user(&data).await {
+            Ok(user) => user,
+            Err(e) => {
+                log_error!("Error fetching system user: {}", e);
+                return HttpResponse::InternalServerError().body("Relay not initialized");
+            }
+        };
+        let actor = system_user.ap_id.inner().as_str();
+        for (_, app_ap_id) in apps_without_create.iter() {
+            if let Err(e) =
+                create_local_activity(&data, actor, app_ap_id, ActivityKind::Create).await
+            {
+                log_error!("Error backfilling Create activity: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to backfill activities");
             }
-
-            let app_slug = app.slug.clone().unwrap_or_else(|| app.id.to_string());
-            let token = match create_owner_token(&data, app.id, &app_slug).await {
-                Ok(t) => t,
-                Err(r) => return r,
-            };
-
-            HttpResponse::Ok()
-                .cookie(
-                    Cookie::build("relay-owner-token", token)
-                        .path("/")
-                        .http_only(true)
-                        .max_age(time::Duration::days(7))
-                        .finish(),
-                )
-                .json(serde_json::json!({
-                    "success": true,
-                    "message": "Verification successful! You can now edit this world."
-                }))
         }
-        Some(code) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Verification code mismatch",
-            "found": code,
-            "expected": verification_code
-        })),
-        None => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Meta tag not found",
-            "instruction": format!(
-                "Add this to the <head> of {}:\n<meta name=\"zesty-verify\" content=\"{}\">",
-                url, verification_code
-            )
-        })),
+        let activity_ids: Vec<i32> = orphaned_activities.iter().map(|(id, _, _)| *id).collect();
+        if let Err(e) = prune_orphans(&data, &activity_ids).await {
+            log_error!("Error pruning orphans: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to prune orphans");
+        }
     }
+
+    HttpResponse::Ok().json(ConsistencyReport {
+        apps_without_create: apps_without_create
+            .into_iter()
+            .map(|(id, ap_id)| serde_json::json!({ "id": id, "activitypub_id": ap_id }))
+            .collect(),
+        orphaned_activities: orphaned_activities
+            .into_iter()
+            .map(|(id, ap_id, obj)| {
+                serde_json::json!({ "id": id, "activitypub_id": ap_id, "object": obj })
+            })
+            .collect(),
+        orphaned_follows: orphaned_follows
+            .into_iter()
+            .map(|(relay_id, other_id)| {
+                serde_json::json!({ "relay_id": relay_id, "other_relay_id": other_id })
+            })
+            .collect(),
+        repaired: query.repair,
+    })
 }
 
 #[derive(Deserialize)]
-pub struct UpdateWorldPayload {
+pub struct AdminEditPayload {
     name: String,
     description: String,
-    image: Option<String>,
-    tags: Option<String>,
-    adult: Option<bool>,
+    image: String,
+    tags: String,
+    // Checkboxes are only submitted when checked
+    adult: Option<String>,
 }
 
-/// Update world details (requires owner token)
-#[post("/world/{slug}/update")]
-pub async fn update_world(
+/// Show the admin edit form for a single app
+#[get("/admin/edit/{id}")]
+async fn admin_edit_page(
     request: HttpRequest,
+    path: web::Path<i32>,
     data: Data<AppState>,
-    path: web::Path<String>,
-    payload: web::Json<UpdateWorldPayload>,
 ) -> HttpResponse {
-    let slug = path.into_inner();
-
-    // Get app by slug or ID
-    let app = if let Ok(id) = slug.parse::<i32>() {
-        get_app_by_id(id + 1, &data).await.ok()
-    } else {
-        match get_app_by_slug(&data, &slug).await {
-            Ok(Some(app)) => Some(app),
-            _ => None,
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        if request.cookie("relay-admin-token").is_none() {
+            return HttpResponse::TemporaryRedirect()
+                .append_header(("Location", "/login"))
+                .finish();
         }
-    };
+        return response;
+    }
 
-    let app = match app {
-        Some(a) => a,
-        None => return HttpResponse::NotFound().body("World not found"),
+    let app = match get_app_by_id(path.into_inner(), &data).await {
+        Ok(app) => app,
+        Err(_) => return HttpResponse::NotFound().body("World not found"),
     };
 
-    // Validate owner token
-    if let Err(response) = validate_owner_token(&request, &data, app.id).await {
+    let template_path = get_template_path(&data, &request, "admin_edit");
+    let mut ctx = tera::Context::new();
+    ctx.insert("app", &app);
+    match data.tera.render(&template_path, &ctx) {
+        Ok(html) => HttpResponse::Ok().body(html),
+        Err(e) => admin_template_fail(e),
+    }
+}
+
+/// Apply an admin edit to an app and federate the change
+#[post("/admin/edit/{id}")]
+async fn admin_edit_world(
+    request: HttpRequest,
+    path: web::Path<i32>,
+    req_body: web::Form<AdminEditPayload>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
         return response;
     }
 
-    // Update the app details
-    let image = payload.image.clone().unwrap_or_else(|| app.image.clone());
-    let tags = payload.tags.clone().unwrap_or_else(|| app.tags.clone());
-    let adult = payload.adult.unwrap_or(app.adult);
+    let app = match get_app_by_id(path.into_inner(), &data).await {
+        Ok(app) => app,
+        Err(_) => return HttpResponse::NotFound().body("World not found"),
+    };
+    let system_user = match get_system_user(&data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Error fetching system user: {}", e);
+            return HttpResponse::InternalServerError().body("Relay not initialized");
+        }
+    };
 
-    if let Err(e) =
-        update_app_details(&data, app.id, &payload.name, &payload.description, &image, &tags, adult)
-            .await
+    let image = if req_body.image.trim().is_empty() {
+        NO_IMAGE.to_string()
+    } else {
+        req_body.image.trim().to_string()
+    };
+    if let Err(e) = update_app(
+        &data,
+        app.ap_id.inner().to_string(),
+        req_body.name.trim().to_string(),
+        req_body.description.trim().to_string(),
+        app.active,
+        image,
+        req_body.adult.is_some(),
+        req_body.tags.trim().to_string(),
+        app.protocol.clone(),
+        SOURCE_ADMIN,
+    )
+    .await
     {
-        eprintln!("Error updating app: {}", e);
+        log_error!("Error updating app: {}", e);
         return HttpResponse::InternalServerError().body("Failed to update world");
     }
+    // Peers only take Updates from the relay that owns a world, so edits to worlds
+    // from other relays stay local, as in admin_toggle_adult
+    if app.is_local() {
+        if let Err(e) = broadcast_app_update(&data, &system_user, &app.ap_id).await {
+            log_error!("Error creating activity: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to federate update");
+        }
+    }
 
-    HttpResponse::Ok().json(serde_json::json!({
-        "success": true,
-        "message": "World updated successfully"
-    }))
+    HttpResponse::Found()
+        .append_header(("Location", "/admin"))
+        .finish()
 }
 
-fn get_template_path(data: &Data<AppState>, page: &str) -> String {
-    if *data.is_custom_page.get(page).unwrap() {
-        format!("{}.html", page)
-    } else {
-        format!("{}.default.html", page)
-    }
+#[derive(Deserialize)]
+pub struct FeatureTogglePayload {
+    name: String,
+    enabled: bool,
 }
 
-fn create_local_image(ap_id: &str, protocol: &str, relay_domain: &str, app_image: &str) -> String {
-    // Get app ID by splitting off from Activitypub ID
-    let count = ap_id.split("/").last().unwrap();
-    // Construct filepath to images folder
-    let filepath = format!("images/{}.png", count);
-    // Construct external URL
-    let image_url = format!("{}{}/{}", protocol, relay_domain, filepath);
-    if std::fs::exists(&filepath).unwrap() {
-        // Image already exists, return image URL
-        return image_url;
-    }
-    let dataurl = match DataUrl::parse(app_image) {
-        Ok(dataurl) => dataurl,
-        Err(e) => {
-            eprintln!("Error parsing image data: {:?}", e);
-            return String::new();
+/// Show the current feature flags and which can be toggled at runtime
+#[get("/admin/features")]
+async fn admin_features_page(request: HttpRequest, data: Data<AppState>) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        if request.cookie("relay-admin-token").is_none() {
+            return HttpResponse::TemporaryRedirect()
+                .append_header(("Location", "/login"))
+                .finish();
         }
-    };
-    let _ = std::fs::write(&filepath, dataurl.get_data());
-    image_url
+        return response;
+    }
+    render_admin_features(&request, &data)
 }
 
-fn get_latest_value<T: PartialEq>(original: T, incoming: T) -> T {
-    if original != incoming {
-        incoming
-    } else {
-        original
+/// Toggle a runtime-safe feature flag without restarting the relay
+#[post("/admin/features")]
+async fn admin_set_feature(
+    request: HttpRequest,
+    req_body: web::Form<FeatureTogglePayload>,
+    data: Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        return response;
     }
+    if !RUNTIME_FEATURES.contains(&req_body.name.as_str()) {
+        return HttpResponse::BadRequest().body("Unknown or non-runtime feature");
+    }
+    if let Err(e) = set_feature(&req_body.name, req_body.enabled, &data).await {
+        log_error!("Error saving feature flag: {}", e);
+        return HttpResponse::InternalServerError().body("Failed to save feature flag");
+    }
+    match data.features.write() {
+        Ok(mut features) => features.set(&req_body.name, req_body.enabled),
+        Err(poisoned) => poisoned.into_inner().set(&req_body.name, req_body.enabled),
+    };
+    render_admin_features(&request, &data)
 }
 
-fn prune_old_sessions(data: &Data<AppState>) {
-    let mut sessions = match data.sessions.write() {
-        Ok(guard) => guard,
-        Err(poisoned) => {
-            eprintln!("Warning: sessions lock was poisoned during pruning. Attempting recovery...");
-            poisoned.into_inner()
+fn render_admin_features(request: &HttpRequest, data: &Data<AppState>) -> HttpResponse {
+    let template_path = get_template_path(data, request, "admin_features");
+    let features = data.features();
+    let mut ctx = tera::Context::new();
+    ctx.insert("features", &features);
+    ctx.insert("runtime_features", &RUNTIME_FEATURES);
+    match data.tera.render(&template_path, &ctx) {
+        Ok(html) => HttpResponse::Ok().body(html),
+        Err(e) => admin_template_fail(e),
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AdminActivitiesQuery {
+    kind: Option<String>,
+    actor_host: Option<String>,
+    /// First day to include, `YYYY-MM-DD` (UTC)
+    since: Option<String>,
+    /// Last day to include, `YYYY-MM-DD` (UTC)
+    until: Option<String>,
+}
+
+/// Federation activity log, for tracing why an app was created or updated
+#[get("/admin/activities")]
+async fn admin_activities(
+    request: HttpRequest,
+    query: web::Query<AdminActivitiesQuery>,
+    pagination: Pagination,
+    data: Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = validate_admin_token(&request, &data).await {
+        if request.cookie("relay-admin-token").is_none() {
+            return HttpResponse::TemporaryRedirect()
+                .append_header(("Location", "/login"))
+                .finish();
         }
+        return response;
+    }
+
+    // Blank form fields mean "no filter"
+    let non_empty = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let parse_day = |value: Option<String>, name: &str| {
+        value
+            .map(|value| {
+                chrono::NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                    .map(|day| day.and_hms_opt(0, 0, 0).unwrap().and_utc())
+                    .map_err(|_| format!("{} must be a date like 2026-01-31", name))
+            })
+            .transpose()
+    };
+    let kind = match non_empty(&query.kind).map(|kind| kind.parse::<ActivityKind>()).transpose() {
+        Ok(kind) => kind,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    let since = match parse_day(non_empty(&query.since), "since") {
+        Ok(since) => since,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    // The until day is inclusive, so stop at the start of the next one
+    let until = match parse_day(non_empty(&query.until), "until") {
+        Ok(until) => until.map(|until| until + chrono::Duration::days(1)),
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let filter = ActivityFilter {
+        kind,
+        actor_host: non_empty(&query.actor_host).map(|host| host.to_lowercase()),
+        since,
+        until,
     };
-    // Iterate through all sessions and remove any that are older than 5 seconds
-    sessions.values_mut().for_each(|url_sessions| {
-        url_sessions.retain(|session| {
-            (time::OffsetDateTime::now_utc().unix_timestamp() * 1000) - session.timestamp < 5000
-        })
-    });
-}
 
-fn normalize_app_url(url: String) -> String {
-    if !url.starts_with("https") && !url.starts_with("http") {
-        let mut adjusted_url = String::new();
-        adjusted_url.push_str("https://");
-        adjusted_url.push_str(&url);
-        adjusted_url
-    } else {
-        url
+    let (activities, total) =
+        match list_activities(&data, &filter, pagination.per_page, pagination.offset()).await {
+            Ok(result) => result,
+            Err(e) => {
+                log_error!("Error fetching activities: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to fetch activities");
+            }
+        };
+    // Carry the filters over to the page links
+    let mut filter_query = url::form_urlencoded::Serializer::new(String::new());
+    for (name, value) in [
+        ("kind", &query.kind),
+        ("actor_host", &query.actor_host),
+        ("since", &query.since),
+        ("until", &query.until),
+    ] {
+        if let Some(value) = non_empty(value) {
+            filter_query.append_pair(name, &value);
+        }
     }
-}
+    filter_query.append_pair("per_page", &pagination.per_page.to_string());
 
-/// Extracts base URL without query parameters (scheme + host + path)
-fn get_base_url(url: &str) -> Option<String> {
-    let normalized = normalize_app_url(url.to_string());
-    let parsed = Url::parse(&normalized).ok()?;
-    let mut base = format!("{}://{}", parsed.scheme(), parsed.host_str()?);
-    let path = parsed.path();
-    if path != "/" {
-        base.push_str(path);
+    let template_path = get_template_path(&data, &request, "admin_activities");
+    let mut ctx = tera::Context::new();
+    ctx.insert("activities", &activities);
+    ctx.insert("total", &total);
+    ctx.insert("kinds", &ActivityKind::ALL.map(|kind| kind.as_str()));
+    ctx.insert("filters", &query.into_inner());
+    ctx.insert("filter_query", &filter_query.finish());
+    ctx.insert("pagination", &pagination);
+    ctx.insert("total_pages", &pagination.total_pages(total));
+    match data.tera.render(&template_path, &ctx) {
+        Ok(html) => HttpResponse::Ok().body(html),
+        Err(e) => admin_template_fail(e),
     }
-    Some(base)
 }
 
-/// Extracts just the domain (host) from a URL
-fn get_domain(url: &str) -> Option<String> {
-    let normalized = normalize_app_url(url.to_string());
-    let parsed = Url::parse(&normalized).ok()?;
-    parsed.host_str().map(|h| h.to_string())
-}
+// ============================================================================
+// Owner Verification and Editing Endpoints
+// ============================================================================
 
-/// Generates a URL-friendly slug from a name
-fn generate_slug(name: &str) -> String {
-    let slug_text = slug::slugify(name);
-    // Ensure slug isn't empty
-    if slug_text.is_empty() {
-        "world".to_string()
-    } else {
-        slug_text
-    }
+#[derive(Serialize, Deserialize)]
+struct OwnerClaims {
+    app_id: i32,
+    slug: String,
 }
 
-/// Generates a unique slug, appending numbers if needed
-async fn generate_unique_slug(data: &Data<AppState>, name: &str) -> String {
-    let base_slug = generate_slug(name);
+/// Validate owner token from cookie
+async fn validate_owner_token(
+    request: &HttpRequest,
+    data: &Data<AppState>,
+    expected_app_id: i32,
+) -> Result<OwnerClaims, HttpResponse> {
+    let cookie = request.cookie("relay-owner-token");
+    let token = match cookie {
+        Some(c) => c.value().to_string(),
+        None => return Err(HttpResponse::Unauthorized().body("No owner token")),
+    };
 
-    // Check if base slug is available
-    if let Ok(false) = slug_exists(data, &base_slug).await {
-        return base_slug;
+    let user = match get_relay_by_id(0, data).await {
+        Ok(u) => u,
+        Err(_) => {
+            return Err(HttpResponse::InternalServerError().body("Failed to get system user"))
+        }
+    };
+
+    let private_key = match user.private_key_pem() {
+        Some(pk) => pk,
+        None => {
+            return Err(HttpResponse::InternalServerError().body("System user has no private key"))
+        }
+    };
+
+    let keypair = match RS256KeyPair::from_pem(&private_key) {
+        Ok(kp) => kp,
+        Err(_) => return Err(HttpResponse::InternalServerError().body("Invalid system keypair")),
+    };
+
+    let public_key = keypair.public_key();
+    let claims = match public_key.verify_token::<OwnerClaims>(&token, None) {
+        Ok(c) => c,
+        Err(_) => return Err(HttpResponse::Unauthorized().body("Invalid or expired token")),
+    };
+
+    // Verify the token is for the expected app
+    if claims.custom.app_id != expected_app_id {
+        return Err(HttpResponse::Unauthorized().body("Token not valid for this world"));
     }
 
-    // Try appending numbers until we find a unique one
-    for i in 2..1000 {
-        let numbered_slug = format!("{}-{}", base_slug, i);
-        if let Ok(false) = slug_exists(data, &numbered_slug).await {
-            return numbered_slug;
+    Ok(claims.custom)
+}
+
+/// Create owner token (JWT)
+async fn create_owner_token(
+    data: &Data<AppState>,
+    app_id: i32,
+    slug: &str,
+) -> Result<String, HttpResponse> {
+    let user = match get_relay_by_id(0, data).await {
+        Ok(u) => u,
+        Err(_) => {
+            return Err(HttpResponse::InternalServerError().body("Failed to get system user"))
+        }
+    };
+
+    let private_key = match user.private_key_pem() {
+        Some(pk) => pk,
+        None => {
+            return Err(HttpResponse::InternalServerError().body("System user has no private key"))
         }
+    };
+
+    let keypair = match RS256KeyPair::from_pem(&private_key) {
+        Ok(kp) => kp,
+        Err(_) => return Err(HttpResponse::InternalServerError().body("Invalid system keypair")),
+    };
+
+    let claims = OwnerClaims {
+        app_id,
+        slug: slug.to_string(),
+    };
+
+    // Token valid for 7 days
+    let duration = jwt_simple::prelude::Duration::from_days(7);
+    let jwt_claims = Claims::with_custom_claims(claims, duration);
+
+    match keypair.sign(jwt_claims) {
+        Ok(token) => Ok(token),
+        Err(_) => Err(HttpResponse::InternalServerError().body("Failed to sign token")),
     }
+}
 
-    // Fallback: append random suffix
-    format!("{}-{}", base_slug, rand::random::<u32>())
+#[derive(Deserialize)]
+pub struct EditWorldQuery {
+    verified: Option<bool>,
 }
 
-/// Generates a random verification code
-fn generate_verification_code() -> String {
-    let mut rng = rand::thread_rng();
-    let code: String = (0..32)
-        .map(|_| {
-            let idx = rng.gen_range(0..36);
-            if idx < 10 {
-                (b'0' + idx) as char
-            } else {
-                (b'a' + idx - 10) as char
+/// Show edit page - either verification instructions or edit form
+#[get("/world/{slug}/edit")]
+pub async fn get_world_edit(
+    request: HttpRequest,
+    data: Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<EditWorldQuery>,
+) -> impl Responder {
+    let template_path = get_template_path(&data, &request, "edit");
+    let error_path = get_template_path(&data, &request, "error");
+    let slug = path.into_inner();
+
+    let app = find_app_by_id_or_slug(&data, &slug).await;
+
+    let app = match app {
+        Some(a) => a,
+        None => {
+            return match data.tera.render(&error_path, &Context::new()) {
+                Ok(html) => web::Html::new(html),
+                Err(e) => template_fail_screen(e),
             }
-        })
-        .collect();
-    code
+        }
+    };
+
+    // Check if user has valid owner token
+    let is_verified = validate_owner_token(&request, &data, app.id).await.is_ok();
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("name", &app.name);
+    ctx.insert("description", &app.description);
+    ctx.insert("url", &app.url);
+    ctx.insert("image", &app.image);
+    ctx.insert("tags", &app.tags);
+    ctx.insert("adult", &app.adult);
+    ctx.insert("slug", &app.slug);
+    ctx.insert("app_id", &app.id);
+    ctx.insert("is_verified", &is_verified);
+    ctx.insert("verification_code", &app.verification_code);
+    ctx.insert("just_verified", &query.verified.unwrap_or(false));
+
+    match data.tera.render(&template_path, &ctx) {
+        Ok(html) => web::Html::new(html),
+        Err(e) => template_fail_screen(e),
+    }
+}
+
+/// Request verification code for a world
+#[post("/world/{slug}/request-verification")]
+pub async fn request_world_verification(
+    data: Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let slug = path.into_inner();
+
+    let app = find_app_by_id_or_slug(&data, &slug).await;
+
+    let app = match app {
+        Some(a) => a,
+        None => return HttpResponse::NotFound().body("World not found"),
+    };
+
+    // Generate verification code if not already set
+    let code = match &app.verification_code {
+        Some(c) if !c.is_empty() => c.clone(),
+        _ => {
+            let new_code = generate_verification_code();
+            if let Err(e) = set_verification_code(&data, app.id, &new_code).await {
+                log_error!("Error setting verification code: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to set verification code");
+            }
+            new_code
+        }
+    };
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "code": code,
+        "url": app.url,
+        "instruction": format!(
+            "Add this meta tag to the <head> of your site at {}:\n<meta name=\"zesty-verify\" content=\"{}\">",
+            app.url, code
+        )
+    }))
+}
+
+/// Verify world ownership by checking meta tag
+#[post("/world/{slug}/verify")]
+pub async fn verify_world_ownership(data: Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let slug = path.into_inner();
+
+    let app = find_app_by_id_or_slug(&data, &slug).await;
+
+    let app = match app {
+        Some(a) => a,
+        None => return HttpResponse::NotFound().body("World not found"),
+    };
+
+    let verification_code = match &app.verification_code {
+        Some(c) if !c.is_empty() => c.clone(),
+        _ => return HttpResponse::BadRequest().body("No verification code set. Request one first."),
+    };
+
+    // Fetch the world's URL and check for the meta tag
+    let url = normalize_app_url(app.url.clone());
+    if let Ok(parsed) = Url::parse(&url) {
+        if let Err(e) = data.outbound_guard.check(&parsed).await {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Your site's address is not publicly reachable.",
+                "details": e
+            }));
+        }
+    }
+    let response = match data.http_client.get(&url).send().await {
+        Ok(r) => r,
+        Err(e) => {
+            log_error!("Error fetching URL {}: {}", url, e);
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Could not fetch your site. Make sure it's accessible.",
+                "details": e.to_string()
+            }));
+        }
+    };
+
+    let html = match response.text().await {
+        Ok(h) => h,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Could not read response from your site.",
+                "details": e.to_string()
+            }))
+        }
+    };
+
+    // Parse HTML and look for zesty-verify meta tag
+    let document = scraper::Html::parse_document(&html);
+    let selector = match scraper::Selector::parse("meta[name=\"zesty-verify\"]") {
+        Ok(s) => s,
+        Err(_) => return HttpResponse::InternalServerError().body("Selector parse error"),
+    };
+
+    let found_code = document
+        .select(&selector)
+        .next()
+        .and_then(|el| el.value().attr("content"))
+        .map(|s| s.to_string());
+
+    match found_code {
+        Some(code) if code == verification_code => {
+            // Verification successful! Mark as verified and create token
+            if let Err(e) = mark_app_verified(&data, app.id).await {
+                log_error!("Error marking app verified: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to save verification");
+            }
+
+            let app_slug = app.slug.clone().unwrap_or_else(|| app.id.to_string());
+            let token = match create_owner_token(&data, app.id, &app_slug).await {
+                Ok(t) => t,
+                Err(r) => return r,
+            };
+
+            HttpResponse::Ok()
+                .cookie(
+                    Cookie::build("relay-owner-token", token)
+                        .path("/")
+                        .http_only(true)
+                        .max_age(time::Duration::days(7))
+                        .finish(),
+                )
+                .json(serde_json::json!({
+                    "success": true,
+                    "message": "Verification successful! You can now edit this world."
+                }))
+        }
+        Some(code) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Verification code mismatch",
+            "found": code,
+            "expected": verification_code
+        })),
+        None => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Meta tag not found",
+            "instruction": format!(
+                "Add this to the <head> of {}:\n<meta name=\"zesty-verify\" content=\"{}\">",
+                url, verification_code
+            )
+        })),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ClaimPayload {
+    url: String,
+    /// Key returned when the claim was started; completes it, or claims the app again
+    claim_key: Option<String>,
+}
+
+fn hash_claim_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Lets the owner of a site take over its world, typically one that arrived through
+/// federation and so has no local owner. Without a `claim_key` this starts a claim,
+/// answering 202 with a random challenge and a key only the requester gets. Once the
+/// challenge is a line of the site's well-known file (see [`verify_claim_challenge`]),
+/// posting again with the key completes the claim and sets the same owner cookie as
+/// `/world/{slug}/verify`, so the world can be edited through `/world/{slug}/update`.
+/// A claimed world answers 409 to anyone but the holder of its key.
+#[post("/beacon/claim")]
+async fn claim_beacon(
+    request: HttpRequest,
+    data: Data<AppState>,
+    payload: web::Json<ClaimPayload>,
+) -> HttpResponse {
+    let url = canonicalize_beacon_url(&payload.url);
+    let base_url = get_base_url(&url).unwrap_or_else(|| url.clone());
+    let app = match get_app_by_base_url(&data, &base_url).await {
+        Ok(Some(app)) => app,
+        Ok(None) => return HttpResponse::NotFound().body("World not found"),
+        Err(e) => {
+            log_error!("Error fetching app for claim: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to look up world");
+        }
+    };
+
+    let Some(key) = payload.claim_key.as_deref() else {
+        let nonce = generate_verification_code();
+        let key = generate_verification_code();
+        if let Err(e) = create_claim_challenge(&data, app.id, &nonce, &hash_claim_key(&key)).await
+        {
+            log_error!("Error creating claim challenge: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to start claim");
+        }
+        return HttpResponse::Accepted().json(serde_json::json!({
+            "challenge": nonce,
+            "claim_key": key,
+            "instruction": format!(
+                "Add the line {} to {}{}, then post again with this claim_key",
+                nonce, app.url, DOMAIN_VERIFICATION_PATH
+            ),
+        }));
+    };
+
+    let key_hash = hash_claim_key(key);
+    // The holder of the key an app was claimed with can claim it again without a challenge
+    let is_owner = match is_app_claim_key(&data, app.id, &key_hash).await {
+        Ok(is_owner) => is_owner,
+        Err(e) => {
+            log_error!("Error checking claim key: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to look up claim");
+        }
+    };
+    if !is_owner {
+        let nonce = match get_claim_challenge(&data, app.id, &key_hash).await {
+            Ok(Some(nonce)) => nonce,
+            Ok(None) => {
+                return HttpResponse::Forbidden()
+                    .body("Unknown or expired claim_key; post without one to start a new claim")
+            }
+            Err(e) => {
+                log_error!("Error fetching claim challenge: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to look up claim");
+            }
+        };
+        if let Err(reason) = verify_claim_challenge(&data, &app.url, &nonce).await {
+            log_error!("Claim of '{}' rejected: {}", app.url, reason);
+            return HttpResponse::Forbidden().body(format!(
+                "Add the challenge to {} on your site to claim it: {}",
+                DOMAIN_VERIFICATION_PATH, reason
+            ));
+        }
+    }
+
+    let claimed_by = client_ip(&request, &data);
+    match claim_app(&data, app.id, &claimed_by, &key_hash).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Conflict().body("This world has already been claimed"),
+        Err(e) => {
+            log_error!("Error recording claim: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to record claim");
+        }
+    }
+    log_info!("World {} ({}) claimed from {}", app.id, app.url, claimed_by);
+
+    let app_slug = app.slug.clone().unwrap_or_else(|| app.id.to_string());
+    let token = match create_owner_token(&data, app.id, &app_slug).await {
+        Ok(t) => t,
+        Err(r) => return r,
+    };
+    HttpResponse::Ok()
+        .cookie(
+            Cookie::build("relay-owner-token", token)
+                .path("/")
+                .http_only(true)
+                .max_age(time::Duration::days(7))
+                .finish(),
+        )
+        .json(serde_json::json!({
+            "success": true,
+            "app_id": app.id,
+            "slug": app_slug,
+        }))
+}
+
+#[derive(Deserialize)]
+pub struct UpdateWorldPayload {
+    name: String,
+    description: String,
+    image: Option<String>,
+    tags: Option<String>,
+    adult: Option<bool>,
+}
+
+/// Update world details (requires owner token)
+#[post("/world/{slug}/update")]
+pub async fn update_world(
+    request: HttpRequest,
+    data: Data<AppState>,
+    path: web::Path<String>,
+    payload: web::Json<UpdateWorldPayload>,
+) -> HttpResponse {
+    let slug = path.into_inner();
+
+    let app = find_app_by_id_or_slug(&data, &slug).await;
+
+    let app = match app {
+        Some(a) => a,
+        None => return HttpResponse::NotFound().body("World not found"),
+    };
+
+    // Validate owner token
+    if let Err(response) = validate_owner_token(&request, &data, app.id).await {
+        return response;
+    }
+
+    // Update the app details
+    let image = payload.image.clone().unwrap_or_else(|| app.image.clone());
+    let tags = payload.tags.clone().unwrap_or_else(|| app.tags.clone());
+    let adult = payload.adult.unwrap_or(app.adult);
+
+    if let Err(e) =
+        update_app_details(&data, app.id, &payload.name, &payload.description, &image, &tags, adult)
+            .await
+    {
+        log_error!("Error updating app: {}", e);
+        return HttpResponse::InternalServerError().body("Failed to update world");
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "World updated successfully"
+    }))
+}
+
+/// Delete a world (requires owner token). Like an admin delete, a tombstone is kept so
+/// its page and AP id answer 410.
+#[post("/world/{slug}/delete")]
+pub async fn delete_world(
+    request: HttpRequest,
+    data: Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let slug = path.into_inner();
+
+    let app = match find_app_by_id_or_slug(&data, &slug).await {
+        Some(a) => a,
+        None => return HttpResponse::NotFound().body("World not found"),
+    };
+
+    if let Err(response) = validate_owner_token(&request, &data, app.id).await {
+        return response;
+    }
+
+    if let Err(e) = delete_app(app.id, &data).await {
+        log_error!("Error deleting app: {}", e);
+        return HttpResponse::InternalServerError().body("Failed to delete world");
+    }
+    log_info!("World {} ({}) deleted by its owner", app.id, app.url);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "World deleted successfully"
+    }))
+}
+
+/// Whether the client asked for HTML rather than ActivityPub JSON
+fn prefers_html(request: &HttpRequest) -> bool {
+    let accept = match request.headers().get("Accept").and_then(|h| h.to_str().ok()) {
+        Some(accept) => accept,
+        None => return false,
+    };
+    accept.contains("text/html")
+        && !accept.contains("application/activity+json")
+        && !accept.contains("application/ld+json")
+}
+
+/// Resolves the template for a page, preferring a locale-suffixed custom template
+/// (e.g. `index.es.html`) matching the request's `Accept-Language` header
+fn get_template_path(data: &Data<AppState>, request: &HttpRequest, page: &str) -> String {
+    for lang in get_accepted_languages(request) {
+        let localized_page = format!("{}.{}", page, lang);
+        if data.is_custom_page.get(&localized_page).copied().unwrap_or(false) {
+            return format!("{}.html", localized_page);
+        }
+    }
+    if *data.is_custom_page.get(page).unwrap() {
+        format!("{}.html", page)
+    } else {
+        format!("{}.default.html", page)
+    }
+}
+
+/// Parses the `Accept-Language` header into lowercase language tags ordered by preference.
+/// Region-qualified tags (e.g. `pt-br`) are followed by their primary language (`pt`).
+fn get_accepted_languages(request: &HttpRequest) -> Vec<String> {
+    let header = match request
+        .headers()
+        .get("Accept-Language")
+        .and_then(|h| h.to_str().ok())
+    {
+        Some(header) => header,
+        None => return vec![],
+    };
+
+    let mut weighted: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|part| {
+            let mut pieces = part.trim().split(';');
+            let tag = pieces.next()?.trim().to_lowercase();
+            if tag.is_empty() || tag == "*" {
+                return None;
+            }
+            let quality = pieces
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // Stable sort keeps header order for equal weights
+    weighted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut languages: Vec<String> = Vec::new();
+    for (tag, _) in weighted {
+        let primary = tag.split('-').next().unwrap_or(&tag).to_string();
+        for lang in [tag, primary] {
+            if !languages.contains(&lang) {
+                languages.push(lang);
+            }
+        }
+    }
+    languages
+}
+
+/// Extensions a content-addressed image may be stored with
+const LOCAL_IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "gif", "webp"];
+
+/// Stores a data URL image under `images/{sha256}.{ext}` and returns its public URL.
+/// The hash is taken over the decoded bytes, so re-sending the same image reuses the
+/// existing file while a changed image gets a new path. Returns an empty string if
+/// the data URL can't be parsed or isn't a raster image; SVG is refused since it can
+/// carry scripts that would run on our origin.
+fn create_local_image(
+    protocol: &str,
+    relay_domain: &str,
+    app_image: &str,
+    max_dimension: u32,
+) -> String {
+    let dataurl = match DataUrl::parse(app_image) {
+        Ok(dataurl) => dataurl,
+        Err(e) => {
+            log_error!("Error parsing image data: {:?}", e);
+            return String::new();
+        }
+    };
+    let Some(ext) = raster_image_extension(dataurl.get_data()) else {
+        log_error!("Rejected image of type {}", dataurl.get_media_type());
+        return String::new();
+    };
+    let hash: String = Sha256::digest(dataurl.get_data())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    // Identical bytes were stored before, possibly by another app
+    for ext in LOCAL_IMAGE_EXTENSIONS {
+        let filepath = format!("images/{}.{}", hash, ext);
+        if std::fs::exists(&filepath).unwrap_or(false) {
+            return format!("{}{}/{}", protocol, relay_domain, filepath);
+        }
+    }
+
+    let Some(bytes) = downscale_image(dataurl.get_data(), max_dimension) else {
+        return String::new();
+    };
+    let filepath = format!("images/{}.{}", hash, ext);
+    if let Err(e) = std::fs::write(&filepath, bytes) {
+        log_error!("Error saving image {}: {}", filepath, e);
+        return String::new();
+    }
+    format!("{}{}/{}", protocol, relay_domain, filepath)
+}
+
+/// Runs [`create_local_image`] on the blocking pool, since decoding, resizing and writing
+/// a large image would otherwise hold up the worker serving the request
+async fn store_local_image(
+    protocol: &'static str,
+    relay_domain: &str,
+    app_image: &str,
+    max_dimension: u32,
+) -> String {
+    let relay_domain = relay_domain.to_string();
+    let app_image = app_image.to_string();
+    tokio::task::spawn_blocking(move || {
+        create_local_image(protocol, &relay_domain, &app_image, max_dimension)
+    })
+    .await
+    .unwrap_or_else(|e| {
+        log_error!("Error storing image: {}", e);
+        String::new()
+    })
+}
+
+/// File extension for the raster formats we store, from the image's own bytes rather
+/// than a claimed media type. Anything else, SVG included, is `None`.
+fn raster_image_extension(bytes: &[u8]) -> Option<&'static str> {
+    match image::guess_format(bytes).ok()? {
+        image::ImageFormat::Png => Some("png"),
+        image::ImageFormat::Jpeg => Some("jpg"),
+        image::ImageFormat::Gif => Some("gif"),
+        image::ImageFormat::WebP => Some("webp"),
+        _ => None,
+    }
+}
+
+/// Requests each app's URL and records whether it answered, hiding apps that
+/// have failed `hide_after` checks in a row
+pub async fn check_beacon_health(data: &Data<AppState>, hide_after: i32) {
+    let apps = match get_app_urls(data).await {
+        Ok(apps) => apps,
+        Err(e) => {
+            log_error!("Healthcheck: error fetching apps: {}", e);
+            return;
+        }
+    };
+    let mut unreachable = 0;
+    for (id, url) in apps {
+        let reachable = is_url_reachable(data, &url).await;
+        if !reachable {
+            unreachable += 1;
+        }
+        match record_app_health(data, id, reachable, hide_after).await {
+            Ok(true) => log_info!("Healthcheck: hid unreachable app #{} ({})", id, url),
+            Ok(false) => {}
+            Err(e) => log_error!("Healthcheck: error saving result for app #{}: {}", id, e),
+        }
+    }
+    if unreachable > 0 {
+        log_info!("Healthcheck: {} apps unreachable", unreachable);
+    }
+}
+
+/// Fetches this relay's webfinger and actor document through the public `DOMAIN` and
+/// logs whether they resolve and agree with the stored system user (`SELF_CHECK`)
+pub async fn run_self_check(data: &Data<AppState>) {
+    let domain = env::var("DOMAIN").expect("DOMAIN must be set");
+    let protocol = public_protocol();
+    let system_user = match get_system_user(data).await {
+        Ok(user) => user,
+        Err(e) => {
+            log_error!("Self-check: error fetching system user: {}", e);
+            return;
+        }
+    };
+    let ap_id = system_user.ap_id.inner().as_str();
+    let mut problems = Vec::new();
+
+    // The actor id is stored on first boot, so a later DOMAIN/PROTOCOL change orphans it
+    let expected_ap_id = format!("{}{}/relay", protocol, domain);
+    if ap_id != expected_ap_id {
+        problems.push(format!(
+            "stored actor id {} doesn't match {} built from PROTOCOL and DOMAIN",
+            ap_id, expected_ap_id
+        ));
+    }
+
+    let webfinger_url = format!(
+        "{}{}/.well-known/webfinger?resource=acct:relay@{}",
+        protocol, domain, domain
+    );
+    match fetch_self_check_json(data, &webfinger_url, "application/jrd+json").await {
+        Ok(json) => {
+            let self_href = json["links"]
+                .as_array()
+                .and_then(|links| links.iter().find(|link| link["rel"] == "self"))
+                .and_then(|link| link["href"].as_str());
+            if self_href != Some(ap_id) {
+                problems.push(format!(
+                    "webfinger self link is {:?}, expected {}",
+                    self_href, ap_id
+                ));
+            }
+        }
+        Err(e) => problems.push(format!("could not fetch {}: {}", webfinger_url, e)),
+    }
+
+    match fetch_self_check_json(data, ap_id, FEDERATION_CONTENT_TYPE).await {
+        Ok(json) => {
+            if json["id"].as_str() != Some(ap_id) {
+                problems.push(format!("actor document has id {}, expected {}", json["id"], ap_id));
+            }
+            if json["publicKey"]["owner"].as_str() != Some(ap_id) {
+                problems.push(format!(
+                    "actor public key is owned by {}, expected {}",
+                    json["publicKey"]["owner"], ap_id
+                ));
+            }
+            // Peers verify our signatures against this key
+            let served_key = json["publicKey"]["publicKeyPem"].as_str().map(str::trim);
+            if served_key != Some(system_user.public_key_pem().trim()) {
+                problems.push("served public key doesn't match the stored key pair".to_string());
+            }
+        }
+        Err(e) => problems.push(format!("could not fetch {}: {}", ap_id, e)),
+    }
+
+    if problems.is_empty() {
+        log_info!("Self-check passed: {} is reachable and consistent", ap_id);
+        return;
+    }
+    log_error!("Self-check FAILED, peers will not be able to follow this relay:");
+    for problem in problems {
+        log_error!("  - {}", problem);
+    }
+    log_error!("  Check DOMAIN, PROTOCOL and that the relay is reachable at that address");
+}
+
+async fn fetch_self_check_json(
+    data: &Data<AppState>,
+    url: &str,
+    accept: &str,
+) -> Result<serde_json::Value, reqwest::Error> {
+    data.http_client
+        .get(url)
+        .header(reqwest::header::ACCEPT, accept)
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+/// HEAD the URL, falling back to GET for servers that don't answer HEAD properly
+async fn is_url_reachable(data: &Data<AppState>, url: &str) -> bool {
+    let parsed = match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => parsed,
+        _ => return false,
+    };
+    if let Err(e) = data.outbound_guard.check(&parsed).await {
+        log_error!("Healthcheck: skipping {}: {}", url, e);
+        return false;
+    }
+    for method in [reqwest::Method::HEAD, reqwest::Method::GET] {
+        let response = data
+            .http_client
+            .request(method, parsed.as_str())
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => return true,
+            Ok(_) => continue,
+            // Connection errors won't be any different for GET
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+/// Deletes files in `images/` that no app references anymore. Files younger
+/// than `grace` are kept so an image written just before its app row is not lost.
+pub async fn remove_orphaned_images(data: &Data<AppState>, grace: Duration) {
+    let images = match get_all_app_images(data).await {
+        Ok(images) => images,
+        Err(e) => {
+            log_error!("Image GC: error fetching app images: {}", e);
+            return;
+        }
+    };
+    let referenced: HashSet<&str> = images
+        .iter()
+        .filter_map(|image| image.rsplit_once("/images/").map(|(_, file)| file))
+        .collect();
+
+    let entries = match std::fs::read_dir("images") {
+        Ok(entries) => entries,
+        Err(e) => {
+            log_error!("Image GC: error reading images directory: {}", e);
+            return;
+        }
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        // Only image files are ours to manage; leave anything else (e.g. README.md) alone
+        let is_image = matches!(
+            file_name.rsplit_once('.').map(|(_, ext)| ext),
+            Some("png" | "jpg" | "jpeg" | "gif" | "webp" | "svg")
+        );
+        if !is_image || referenced.contains(file_name.as_str()) {
+            continue;
+        }
+        let old_enough = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= grace);
+        if !old_enough || !entry.path().is_file() {
+            continue;
+        }
+        match std::fs::remove_file(entry.path()) {
+            Ok(_) => removed += 1,
+            Err(e) => log_error!("Image GC: error removing {}: {}", file_name, e),
+        }
+    }
+    if removed > 0 {
+        log_info!("Image GC: removed {} orphaned image(s)", removed);
+    }
+}
+
+/// Largest remote image we are willing to download when proxying
+const MAX_REMOTE_IMAGE_BYTES: usize = 1024 * 1024 * 10;
+
+/// Downloads a federated app's remote image into our image folder and points
+/// the app at the local copy, so our pages stop hotlinking other servers.
+/// On any failure the remote URL is kept.
+pub async fn localize_remote_image(data: &Data<AppState>, app: &mut DbApp) {
+    if !data.features().proxy_remote_images {
+        return;
+    }
+    let relay_domain = env::var("DOMAIN").expect("DOMAIN must be set");
+    let protocol = public_protocol();
+    if !app.has_real_image() {
+        return;
+    }
+    let remote = match Url::parse(&app.image) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => return,
+    };
+    if remote.host_str() == Some(relay_domain.as_str()) {
+        return;
+    }
+    if let Err(e) = data.outbound_guard.check(&remote).await {
+        log_error!("Skipping remote image: {}", e);
+        return;
+    }
+
+    let response = match data
+        .http_client
+        .get(remote.as_str())
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+    {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            log_error!("Remote image {} returned {}", remote, response.status());
+            return;
+        }
+        Err(e) => {
+            log_error!("Error fetching remote image {}: {}", remote, e);
+            return;
+        }
+    };
+    let bytes = match read_body_capped(response, MAX_REMOTE_IMAGE_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log_error!("Skipping remote image {}: {}", remote, e);
+            return;
+        }
+    };
+    // Go by the contents, not the Content-Type, so nothing but raster images gets stored
+    let Some(ext) = raster_image_extension(&bytes) else {
+        log_error!("Skipping remote image {}: not a PNG, JPEG, GIF or WebP image", remote);
+        return;
+    };
+
+    // Name the copy after the origin relay and its app id so updates overwrite it
+    let ap_id = app.ap_id.inner();
+    let name: String = format!(
+        "remote-{}-{}",
+        ap_id.host_str().unwrap_or(""),
+        ap_id.path_segments().and_then(|mut s| s.next_back()).unwrap_or("")
+    )
+    .chars()
+    .map(|c| if c.is_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+    .collect();
+    let filepath = format!("images/{}.{}", name, ext);
+    let max_dimension = data.max_image_dimension;
+    let path = filepath.clone();
+    // Decoding, resizing and writing run on the blocking pool, off the inbox worker
+    let stored = tokio::task::spawn_blocking(move || {
+        let bytes = downscale_image(&bytes, max_dimension)
+            .ok_or_else(|| "not a decodable image within the size limits".to_string())?;
+        std::fs::write(&path, bytes).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    if let Err(e) = stored {
+        log_error!("Error saving remote image {}: {}", remote, e);
+        return;
+    }
+    app.image = format!("{}{}/{}", protocol, relay_domain, filepath);
+}
+
+/// Widest or tallest image we decode; larger ones are refused before their pixels are
+/// allocated, whatever `MAX_IMAGE_DIMENSION` is
+const MAX_DECODED_DIMENSION: u32 = 16_384;
+
+/// Re-encodes an image in its own format if either dimension exceeds `max_dimension`,
+/// so it keeps the extension it was stored under. `None` if the image can't be decoded
+/// or is larger than [`MAX_DECODED_DIMENSION`]. Blocking; call it off the async workers.
+fn downscale_image(bytes: &[u8], max_dimension: u32) -> Option<Vec<u8>> {
+    let mut reader = image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?;
+    let format = reader.format()?;
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_DECODED_DIMENSION);
+    limits.max_image_height = Some(MAX_DECODED_DIMENSION);
+    reader.limits(limits);
+    let decoded = match reader.decode() {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            log_error!("Refusing image that can't be decoded: {}", e);
+            return None;
+        }
+    };
+    if decoded.width() <= max_dimension && decoded.height() <= max_dimension {
+        return Some(bytes.to_vec());
+    }
+
+    let resized = decoded.resize(
+        max_dimension,
+        max_dimension,
+        image::imageops::FilterType::Lanczos3,
+    );
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    match resized.write_to(&mut encoded, format) {
+        Ok(_) => Some(encoded.into_inner()),
+        Err(e) => {
+            log_error!("Error re-encoding oversized image: {}", e);
+            None
+        }
+    }
+}
+
+/// Trims and collapses runs of whitespace into single spaces
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Whether two texts are the same apart from spacing
+fn same_text(a: &str, b: &str) -> bool {
+    a.split_whitespace().eq(b.split_whitespace())
+}
+
+/// Sends an app Update, or coalesces it with others for the same app when one
+/// went out within `UPDATE_MIN_INTERVAL_SECS` or the app is still changing
+/// (`UPDATE_DEBOUNCE_MS`)
+async fn broadcast_app_update(
+    data: &Data<AppState>,
+    system_user: &DbRelay,
+    app_ap_id: &ObjectId<DbApp>,
+) -> Result<(), super::error::Error> {
+    match data.update_throttle.decide(app_ap_id.inner().as_str()) {
+        BroadcastDecision::SendNow => send_app_update(data, system_user, app_ap_id).await,
+        BroadcastDecision::Coalesced => Ok(()),
+        BroadcastDecision::SendAfter(delay) => {
+            let data = data.reset_request_count();
+            let system_user = system_user.clone();
+            let app_ap_id = app_ap_id.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let ap_id = app_ap_id.inner().as_str();
+                loop {
+                    let remaining = data.update_throttle.remaining_debounce(ap_id);
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    tokio::time::sleep(remaining).await;
+                }
+                data.update_throttle.flushed(ap_id);
+                if let Err(e) = send_app_update(&data, &system_user, &app_ap_id).await {
+                    log_error!("Error sending coalesced update: {}", e);
+                }
+            });
+            Ok(())
+        }
+    }
+}
+
+/// Records an Update activity for an app and sends it to following relays.
+/// Only failing to record the activity is an error; delivery failures are logged.
+async fn send_app_update(
+    data: &Data<AppState>,
+    system_user: &DbRelay,
+    app_ap_id: &ObjectId<DbApp>,
+) -> Result<(), super::error::Error> {
+    let activity_id = create_local_activity(
+        data,
+        system_user.ap_id.inner().as_str(),
+        app_ap_id.inner().as_str(),
+        ActivityKind::Update,
+    )
+    .await?;
+    let activity = Update {
+        actor: system_user.ap_id.clone(),
+        object: app_ap_id.clone(),
+        kind: UpdateType::Update,
+        id: Url::from_str(&activity_id)?,
+        published: Some(Utc::now()),
+    };
+
+    let recipient_inboxes = get_broadcast_inboxes(data).await;
+    let _ = system_user
+        .send(activity, recipient_inboxes, false, data)
+        .await
+        .map_err(|e| log_error!("Error sending activity: {}", e));
+    Ok(())
+}
+
+/// Followers a broadcast goes to, honouring `mutual_only`
+async fn get_broadcast_recipients(
+    data: &Data<AppState>,
+) -> Result<Vec<DbRelay>, super::error::Error> {
+    if data.features().mutual_only {
+        get_mutual_relay_followers(data).await
+    } else {
+        get_relay_followers(data).await
+    }
+}
+
+/// Collects the inboxes that beacon activities should be fanned out to.
+/// In mutual-only mode, followers we don't follow back receive nothing.
+async fn get_broadcast_inboxes(data: &Data<AppState>) -> Vec<Url> {
+    let recipients: Vec<DbRelay> = match get_broadcast_recipients(data).await {
+        Ok(relays) => relays,
+        Err(e) => {
+            log_error!("Error fetching relays: {}", e);
+            vec![]
+        }
+    };
+    distinct_inboxes(&recipients)
+}
+
+/// Each recipient's shared inbox, or its own inbox without one, once per inbox so
+/// followers behind the same shared inbox get a single delivery
+fn distinct_inboxes(recipients: &[DbRelay]) -> Vec<Url> {
+    let mut seen = HashSet::new();
+    recipients
+        .iter()
+        .map(|relay| relay.shared_inbox_or_inbox())
+        .filter(|inbox| seen.insert(inbox.clone()))
+        .collect()
+}
+
+/// Escapes the characters that are significant in XML text and attributes
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn get_latest_value<T: PartialEq>(original: T, incoming: T) -> T {
+    if original != incoming {
+        incoming
+    } else {
+        original
+    }
+}
+
+fn prune_old_sessions(data: &Data<AppState>) {
+    let mut sessions = match data.sessions.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            log_error!("Warning: sessions lock was poisoned during pruning. Attempting recovery...");
+            poisoned.into_inner()
+        }
+    };
+    // Iterate through all sessions and remove any that are older than 5 seconds
+    sessions.values_mut().for_each(|url_sessions| {
+        url_sessions.retain(|session| {
+            (time::OffsetDateTime::now_utc().unix_timestamp() * 1000) - session.timestamp < 5000
+        })
+    });
+}
+
+fn normalize_app_url(url: String) -> String {
+    // Schemes are case-insensitive, so `HTTPS://` must not get another one in front
+    if !url.to_ascii_lowercase().starts_with("http") {
+        let mut adjusted_url = String::new();
+        adjusted_url.push_str("https://");
+        adjusted_url.push_str(&url);
+        adjusted_url
+    } else {
+        url
+    }
+}
+
+/// Extracts base URL without query parameters (scheme + host + path)
+fn get_base_url(url: &str) -> Option<String> {
+    let normalized = normalize_app_url(url.to_string());
+    let parsed = Url::parse(&normalized).ok()?;
+    let mut base = format!("{}://{}", parsed.scheme(), parsed.host_str()?);
+    let path = parsed.path();
+    if path != "/" {
+        base.push_str(path);
+    }
+    Some(base)
+}
+
+/// Query parameters that only carry tracking information and never identify an app,
+/// besides any `utm_*`. Kept short, since sites route on names like `ref` or `source`.
+const TRACKING_QUERY_PARAMS: &[&str] = &["fbclid", "gclid"];
+
+/// Normalizes a beacon URL into the canonical form used for storage and lookups.
+/// Lowercases the host, strips tracking query params and drops any trailing slash.
+fn canonicalize_beacon_url(url: &str) -> String {
+    let normalized = normalize_app_url(url.trim().to_string());
+    let parsed = match Url::parse(&normalized) {
+        Ok(parsed) => parsed,
+        Err(_) => return url.trim().to_string(),
+    };
+    let host = match parsed.host_str() {
+        Some(host) => host.to_lowercase(),
+        None => return normalized,
+    };
+
+    let mut canonical = format!("{}://{}", parsed.scheme(), host);
+    if let Some(port) = parsed.port() {
+        canonical.push_str(&format!(":{}", port));
+    }
+    canonical.push_str(parsed.path().trim_end_matches('/'));
+
+    let kept_pairs: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_lowercase();
+            !key.starts_with("utm_") && !TRACKING_QUERY_PARAMS.contains(&key.as_str())
+        })
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if !kept_pairs.is_empty() {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(kept_pairs)
+            .finish();
+        canonical.push('?');
+        canonical.push_str(&query);
+    }
+    if let Some(fragment) = parsed.fragment() {
+        canonical.push('#');
+        canonical.push_str(fragment);
+    }
+    canonical
+}
+
+/// Extracts just the domain (host) from a URL
+fn get_domain(url: &str) -> Option<String> {
+    let normalized = normalize_app_url(url.to_string());
+    let parsed = Url::parse(&normalized).ok()?;
+    parsed.host_str().map(|h| h.to_string())
+}
+
+/// Generates a URL-friendly slug from a name
+fn generate_slug(name: &str) -> String {
+    let slug_text = slug::slugify(name);
+    // Ensure slug isn't empty
+    if slug_text.is_empty() {
+        "world".to_string()
+    } else {
+        slug_text
+    }
+}
+
+/// The app a `/world/{id_or_slug}` path names. Slugs are tried first, so a world
+/// whose name slugifies to a number isn't shadowed by the app with that id.
+async fn find_app_by_id_or_slug(data: &Data<AppState>, id_or_slug: &str) -> Option<DbApp> {
+    match get_app_by_slug(data, id_or_slug).await {
+        Ok(Some(app)) => return Some(app),
+        Ok(None) => {}
+        Err(e) => log_error!("Error fetching app by slug {}: {}", id_or_slug, e),
+    }
+    let id = id_or_slug.parse::<i32>().ok()?;
+    get_app_by_id(id, data).await.ok()
+}
+
+/// Generates a unique slug, appending numbers if needed
+async fn generate_unique_slug(data: &Data<AppState>, name: &str) -> String {
+    let base_slug = generate_slug(name);
+
+    // Check if base slug is available
+    if let Ok(false) = slug_exists(data, &base_slug).await {
+        return base_slug;
+    }
+
+    // Try appending numbers until we find a unique one
+    for i in 2..1000 {
+        let numbered_slug = format!("{}-{}", base_slug, i);
+        if let Ok(false) = slug_exists(data, &numbered_slug).await {
+            return numbered_slug;
+        }
+    }
+
+    // Fallback: append random suffix
+    format!("{}-{}", base_slug, rand::random::<u32>())
+}
+
+/// Generates a random verification code
+fn generate_verification_code() -> String {
+    let mut rng = rand::thread_rng();
+    let code: String = (0..32)
+        .map(|_| {
+            let idx = rng.gen_range(0..36);
+            if idx < 10 {
+                (b'0' + idx) as char
+            } else {
+                (b'a' + idx - 10) as char
+            }
+        })
+        .collect();
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use activitypub_federation::config::FederationMiddleware;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{
+        call_and_read_body, call_and_read_body_json, call_service, init_service, read_body_json,
+        TestRequest,
+    };
+    use actix_web::App;
+
+    use super::*;
+    use crate::activitypub::apps::SOURCE_OWNER;
+    use crate::activitypub::db::{add_follower_to_relay, create_activity, create_relay};
+    use crate::test_support::{self, SiteFiles};
+
+    /// A valid admin session, signed with the system user's key
+    async fn admin_cookie(data: &Data<AppState>) -> Cookie<'static> {
+        let user = get_relay_by_id(0, data).await.unwrap();
+        let keypair = RS256KeyPair::from_pem(&user.private_key_pem().unwrap()).unwrap();
+        let token = keypair
+            .sign(Claims::create(jwt_simple::prelude::Duration::from_hours(1)))
+            .unwrap();
+        Cookie::new("relay-admin-token", token)
+    }
+
+    #[actix_web::test]
+    async fn claim_then_authorized_update() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let files: SiteFiles = Arc::new(Mutex::new(HashMap::new()));
+        let site = test_support::serve_site(files.clone());
+        let app_id = test_support::insert_app(&data, &site, "Unclaimed").await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(claim_beacon)
+                .service(update_world),
+        )
+        .await;
+        let claim = |claim_key: Option<&str>| {
+            TestRequest::post()
+                .uri("/beacon/claim")
+                .set_json(serde_json::json!({ "url": site, "claim_key": claim_key }))
+                .to_request()
+        };
+
+        // The site's file listing this relay is no longer enough on its own
+        files.lock().unwrap().insert(
+            DOMAIN_VERIFICATION_PATH.to_string(),
+            test_support::TEST_DOMAIN.into(),
+        );
+        let started: serde_json::Value = call_and_read_body_json(&app, claim(None)).await;
+        let challenge = started["challenge"].as_str().unwrap().to_string();
+        let key = started["claim_key"].as_str().unwrap().to_string();
+        let response = call_service(&app, claim(Some(&key))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // Someone else starting a claim can't complete it with the owner's published nonce
+        files.lock().unwrap().insert(
+            DOMAIN_VERIFICATION_PATH.to_string(),
+            format!("# claim\n{}\n", challenge).into(),
+        );
+        let other: serde_json::Value = call_and_read_body_json(&app, claim(None)).await;
+        let other_key = other["claim_key"].as_str().unwrap();
+        let response = call_service(&app, claim(Some(other_key))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = call_service(&app, claim(Some("guessed"))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = call_service(&app, claim(Some(&key))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = response
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == "relay-owner-token")
+            .unwrap()
+            .into_owned();
+
+        let update = serde_json::json!({ "name": "Claimed", "description": "Mine now" });
+        let response = call_service(
+            &app,
+            TestRequest::post()
+                .uri(&format!("/world/{}/update", app_id))
+                .set_json(&update)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = call_service(
+            &app,
+            TestRequest::post()
+                .uri(&format!("/world/{}/update", app_id))
+                .cookie(cookie)
+                .set_json(&update)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(get_app_by_id(app_id, &data).await.unwrap().name, "Claimed");
+
+        // Once claimed, a proven challenge from someone else no longer takes it over
+        let late: serde_json::Value = call_and_read_body_json(&app, claim(None)).await;
+        files.lock().unwrap().insert(
+            DOMAIN_VERIFICATION_PATH.to_string(),
+            late["challenge"].as_str().unwrap().into(),
+        );
+        let late_key = late["claim_key"].as_str().unwrap();
+        let response = call_service(&app, claim(Some(late_key))).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    fn png_data_url(width: u32, height: u32, shade: u8) -> String {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::RgbImage::from_pixel(width, height, image::Rgb([shade, shade, shade]))
+            .write_to(&mut bytes, image::ImageFormat::Png)
+            .unwrap();
+        format!(
+            "data:image/png;base64,{}",
+            BASE64_STANDARD.encode(bytes.into_inner())
+        )
+    }
+
+    fn stored_file(url: &str) -> String {
+        url.rsplit_once("/images/")
+            .map(|(_, file)| format!("images/{}", file))
+            .unwrap()
+    }
+
+    #[test]
+    fn unchanged_image_reuses_its_file_and_changed_image_gets_a_new_one() {
+        // Random sizes keep the bytes, and so the paths, apart from other test runs
+        let side = rand::random::<u8>() as u32 + 2;
+        let original = png_data_url(side, side, 10);
+        let first = create_local_image("https://", "relay.test", &original, 2048);
+        assert!(first.starts_with("https://relay.test/images/"));
+        assert!(first.ends_with(".png"));
+        let again = create_local_image("https://", "relay.test", &original, 2048);
+        assert_eq!(first, again);
+
+        let changed = create_local_image(
+            "https://",
+            "relay.test",
+            &png_data_url(side, side, 20),
+            2048,
+        );
+        assert!(!changed.is_empty());
+        assert_ne!(first, changed);
+
+        std::fs::remove_file(stored_file(&first)).unwrap();
+        std::fs::remove_file(stored_file(&changed)).unwrap();
+    }
+
+    #[test]
+    fn svg_and_non_images_are_refused() {
+        let svg = format!(
+            "data:image/svg+xml;base64,{}",
+            BASE64_STANDARD.encode(
+                r#"<svg xmlns="http://www.w3.org/2000/svg"><script>alert(1)</script></svg>"#
+            )
+        );
+        assert_eq!(create_local_image("https://", "relay.test", &svg, 2048), "");
+        let html = format!(
+            "data:image/png;base64,{}",
+            BASE64_STANDARD.encode("<html></html>")
+        );
+        assert_eq!(
+            create_local_image("https://", "relay.test", &html, 2048),
+            ""
+        );
+    }
+
+    #[actix_web::test]
+    async fn images_are_served_sandboxed() {
+        let config = test_support::offline_config().await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config))
+                .service(get_image),
+        )
+        .await;
+        let name = format!("{}.svg", test_support::unique("legacy"));
+        std::fs::write(
+            format!("images/{}", name),
+            "<svg><script>alert(1)</script></svg>",
+        )
+        .unwrap();
+        let response = call_service(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/images/{}", name))
+                .to_request(),
+        )
+        .await;
+        std::fs::remove_file(format!("images/{}", name)).unwrap();
+        let headers = response.headers();
+        assert_eq!(
+            headers.get("Content-Security-Policy").unwrap(),
+            "default-src 'none'; sandbox"
+        );
+        assert_eq!(headers.get("X-Content-Type-Options").unwrap(), "nosniff");
+        assert_eq!(headers.get("Content-Disposition").unwrap(), "attachment");
+
+        let response = call_service(
+            &app,
+            TestRequest::get().uri("/images/missing.png").to_request(),
+        )
+        .await;
+        let headers = response.headers();
+        assert_eq!(headers.get("X-Content-Type-Options").unwrap(), "nosniff");
+        assert!(headers.get("Content-Disposition").is_none());
+    }
+
+    fn inbox_activity(kind: &str, object: &str) -> serde_json::Value {
+        serde_json::json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "type": kind,
+            "id": format!("https://peer.test/activities/{}", kind.to_lowercase()),
+            "actor": "https://peer.test/relay",
+            "object": object,
+        })
+    }
+
+    /// Matches every variant, so a new one can't be added without updating this
+    fn variant_kind(activity: &RelayAcceptedActivities) -> ActivityKind {
+        match activity {
+            RelayAcceptedActivities::Follow(_) => ActivityKind::Follow,
+            RelayAcceptedActivities::Create(_) => ActivityKind::Create,
+            RelayAcceptedActivities::Update(_) => ActivityKind::Update,
+        }
+    }
+
+    #[test]
+    fn inbox_activities_round_trip_into_their_own_variant() {
+        let cases = [
+            ("Follow", "https://relay.test/relay"),
+            ("Create", "https://peer.test/relay/beacon/1"),
+            ("Update", "https://peer.test/relay/beacon/1"),
+        ];
+        for (kind, object) in cases {
+            let json = inbox_activity(kind, object);
+            let activity: WithContext<RelayAcceptedActivities> =
+                serde_json::from_value(json.clone()).unwrap();
+            assert_eq!(variant_kind(activity.inner()).as_str(), kind);
+            assert_eq!(serde_json::to_value(&activity).unwrap(), json);
+        }
+    }
+
+    #[actix_web::test]
+    async fn capabilities_advertise_exactly_the_inbox_variants() {
+        let config = test_support::offline_config().await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(api_get_capabilities),
+        )
+        .await;
+        let request = TestRequest::get().uri("/api/capabilities").to_request();
+        let capabilities: serde_json::Value = call_and_read_body_json(&app, request).await;
+        let advertised: Vec<String> =
+            serde_json::from_value(capabilities["inbox"]["supported_activities"].clone()).unwrap();
+
+        // Each advertised type parses into the variant of that name, and each variant
+        // is advertised once
+        let mut kinds = Vec::new();
+        for kind in &advertised {
+            let json = inbox_activity(kind, "https://peer.test/relay/beacon/1");
+            let activity: RelayAcceptedActivities = serde_json::from_value(json).unwrap();
+            assert_eq!(variant_kind(&activity).as_str(), kind);
+            kinds.push(variant_kind(&activity));
+        }
+        assert_eq!(kinds, ActivityKind::INBOX);
+        assert_eq!(
+            capabilities["inbox"]["accepted_activities"],
+            serde_json::json!(["Follow", "Create", "Update"])
+        );
+    }
+
+    #[test]
+    fn unknown_inbox_activities_are_refused() {
+        for kind in ["Announce", "Accept", "Delete", "follow"] {
+            let json = inbox_activity(kind, "https://relay.test/relay");
+            assert!(
+                serde_json::from_value::<WithContext<RelayAcceptedActivities>>(json).is_err(),
+                "{}",
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn json_depth_counts_only_structural_brackets() {
+        assert!(!json_depth_exceeds(b"{\"a\": [1, {\"b\": []}]}", 4));
+        assert!(json_depth_exceeds(b"{\"a\": [1, {\"b\": []}]}", 3));
+        // Brackets and escaped quotes inside strings don't count
+        assert!(!json_depth_exceeds(br#"{"a": "[[[{{{\"[[["}"#, 1));
+        assert!(!json_depth_exceeds(b"", 0));
+    }
+
+    #[actix_web::test]
+    async fn inbox_refuses_oversized_and_deeply_nested_bodies() {
+        let config = test_support::offline_config().await;
+        let max_depth = test_support::data(&config).inbox_max_depth;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .app_data(web::PayloadConfig::default().limit(1024))
+                .service(http_post_shared_inbox),
+        )
+        .await;
+        let post = |body: String| {
+            TestRequest::post()
+                .uri("/inbox")
+                .insert_header(("Content-Type", "application/activity+json"))
+                .set_payload(body)
+                .to_request()
+        };
+
+        let padding = "x".repeat(2048);
+        let oversized = format!("{{\"type\": \"Follow\", \"padding\": \"{}\"}}", padding);
+        let response = call_service(&app, post(oversized)).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let nested = format!("{}{}", "[".repeat(max_depth + 1), "]".repeat(max_depth + 1));
+        let response = call_service(&app, post(nested)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        assert_eq!(body, "Activity is nested too deeply");
+    }
+
+    #[actix_web::test]
+    async fn unsupported_inbox_activities_are_acknowledged_and_dropped() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(http_post_shared_inbox),
+        )
+        .await;
+        let mut like = inbox_activity("Like", "https://relay.test/relay/beacon/1");
+        let ap_id = format!(
+            "https://peer.test/activities/{}",
+            test_support::unique("like")
+        );
+        like["id"] = ap_id.clone().into();
+        let request = TestRequest::post()
+            .uri("/inbox")
+            .insert_header(("Content-Type", "application/activity+json"))
+            .set_payload(like.to_string())
+            .to_request();
+
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let stored: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM activities WHERE activitypub_id = $1")
+                .bind(&ap_id)
+                .fetch_one(&data.db)
+                .await
+                .unwrap();
+        assert_eq!(stored, 0);
+    }
+
+    #[actix_web::test]
+    async fn deleted_worlds_are_gone_and_unknown_ones_not_found() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let url = format!("https://{}.test/", test_support::unique("gone"));
+        let app_id = test_support::insert_app(&data, &url, "Gone").await;
+        let slug = test_support::unique("gone");
+        set_app_slug(&data, app_id, &slug).await.unwrap();
+        let ap_id = get_app_by_id(app_id, &data)
+            .await
+            .unwrap()
+            .ap_id
+            .to_string();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_world),
+        )
+        .await;
+        let get = |path: String, accept: &str| {
+            TestRequest::get()
+                .uri(&path)
+                .insert_header(("Accept", accept))
+                .to_request()
+        };
+
+        let response = call_service(&app, get(format!("/world/{}", slug), "text/html")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        delete_app(app_id, &data).await.unwrap();
+
+        for path in [format!("/world/{}", slug), format!("/world/{}", app_id)] {
+            let response = call_service(&app, get(path.clone(), "text/html")).await;
+            assert_eq!(response.status(), StatusCode::GONE, "{}", path);
+            let response = call_service(&app, get(path.clone(), FEDERATION_CONTENT_TYPE)).await;
+            assert_eq!(response.status(), StatusCode::GONE, "{}", path);
+            let tombstone: serde_json::Value = read_body_json(response).await;
+            assert_eq!(tombstone["type"], "Tombstone");
+            assert_eq!(tombstone["id"], ap_id.as_str());
+        }
+
+        let never = test_support::unique("never");
+        for path in [format!("/world/{}", never), format!("/world/{}", i32::MAX)] {
+            let response = call_service(&app, get(path.clone(), "text/html")).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+            let response = call_service(&app, get(path.clone(), FEDERATION_CONTENT_TYPE)).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+        }
+    }
+
+    #[actix_web::test]
+    async fn metrics_require_the_configured_basic_auth() {
+        let Some(config) = test_support::db_config_with(|state| {
+            state.metrics_auth = Some("prometheus:secret".to_string());
+        })
+        .await
+        else {
+            return;
+        };
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_metrics),
+        )
+        .await;
+        let scrape = |credentials: Option<&str>| {
+            let mut request = TestRequest::get().uri("/metrics");
+            if let Some(credentials) = credentials {
+                let header = format!("Basic {}", BASE64_STANDARD.encode(credentials));
+                request = request.insert_header(("Authorization", header));
+            }
+            request.to_request()
+        };
+
+        for credentials in [None, Some("prometheus:wrong"), Some("prometheus")] {
+            let response = call_service(&app, scrape(credentials)).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{:?}", credentials);
+            assert_eq!(
+                response.headers().get("WWW-Authenticate").unwrap(),
+                "Basic realm=\"relay\""
+            );
+        }
+        let response = call_service(&app, scrape(Some("prometheus:secret"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("relay_apps_total "));
+
+        // Without METRICS_AUTH the endpoint is open
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_metrics),
+        )
+        .await;
+        let response = call_service(&app, scrape(None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn relay_routes_report_a_missing_system_user() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        sqlx::query("DELETE FROM relays WHERE id = 0")
+            .execute(&data.db)
+            .await
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(http_get_system_user)
+                .service(get_beacon)
+                .service(get_relay_catalog),
+        )
+        .await;
+
+        for uri in ["/relay", "/relay/beacon/1", "/relay/catalog"] {
+            let request = TestRequest::get()
+                .uri(uri)
+                .insert_header(("Accept", FEDERATION_CONTENT_TYPE))
+                .to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "{}",
+                uri
+            );
+            let body = actix_web::body::to_bytes(response.into_body())
+                .await
+                .unwrap();
+            assert_eq!(body, "Relay not initialized", "{}", uri);
+        }
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn relay_apps_are_filtered_by_origin_relay() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let mut relays = Vec::new();
+        for peer in ["first", "second"] {
+            let actor = format!("https://{}.test/relay", test_support::unique(peer));
+            let inbox = format!("{}/inbox", actor);
+            create_relay(&data, peer, &actor, &inbox, &inbox, "unused")
+                .await
+                .unwrap();
+            let relay_id = get_relay_follower_id_by_ap_id(&data, &actor).await.unwrap();
+            let mut app_ids = Vec::new();
+            for name in ["listed", "hidden"] {
+                let url = format!("https://{}.test", test_support::unique(name));
+                let app_id = test_support::insert_app(&data, &url, name).await;
+                sqlx::query("UPDATE apps SET origin_relay_id = $1, visible = $2 WHERE id = $3")
+                    .bind(relay_id)
+                    .bind(name == "listed")
+                    .bind(app_id)
+                    .execute(&data.db)
+                    .await
+                    .unwrap();
+                app_ids.push(app_id as i64);
+            }
+            relays.push((relay_id, app_ids));
+        }
+        let cookie = admin_cookie(&data).await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(api_get_relay_apps),
+        )
+        .await;
+        let list = |relay_id: i32, admin: bool| {
+            let mut request = TestRequest::get().uri(&format!("/api/relays/{}/apps", relay_id));
+            if admin {
+                request = request.cookie(cookie.clone());
+            }
+            let app = &app;
+            async move {
+                let page: serde_json::Value =
+                    call_and_read_body_json(app, request.to_request()).await;
+                let ids: Vec<i64> = page["apps"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|app| app["id"].as_i64().unwrap())
+                    .collect();
+                (ids, page["total"].as_i64().unwrap())
+            }
+        };
+
+        for (relay_id, app_ids) in &relays {
+            assert_eq!(list(*relay_id, false).await, (vec![app_ids[0]], 1));
+            assert_eq!(list(*relay_id, true).await, (app_ids.clone(), 2));
+        }
+        let request = TestRequest::get()
+            .uri(&format!("/api/relays/{}/apps", i32::MAX))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn similar_worlds_are_ranked_by_shared_tags() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let tag = test_support::unique("tag");
+        async fn world(data: &Data<AppState>, name: &str, tags: String) -> i32 {
+            let url = format!("https://{}.test/", test_support::unique(name));
+            let id = test_support::insert_app(data, &url, name).await;
+            sqlx::query("UPDATE apps SET tags = $1 WHERE id = $2")
+                .bind(tags)
+                .bind(id)
+                .execute(&data.db)
+                .await
+                .unwrap();
+            id
+        }
+        let target = world(&data, "target", format!("{0}-a, {0}-b, {0}-c", tag)).await;
+        let two = world(&data, "two", format!("{0}-A,{0}-b, other", tag)).await;
+        let one = world(&data, "one", format!("{0}-c", tag)).await;
+        world(&data, "none", "other".to_string()).await;
+        let hidden = world(&data, "hidden", format!("{0}-a,{0}-b,{0}-c", tag)).await;
+        toggle_app_visibility(hidden, &data).await.unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(api_get_similar_apps),
+        )
+        .await;
+
+        let uri = format!("/api/apps/{}/similar", target);
+        let similar: Vec<serde_json::Value> =
+            call_and_read_body_json(&app, TestRequest::get().uri(&uri).to_request()).await;
+        let ranked: Vec<(i64, i64)> = similar
+            .iter()
+            .map(|app| {
+                (
+                    app["id"].as_i64().unwrap(),
+                    app["shared_tags"].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(ranked, [(two as i64, 2), (one as i64, 1)]);
+
+        let uri = format!("/api/apps/{}/similar?limit=1", target);
+        let similar: Vec<serde_json::Value> =
+            call_and_read_body_json(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(similar.len(), 1);
+
+        let uri = format!("/api/apps/{}/similar", i32::MAX);
+        let response = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn similar_worlds_report_database_errors() {
+        let config = test_support::offline_config().await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(api_get_similar_apps),
+        )
+        .await;
+        let request = TestRequest::get().uri("/api/apps/1/similar").to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[actix_web::test]
+    async fn received_activities_are_served_as_they_arrived() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let ap_id = format!(
+            "https://peer.test/activities/{}",
+            test_support::unique("update")
+        );
+        let raw = serde_json::json!({
+            "@context": ["https://www.w3.org/ns/activitystreams", {"peer": "https://peer.test/ns#"}],
+            "type": "Update",
+            "id": ap_id,
+            "actor": "https://peer.test/relay",
+            "object": "https://peer.test/relay/beacon/7",
+            "published": "2026-10-16T12:00:00Z",
+            "peer:note": {"kept": [1, 2, 3]},
+        });
+        let id = create_activity(
+            &data,
+            ap_id.clone(),
+            "https://peer.test/relay",
+            "https://peer.test/relay/beacon/7",
+            ActivityKind::Update,
+        )
+        .await
+        .unwrap();
+        store_activity_raw(&data, &ap_id, &raw).await.unwrap();
+        // A redelivery doesn't replace the first copy
+        let redelivered = serde_json::json!({"type": "Update", "id": ap_id});
+        store_activity_raw(&data, &ap_id, &redelivered)
+            .await
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_activity),
+        )
+        .await;
+
+        let uri = format!("/relay/activities/{}", id);
+        let response = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Type").unwrap(),
+            FEDERATION_CONTENT_TYPE
+        );
+        let served: serde_json::Value = read_body_json(response).await;
+        assert_eq!(served, raw);
+    }
+
+    #[actix_web::test]
+    async fn world_pages_carry_link_preview_tags() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let url = format!("https://{}.test/", test_support::unique("preview"));
+        let with_image = test_support::insert_app(&data, &url, "Cafe \"<Mars>\"").await;
+        sqlx::query("UPDATE apps SET image = '/images/mars.png', description = $2 WHERE id = $1")
+            .bind(with_image)
+            .bind("Red ".repeat(100))
+            .execute(&data.db)
+            .await
+            .unwrap();
+        let url = format!("https://{}.test/", test_support::unique("preview"));
+        let without_image = test_support::insert_app(&data, &url, "Plain").await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_world),
+        )
+        .await;
+        let page = |id: i32| {
+            let app = &app;
+            async move {
+                let request = TestRequest::get()
+                    .uri(&format!("/world/{}", id))
+                    .insert_header(("Accept", "text/html"))
+                    .to_request();
+                let body = actix_web::body::to_bytes(call_service(app, request).await.into_body())
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+        // Tera escapes `/` in the rendered values
+        let site = format!("https:&#x2F;&#x2F;{}", test_support::TEST_DOMAIN);
+
+        let html = page(with_image).await;
+        for tag in [
+            "<meta property=\"og:title\" content=\"Cafe &quot;&lt;Mars&gt;&quot;\">".to_string(),
+            format!(
+                "<meta property=\"og:url\" content=\"{}&#x2F;world&#x2F;{}\">",
+                site, with_image
+            ),
+            format!(
+                "<meta property=\"og:image\" content=\"{}&#x2F;images&#x2F;mars.png\">",
+                site
+            ),
+            "<meta name=\"twitter:card\" content=\"summary_large_image\">".to_string(),
+            format!(
+                "<meta name=\"twitter:image\" content=\"{}&#x2F;images&#x2F;mars.png\">",
+                site
+            ),
+        ] {
+            assert!(html.contains(&tag), "missing {}", tag);
+        }
+        // Descriptions are cut to 200 characters for previews
+        let description = format!(
+            "<meta property=\"og:description\" content=\"{}…\">",
+            "Red ".repeat(50)
+        );
+        assert!(html.contains(&description));
+
+        let html = page(without_image).await;
+        assert!(html.contains("<meta name=\"twitter:card\" content=\"summary\">"));
+        assert!(!html.contains("og:image"));
+        assert!(!html.contains("twitter:image"));
+    }
+
+    #[actix_web::test]
+    async fn refreshed_admin_token_expires_later() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let user = get_relay_by_id(0, &data).await.unwrap();
+        let keypair = RS256KeyPair::from_pem(&user.private_key_pem().unwrap()).unwrap();
+        let token = |hours: u64| {
+            let claims = Claims::create(jwt_simple::prelude::Duration::from_hours(hours));
+            keypair.sign(claims).unwrap()
+        };
+        let expiry = |token: &str| {
+            let claims = keypair
+                .public_key()
+                .verify_token::<NoCustomClaims>(token, None)
+                .unwrap();
+            claims.expires_at.unwrap().as_secs()
+        };
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(admin_refresh_token),
+        )
+        .await;
+        let refresh = |token: String| {
+            TestRequest::post()
+                .uri("/admin/refresh")
+                .cookie(Cookie::new("relay-admin-token", token))
+                .to_request()
+        };
+
+        // Less than half of the 24h lifetime left: renewed
+        let old = token(1);
+        let response = call_service(&app, refresh(old.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = response
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == "relay-admin-token")
+            .unwrap()
+            .into_owned();
+        let renewed: serde_json::Value = read_body_json(response).await;
+        assert_eq!(renewed["renewed"], true);
+        let new_expiry = expiry(cookie.value());
+        assert!(new_expiry >= expiry(&old) + 22 * 3600);
+        let reported: DateTime<Utc> =
+            serde_json::from_value(renewed["expires_at"].clone()).unwrap();
+        assert!(reported.timestamp().abs_diff(new_expiry as i64) <= 1);
+
+        // A fresh token is left alone
+        let fresh = token(24);
+        let response = call_service(&app, refresh(fresh.clone())).await;
+        assert!(response.response().cookies().next().is_none());
+        let kept: serde_json::Value = read_body_json(response).await;
+        assert_eq!(kept["renewed"], false);
+        let reported: DateTime<Utc> = serde_json::from_value(kept["expires_at"].clone()).unwrap();
+        assert_eq!(reported.timestamp() as u64, expiry(&fresh));
+
+        let response = call_service(&app, refresh("not-a-token".to_string())).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn index_of_a_relay_without_worlds_explains_how_to_get_listed() {
+        let Some((config, database)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(index),
+        )
+        .await;
+        let page = || async {
+            let response = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+            let body = actix_web::body::to_bytes(response.into_body())
+                .await
+                .unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        let html = page().await;
+        assert!(html.contains("No worlds yet"));
+        assert!(html.contains(&format!(
+            "https:&#x2F;&#x2F;{}&#x2F;beacon",
+            test_support::TEST_DOMAIN
+        )));
+
+        let app_id = test_support::insert_app(&data, "https://listed.test/", "Listed").await;
+        sqlx::query("UPDATE apps SET image = '/images/listed.png' WHERE id = $1")
+            .bind(app_id)
+            .execute(&data.db)
+            .await
+            .unwrap();
+        let html = page().await;
+        assert!(!html.contains("No worlds yet"));
+        assert!(html.contains("Listed"));
+
+        test_support::drop_database(&database).await;
+    }
+
+    #[actix_web::test]
+    async fn actor_and_webfinger_agree_on_the_relay_username() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(http_get_system_user)
+                .service(webfinger),
+        )
+        .await;
+
+        let request = TestRequest::get()
+            .uri("/relay")
+            .insert_header(("Accept", FEDERATION_CONTENT_TYPE))
+            .to_request();
+        let actor: serde_json::Value = call_and_read_body_json(&app, request).await;
+        assert_eq!(actor["preferredUsername"], RELAY_USERNAME);
+        // The document federated with activities says the same
+        let federated = get_system_user(&data)
+            .await
+            .unwrap()
+            .into_json(&data)
+            .await
+            .unwrap();
+        assert_eq!(federated.preferred_username, RELAY_USERNAME);
+
+        let resource = format!("acct:{}@{}", RELAY_USERNAME, test_support::TEST_DOMAIN);
+        let request = TestRequest::get()
+            .uri(&format!("/.well-known/webfinger?resource={}", resource))
+            .to_request();
+        let finger: serde_json::Value = call_and_read_body_json(&app, request).await;
+        assert_eq!(finger["subject"], resource.as_str());
+        let self_link = finger["links"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|link| link["rel"] == "self")
+            .unwrap();
+        assert_eq!(self_link["href"], actor["id"]);
+
+        let request = TestRequest::get()
+            .uri(&format!(
+                "/.well-known/webfinger?resource=acct:admin@{}",
+                test_support::TEST_DOMAIN
+            ))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn actor_json_declares_the_security_context() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(http_get_system_user),
+        )
+        .await;
+        let request = TestRequest::get()
+            .uri("/relay")
+            .insert_header(("Accept", FEDERATION_CONTENT_TYPE))
+            .to_request();
+        let actor: serde_json::Value = call_and_read_body_json(&app, request).await;
+        // publicKey is only defined by the security vocabulary
+        assert_eq!(
+            actor["@context"],
+            serde_json::json!([
+                "https://www.w3.org/ns/activitystreams",
+                "https://w3id.org/security/v1"
+            ])
+        );
+        assert!(actor["publicKey"]["publicKeyPem"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn browsers_get_a_relay_profile_page() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(http_get_system_user),
+        )
+        .await;
+        let request = TestRequest::get()
+            .uri("/relay")
+            .insert_header(("Accept", "text/html"))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Vary").unwrap(), "Accept");
+        assert_eq!(response.headers().get("Cache-Control").unwrap(), "no-cache");
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        let user = get_system_user(&data).await.unwrap();
+        let followers = get_followers_count(&data).await.unwrap();
+        assert!(html.contains(user.ap_id.inner().as_str()));
+        assert!(html.contains(&format!("{} follower", followers)));
+        assert!(html.contains("/relay/key.pem"));
+    }
+
+    fn beacon(url: &str) -> BeaconPayload {
+        serde_json::from_value(serde_json::json!({
+            "url": url,
+            "name": "World",
+            "description": "",
+            "active": true,
+        }))
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn resubmissions_that_only_change_spacing_are_unchanged() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let url = format!("https://{}.test/", test_support::unique("spacing"));
+        let submit = |description: &str, tags: &str| {
+            let mut payload = beacon(&url);
+            payload.description = description.to_string();
+            payload.tags = Some(tags.to_string());
+            payload
+        };
+
+        let Ok(BeaconOutcome::Created(app_id)) = ingest_beacon(
+            &data,
+            &submit("A  world\n\nwith rooms ", "vr,  social"),
+            SOURCE_BEACON,
+        )
+        .await
+        else {
+            panic!("beacon did not create a world");
+        };
+        // Stored as sent
+        let app = get_app_by_id(app_id, &data).await.unwrap();
+        assert_eq!(app.description, "A  world\n\nwith rooms ");
+        assert_eq!(app.tags, "vr,  social");
+
+        let outcome = ingest_beacon(
+            &data,
+            &submit("A world with rooms", "vr, social"),
+            SOURCE_BEACON,
+        )
+        .await;
+        assert!(matches!(outcome, Ok(BeaconOutcome::Unchanged(id)) if id == app_id));
+        let outcome = ingest_beacon(
+            &data,
+            &submit("A world with halls", "vr, social"),
+            SOURCE_BEACON,
+        )
+        .await;
+        assert!(matches!(outcome, Ok(BeaconOutcome::Updated(id)) if id == app_id));
+    }
+
+    #[actix_web::test]
+    async fn https_only_relays_refuse_urls_they_cannot_parse() {
+        let Some(config) = test_support::db_config_with(|state| {
+            state.require_https_beacons = true;
+        })
+        .await
+        else {
+            return;
+        };
+        let data = test_support::data(&config);
+        for url in ["http://insecure.test/", "not a url", "https://[broken/"] {
+            let outcome = ingest_beacon(&data, &beacon(url), SOURCE_BEACON).await;
+            assert!(
+                matches!(&outcome, Err(response) if response.status() == StatusCode::BAD_REQUEST),
+                "{} was not refused",
+                url
+            );
+        }
+    }
+
+    /// Registers a world for a site serving `verification_file`, or for an unreachable
+    /// site when it's `None`
+    async fn register_world(data: &Data<AppState>, verification_file: Option<String>) -> DbApp {
+        let url = match verification_file {
+            Some(file) => {
+                let files = HashMap::from([(DOMAIN_VERIFICATION_PATH.to_string(), file.into())]);
+                test_support::serve_site(Arc::new(Mutex::new(files)))
+            }
+            // Nothing listens on port 1
+            None => format!(
+                "http://127.{}.{}.1:1",
+                rand::random::<u8>() | 1,
+                rand::random::<u8>()
+            ),
+        };
+        let Ok(BeaconOutcome::Created(app_id)) =
+            ingest_beacon(data, &beacon(&url), SOURCE_BEACON).await
+        else {
+            panic!("beacon for {} did not create a world", url);
+        };
+        get_app_by_id(app_id, data).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn hide_mode_lists_only_verified_worlds() {
+        let Some(config) = test_support::db_config_with(|state| {
+            state.domain_verification = DomainVerification::Hide;
+        })
+        .await
+        else {
+            return;
+        };
+        let data = test_support::data(&config);
+
+        let listed = format!(
+            "# relays allowed to index this site\n{}\n",
+            test_support::TEST_DOMAIN
+        );
+        let verified = register_world(&data, Some(listed.clone())).await;
+        assert!(verified.visible);
+        assert!(verified.domain_verified_at.is_some());
+
+        let unverified = register_world(&data, Some("other-relay.test\n".to_string())).await;
+        assert!(!unverified.visible);
+        assert!(unverified.domain_verified_at.is_none());
+
+        let unreachable = register_world(&data, None).await;
+        assert!(!unreachable.visible);
+        assert!(unreachable.domain_verified_at.is_none());
+
+        // Only the start of an oversized file would be read, so it doesn't count
+        let oversized =
+            register_world(&data, Some(format!("{}{}", "#".repeat(32 * 1024), listed))).await;
+        assert!(!oversized.visible);
+
+        // Approving a held world lists it
+        assert!(toggle_app_visibility(unverified.id, &data).await.unwrap());
+        assert!(get_app_by_id(unverified.id, &data).await.unwrap().visible);
+    }
+
+    #[actix_web::test]
+    async fn worlds_are_searched_and_filtered_before_paging() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let token = test_support::unique("search");
+        let world = |n: &str| format!("https://{}-{}.test/", n, token);
+        test_support::insert_app(&data, &world("one"), &format!("{} One", token)).await;
+        test_support::insert_app(&data, &world("two"), &format!("{} Two", token)).await;
+        let hidden = test_support::insert_app(&data, &world("hidden"), &token).await;
+        toggle_app_visibility(hidden, &data).await.unwrap();
+        let adult = test_support::insert_app(&data, &world("adult"), &token).await;
+        sqlx::query("UPDATE apps SET is_adult = true WHERE id = $1")
+            .bind(adult)
+            .execute(&data.db)
+            .await
+            .unwrap();
+        // Matches by domain rather than by name
+        test_support::insert_app(&data, &world("three"), "Unrelated").await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_worlds),
+        )
+        .await;
+
+        let mut listed = Vec::new();
+        for page in 1..=3 {
+            let uri = format!(
+                "/worlds?q={}&per_page=1&page={}",
+                token.to_uppercase(),
+                page
+            );
+            let response = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = actix_web::body::to_bytes(response.into_body())
+                .await
+                .unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            assert!(body.contains(&format!("Page {} of 3", page)), "{}", body);
+            listed.extend(
+                ["one", "two", "three", "hidden", "adult"]
+                    .into_iter()
+                    .filter(|n| body.contains(&format!("{}-{}.test", n, token))),
+            );
+        }
+        listed.sort();
+        assert_eq!(listed, ["one", "three", "two"]);
+    }
+
+    fn png_bytes(side: u32) -> Vec<u8> {
+        image_bytes(side, image::ImageFormat::Png)
+    }
+
+    fn image_bytes(side: u32, format: image::ImageFormat) -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(side, side)
+            .write_to(&mut bytes, format)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn downscaled_images_keep_their_format() {
+        for format in [
+            image::ImageFormat::Png,
+            image::ImageFormat::Jpeg,
+            image::ImageFormat::Gif,
+            image::ImageFormat::WebP,
+        ] {
+            let small = image_bytes(8, format);
+            assert_eq!(downscale_image(&small, 8), Some(small.clone()));
+            let downscaled = downscale_image(&small, 4).unwrap();
+            assert_eq!(image::guess_format(&downscaled).unwrap(), format);
+            let decoded = image::load_from_memory(&downscaled).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (4, 4));
+        }
+        assert_eq!(downscale_image(b"<svg/>", 1), None);
+    }
+
+    #[test]
+    fn images_beyond_the_decode_limit_are_refused() {
+        let mut tall = std::io::Cursor::new(Vec::new());
+        image::GrayImage::new(1, MAX_DECODED_DIMENSION + 1)
+            .write_to(&mut tall, image::ImageFormat::Png)
+            .unwrap();
+        assert_eq!(downscale_image(tall.get_ref(), 2048), None);
+    }
+
+    #[actix_web::test]
+    async fn only_raster_remote_images_are_stored() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        data.features.write().unwrap().proxy_remote_images = true;
+        let files = HashMap::from([
+            ("/icon.png".to_string(), png_bytes(4)),
+            (
+                "/page.png".to_string(),
+                b"<html><script>alert(1)</script></html>".to_vec(),
+            ),
+            ("/huge.png".to_string(), vec![0; MAX_REMOTE_IMAGE_BYTES + 1]),
+        ]);
+        let site = test_support::serve_site(Arc::new(Mutex::new(files)));
+        let app_id = test_support::insert_app(&data, &site, "Remote").await;
+        let mut app = get_app_by_id(app_id, &data).await.unwrap();
+
+        for rejected in ["page.png", "huge.png"] {
+            app.image = format!("{}/{}", site, rejected);
+            localize_remote_image(&data, &mut app).await;
+            assert_eq!(app.image, format!("{}/{}", site, rejected));
+        }
+
+        app.image = format!("{}/icon.png", site);
+        localize_remote_image(&data, &mut app).await;
+        let stored = stored_file(&app.image);
+        assert!(stored.starts_with("images/remote-"));
+        assert_eq!(std::fs::read(&stored).unwrap(), png_bytes(4));
+        std::fs::remove_file(stored).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn strict_follower_gets_deliveries_only_once_the_accept_arrives() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        data.features.write().unwrap().strict_follows = true;
+        let files: SiteFiles = Arc::new(Mutex::new(HashMap::new()));
+        let site = test_support::serve_peer(files.clone());
+        let actor = format!("{}/{}", site, test_support::unique("relay"));
+        let inbox = format!("{}/inbox", actor);
+        // Nothing listens on port 1, so the first Accept can't be delivered
+        let relay = serde_json::json!({
+            "id": actor,
+            "type": "Service",
+            "preferredUsername": "peer",
+            "name": "peer",
+            "inbox": "http://localhost:1/inbox",
+            "outbox": format!("{}/outbox", actor),
+            "publicKey": {"id": actor, "owner": actor, "publicKeyPem": "unused"},
+        });
+        files.lock().unwrap().insert(
+            Url::parse(&actor).unwrap().path().to_string(),
+            relay.to_string().into(),
+        );
+        let system_user = get_system_user(&data).await.unwrap();
+        let follow = Follow::new(
+            Url::parse(&actor).unwrap().into(),
+            system_user.ap_id.clone(),
+            Url::parse(&format!("{}/follow", actor)).unwrap(),
+        );
+        let delivered_to_peer =
+            |inboxes: Vec<Url>| inboxes.iter().any(|url| url.as_str().starts_with(&actor));
+
+        assert!(follow.clone().receive(&data).await.is_err());
+        let follower_id = get_relay_follower_id_by_ap_id(&data, &actor).await.unwrap();
+        assert!(!is_relay_follower(&data, follower_id).await.unwrap());
+        assert!(!delivered_to_peer(get_broadcast_inboxes(&data).await));
+
+        // Once the peer's inbox answers, the Accept goes through and the follow counts
+        sqlx::query("UPDATE relays SET inbox = $1 WHERE activitypub_id = $2")
+            .bind(&inbox)
+            .bind(&actor)
+            .execute(&data.db)
+            .await
+            .unwrap();
+        files
+            .lock()
+            .unwrap()
+            .insert(Url::parse(&inbox).unwrap().path().to_string(), "ok".into());
+        follow.receive(&data).await.unwrap();
+        assert!(is_relay_follower(&data, follower_id).await.unwrap());
+        assert!(delivered_to_peer(get_broadcast_inboxes(&data).await));
+
+        // The Accept is kept under an id that can be fetched back from this relay
+        let accept: (String, Option<serde_json::Value>) = sqlx::query_as(
+            "SELECT activitypub_id, raw FROM activities WHERE obj = $1 AND kind = 'Accept' \
+             ORDER BY id DESC LIMIT 1",
+        )
+        .bind(format!("{}/follow", actor))
+        .fetch_one(&data.db)
+        .await
+        .unwrap();
+        assert!(accept
+            .0
+            .starts_with(&format!("{}/activities/", system_user.ap_id.inner())));
+        assert_eq!(accept.1.unwrap()["id"], accept.0);
+    }
+
+    #[actix_web::test]
+    async fn reach_counts_the_inboxes_a_broadcast_posts_to() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        // Two followers behind one inbox, as on a multi-user instance, and one on its own
+        for (actor, inbox) in [
+            ("https://shared.test/a", "https://shared.test/inbox"),
+            ("https://shared.test/b", "https://shared.test/inbox"),
+            ("https://alone.test/relay", "https://alone.test/relay/inbox"),
+        ] {
+            create_relay(&data, "peer", actor, inbox, inbox, "unused")
+                .await
+                .unwrap();
+            let follower_id = get_relay_follower_id_by_ap_id(&data, actor).await.unwrap();
+            add_follower_to_relay(&data, follower_id).await.unwrap();
+        }
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(admin_reach),
+        )
+        .await;
+        let request = TestRequest::get()
+            .uri("/admin/reach")
+            .cookie(admin_cookie(&data).await)
+            .to_request();
+        let reach: serde_json::Value = call_and_read_body_json(&app, request).await;
+        assert_eq!(reach["followers"], 3);
+        assert_eq!(reach["inboxes"], 2);
+        assert_eq!(get_broadcast_inboxes(&data).await.len(), 2);
+        let shared = reach["hosts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|host| host["host"] == "shared.test")
+            .unwrap();
+        assert_eq!(
+            (shared["followers"].as_u64(), shared["inboxes"].as_u64()),
+            (Some(2), Some(1))
+        );
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn admin_changes_federate_only_for_local_worlds() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let local = test_support::insert_app(&data, "https://local.test", "Local").await;
+        let remote = test_support::insert_app(&data, "https://remote.test", "Remote").await;
+        sqlx::query(
+            "UPDATE apps SET activitypub_id = 'https://peer.test/relay/beacon/1' WHERE id = $1",
+        )
+        .bind(remote)
+        .execute(&data.db)
+        .await
+        .unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(admin_toggle_adult)
+                .service(admin_edit_world),
+        )
+        .await;
+        let cookie = admin_cookie(&data).await;
+
+        for id in [local, remote] {
+            let request = TestRequest::post()
+                .uri("/admin/toggle-adult")
+                .cookie(cookie.clone())
+                .set_form([("app_id", id.to_string())])
+                .to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::FOUND);
+            assert_eq!(response.headers().get("Location").unwrap(), "/admin");
+            assert!(get_app_by_id(id, &data).await.unwrap().adult);
+
+            let request = TestRequest::post()
+                .uri(&format!("/admin/edit/{}", id))
+                .cookie(cookie.clone())
+                .set_form([
+                    ("name", "Edited"),
+                    ("description", ""),
+                    ("image", ""),
+                    ("tags", ""),
+                ])
+                .to_request();
+            assert_eq!(
+                call_service(&app, request).await.status(),
+                StatusCode::FOUND
+            );
+        }
+        let updates = |id: i32| {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM activities ac JOIN apps a ON a.activitypub_id = ac.obj \
+                 WHERE a.id = $1 AND ac.kind = 'Update'",
+            )
+            .bind(id)
+            .fetch_one(&data.db)
+        };
+        assert_eq!(updates(local).await.unwrap(), 2);
+        assert_eq!(updates(remote).await.unwrap(), 0);
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn beacons_and_catalog_pages_share_the_app_context() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let app_id = test_support::insert_app(&data, "https://world.test", "World").await;
+        let ap_id = format!(
+            "https://{}/relay/beacon/{}",
+            test_support::TEST_DOMAIN,
+            app_id
+        );
+        sqlx::query("UPDATE apps SET activitypub_id = $1 WHERE id = $2")
+            .bind(&ap_id)
+            .bind(app_id)
+            .execute(&data.db)
+            .await
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_beacon)
+                .service(get_relay_catalog),
+        )
+        .await;
+        let fetch = |uri: String| {
+            TestRequest::get()
+                .uri(&uri)
+                .insert_header(("Accept", FEDERATION_CONTENT_TYPE))
+                .to_request()
+        };
+
+        let mut beacon: serde_json::Value =
+            call_and_read_body_json(&app, fetch(format!("/relay/beacon/{}", app_id))).await;
+        let page: serde_json::Value =
+            call_and_read_body_json(&app, fetch("/relay/catalog?page=1".to_string())).await;
+        assert_eq!(beacon["@context"], app_context(&data));
+        assert_eq!(page["@context"], app_context(&data));
+        beacon.as_object_mut().unwrap().remove("@context");
+        assert_eq!(page["orderedItems"][0], beacon);
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn browsers_are_redirected_from_beacon_ids_to_the_world_page() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let app_id = test_support::insert_app(&data, "https://redirect.test", "Redirect").await;
+        let system_user = get_system_user(&data).await.unwrap();
+        let ap_id = format!("{}/beacon/{}", system_user.ap_id.inner(), app_id);
+        sqlx::query("UPDATE apps SET activitypub_id = $1 WHERE id = $2")
+            .bind(&ap_id)
+            .bind(app_id)
+            .execute(&data.db)
+            .await
+            .unwrap();
+        let slug = test_support::unique("redirect");
+        set_app_slug(&data, app_id, &slug).await.unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_beacon),
+        )
+        .await;
+        let fetch = |accept: &str| {
+            TestRequest::get()
+                .uri(&format!("/relay/beacon/{}", app_id))
+                .insert_header(("Accept", accept))
+                .to_request()
+        };
+
+        let response = call_service(&app, fetch("text/html,application/xhtml+xml")).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers().get("Location").unwrap(),
+            format!("/world/{}", slug).as_str()
+        );
+        // AP clients get the object, even when they also accept HTML
+        for accept in [
+            FEDERATION_CONTENT_TYPE,
+            "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"",
+            "text/html, application/activity+json",
+        ] {
+            let response = call_service(&app, fetch(accept)).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", accept);
+            let object: serde_json::Value = read_body_json(response).await;
+            assert_eq!(object["id"], ap_id.as_str(), "{}", accept);
+        }
+    }
+
+    #[actix_web::test]
+    async fn include_inactive_lists_inactive_worlds_but_never_hidden_ones() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let tag = test_support::unique("listed");
+        let mut names = Vec::new();
+        for (active, visible) in [(true, true), (false, true), (true, false), (false, false)] {
+            let name = format!("{}-{}-{}", tag, active, visible);
+            let url = format!("https://{}.test", name);
+            let id = test_support::insert_app(&data, &url, &name).await;
+            sqlx::query("UPDATE apps SET is_active = $1, visible = $2 WHERE id = $3")
+                .bind(active)
+                .bind(visible)
+                .bind(id)
+                .execute(&data.db)
+                .await
+                .unwrap();
+            names.push(((active, visible), name));
+        }
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_apps),
+        )
+        .await;
+
+        for include_inactive in [None, Some(false), Some(true)] {
+            let mut uri = format!("/apps?q={}", tag);
+            if let Some(include_inactive) = include_inactive {
+                uri.push_str(&format!("&include_inactive={}", include_inactive));
+            }
+            let body = call_and_read_body(&app, TestRequest::get().uri(&uri).to_request()).await;
+            let html = String::from_utf8(body.to_vec()).unwrap();
+            for ((active, visible), name) in &names {
+                let listed = *visible && (*active || include_inactive == Some(true));
+                assert_eq!(html.contains(name.as_str()), listed, "{} {}", uri, name);
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn inactive_worlds_are_left_out_of_public_listings() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let active = test_support::insert_app(&data, "https://active.test", "Awake World").await;
+        let inactive =
+            test_support::insert_app(&data, "https://inactive.test", "Dormant World").await;
+        sqlx::query("UPDATE apps SET image = '/images/world.png'")
+            .execute(&data.db)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE apps SET is_active = FALSE WHERE id = $1")
+            .bind(inactive)
+            .execute(&data.db)
+            .await
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(index)
+                .service(get_apps)
+                .service(get_relay_catalog),
+        )
+        .await;
+        let page = |uri: &str| {
+            let request = TestRequest::get().uri(uri).to_request();
+            let app = &app;
+            async move {
+                let body = call_and_read_body(app, request).await;
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        for uri in ["/", "/apps"] {
+            let html = page(uri).await;
+            assert!(html.contains("Awake World"), "{}", uri);
+            assert!(!html.contains("Dormant World"), "{}", uri);
+        }
+        let request = TestRequest::get()
+            .uri("/relay/catalog?page=1")
+            .insert_header(("Accept", FEDERATION_CONTENT_TYPE))
+            .to_request();
+        let catalog: serde_json::Value = call_and_read_body_json(&app, request).await;
+        let ids: Vec<i64> = catalog["orderedItems"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["appId"].as_i64().unwrap())
+            .collect();
+        assert_eq!(ids, [active as i64]);
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn toggled_features_take_effect_without_a_restart() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let adult = test_support::insert_app(&data, "https://adult.test", "Adult World").await;
+        sqlx::query("UPDATE apps SET is_adult = TRUE WHERE id = $1")
+            .bind(adult)
+            .execute(&data.db)
+            .await
+            .unwrap();
+        let cookie = admin_cookie(&data).await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_apps)
+                .service(admin_set_feature),
+        )
+        .await;
+        let listed = || async {
+            let request = TestRequest::get().uri("/apps?adult=true").to_request();
+            let body = call_and_read_body(&app, request).await;
+            String::from_utf8(body.to_vec()).unwrap().contains("Adult World")
+        };
+        let toggle = |name: &str, enabled: bool| {
+            TestRequest::post()
+                .uri("/admin/features")
+                .cookie(cookie.clone())
+                .set_form(serde_json::json!({ "name": name, "enabled": enabled }))
+                .to_request()
+        };
+
+        assert!(!data.features().show_adult_content);
+        assert!(!listed().await);
+        let response = call_service(&app, toggle("show_adult_content", true)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(listed().await);
+        let response = call_service(&app, toggle("show_adult_content", false)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!listed().await);
+        // Startup-only flags can't be flipped here
+        let response = call_service(&app, toggle("debug", true)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(!data.features().debug);
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn activity_log_filters_narrow_the_results() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let activities = [
+            ("a.test", ActivityKind::Create, "2026-01-10", "a-create"),
+            ("a.test", ActivityKind::Update, "2026-01-20", "a-update"),
+            ("b.test", ActivityKind::Create, "2026-01-20", "b-create"),
+            ("b.test", ActivityKind::Update, "2026-02-01", "b-update"),
+        ];
+        for (host, kind, day, object) in activities {
+            let id = create_activity(
+                &data,
+                format!("https://{}/activities/{}", host, object),
+                &format!("https://{}/relay", host),
+                &format!("https://{}/{}", host, object),
+                kind,
+            )
+            .await
+            .unwrap();
+            sqlx::query("UPDATE activities SET created_at = $1::DATE + TIME '12:00' WHERE id = $2")
+                .bind(day)
+                .bind(id)
+                .execute(&data.db)
+                .await
+                .unwrap();
+        }
+        let cookie = admin_cookie(&data).await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(admin_activities),
+        )
+        .await;
+        let listed = |query: &str| {
+            let request = TestRequest::get()
+                .uri(&format!("/admin/activities?{}", query))
+                .cookie(cookie.clone())
+                .to_request();
+            let app = &app;
+            async move {
+                let body = call_and_read_body(app, request).await;
+                let html = String::from_utf8(body.to_vec()).unwrap();
+                activities
+                    .iter()
+                    .map(|(_, _, _, object)| *object)
+                    .filter(|object| html.contains(object))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(listed("").await.len(), 4);
+        assert_eq!(listed("kind=Create").await, ["a-create", "b-create"]);
+        assert_eq!(listed("actor_host=B.test").await, ["b-create", "b-update"]);
+        // The until day is included
+        let listed_days = listed("since=2026-01-15&until=2026-01-20").await;
+        assert_eq!(listed_days, ["a-update", "b-create"]);
+        assert_eq!(listed("kind=Update&actor_host=a.test").await, ["a-update"]);
+        // Blank fields don't filter
+        assert_eq!(listed("kind=&actor_host=&since=&until=").await.len(), 4);
+
+        for query in ["kind=Like", "since=yesterday", "until=2026-13-01"] {
+            let request = TestRequest::get()
+                .uri(&format!("/admin/activities?{}", query))
+                .cookie(cookie.clone())
+                .to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+        }
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn apps_are_ordered_by_each_sort_mode() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let worlds = [
+            ("https://zeta.test", "Cedar", "2026-01-03"),
+            ("https://alpha.test", "Birch", "2026-01-01"),
+            ("https://alpha.test/b", "Aster", "2026-01-05"),
+            ("https://mid.test", "Dahlia", "2026-01-02"),
+        ];
+        for (url, name, day) in worlds {
+            let id = test_support::insert_app(&data, url, name).await;
+            sqlx::query("UPDATE apps SET created_at = $1::DATE WHERE id = $2")
+                .bind(day)
+                .bind(id)
+                .execute(&data.db)
+                .await
+                .unwrap();
+        }
+        let now = time::OffsetDateTime::now_utc().unix_timestamp() * 1000;
+        for (url, people) in [("https://mid.test", 3), ("https://zeta.test", 1)] {
+            let sessions = (0..people)
+                .map(|i| crate::SessionInfo {
+                    session_id: format!("{}-{}", url, i),
+                    timestamp: now,
+                })
+                .collect();
+            data.sessions
+                .write()
+                .unwrap()
+                .insert(url.to_string(), sessions);
+        }
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_apps),
+        )
+        .await;
+        let order = |query: &str| {
+            let request = TestRequest::get()
+                .uri(&format!("/apps{}", query))
+                .to_request();
+            let app = &app;
+            async move {
+                let body = call_and_read_body(app, request).await;
+                let html = String::from_utf8(body.to_vec()).unwrap();
+                let mut names: Vec<&str> = worlds.iter().map(|world| world.1).collect();
+                names.sort_by_key(|name| html.find(name).unwrap());
+                names
+            }
+        };
+
+        let by_name = ["Aster", "Birch", "Dahlia", "Cedar"];
+        assert_eq!(order("?sort=name").await, by_name);
+        let newest = ["Aster", "Birch", "Cedar", "Dahlia"];
+        assert_eq!(order("?sort=newest").await, newest);
+        let oldest = ["Birch", "Aster", "Dahlia", "Cedar"];
+        assert_eq!(order("?sort=oldest").await, oldest);
+        // Domains rank by everyone in any of their worlds
+        let popular = ["Dahlia", "Cedar", "Aster", "Birch"];
+        assert_eq!(order("?sort=popular").await, popular);
+        // Without a valid sort the APPS_DEFAULT_SORT order is used
+        assert_eq!(data.apps_default_sort, AppsSort::Name);
+        assert_eq!(order("").await, by_name);
+        assert_eq!(order("?sort=random").await, by_name);
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn broken_admin_templates_render_no_context() {
+        let Some(config) = test_support::db_config_with(|state| {
+            // Renders the whole context, then fails on a variable that isn't in it
+            state
+                .tera
+                .add_raw_template(
+                    "admin_features.default.html",
+                    "{{ __tera_context }}{{ missing.field }}",
+                )
+                .unwrap();
+        })
+        .await
+        else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(admin_features_page),
+        )
+        .await;
+        let request = TestRequest::get()
+            .uri("/admin/features")
+            .cookie(admin_cookie(&data).await)
+            .to_request();
+
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        assert_eq!(
+            body,
+            "Failed to render the admin page. Check the relay logs for details."
+        );
+    }
+
+    #[actix_web::test]
+    async fn world_paths_resolve_slugs_before_ids() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let by_id = test_support::unique("by-id");
+        let numbered = test_support::unique("numbered");
+        let target = test_support::insert_app(&data, "https://by-id.test", &by_id).await;
+        let slugged = test_support::insert_app(&data, "https://numbered.test", &numbered).await;
+        // A world named after a number gets that number as its slug
+        set_app_slug(&data, slugged, &target.to_string())
+            .await
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(get_world),
+        )
+        .await;
+        let page = |id: i32| {
+            let request = TestRequest::get()
+                .uri(&format!("/world/{}", id))
+                .insert_header(("Accept", "text/html"))
+                .to_request();
+            call_and_read_body(&app, request)
+        };
+
+        let body = String::from_utf8(page(target).await.to_vec()).unwrap();
+        assert!(body.contains(&numbered) && !body.contains(&by_id));
+        let body = String::from_utf8(page(slugged).await.to_vec()).unwrap();
+        assert!(body.contains(&numbered));
+    }
+
+    #[actix_web::test]
+    async fn beacons_with_an_unknown_protocol_are_refused() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let url = format!("https://{}.test", test_support::unique("protocol"));
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(new_beacon),
+        )
+        .await;
+        let submit = |protocol: &str| {
+            TestRequest::put()
+                .uri("/beacon")
+                .set_json(serde_json::json!({
+                    "url": url,
+                    "name": "Protocol",
+                    "description": "",
+                    "active": true,
+                    "protocol": protocol,
+                }))
+                .to_request()
+        };
+        let stored = || {
+            sqlx::query_scalar::<_, String>("SELECT protocol FROM apps WHERE url = $1")
+                .bind(&url)
+                .fetch_optional(&data.db)
+        };
+
+        let response = call_service(&app, submit("https")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        assert_eq!(
+            body,
+            "Unknown protocol 'https', expected one of: webxr, deeplink, native"
+        );
+        assert_eq!(stored().await.unwrap(), None);
+
+        let response = call_service(&app, submit("DeepLink")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(stored().await.unwrap().as_deref(), Some("deeplink"));
+    }
+
+    #[actix_web::test]
+    async fn beacon_origins_are_checked_against_the_world_host() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let host = format!("{}.test", test_support::unique("origin"));
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(new_beacon),
+        )
+        .await;
+        let submit = |url: String, origin: &str| {
+            TestRequest::put()
+                .uri("/beacon")
+                .insert_header(("Origin", origin))
+                .set_json(serde_json::json!({
+                    "url": url,
+                    "name": "Origin",
+                    "description": "",
+                    "active": true,
+                }))
+                .to_request()
+        };
+
+        // A bare address is checked like a full one
+        let response = call_service(&app, submit(host.clone(), "https://other.test")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let url = format!("HTTPS://WWW.{}/lobby/?utm_source=feed", host.to_uppercase());
+        let response = call_service(&app, submit(url, &format!("https://{}", host))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn registered_worlds_share_one_id_across_routes_and_ap_id() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let name = test_support::unique("routed");
+        let url = format!("https://{}.test", name);
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(new_beacon)
+                .service(get_app)
+                .service(get_beacon),
+        )
+        .await;
+        let payload = serde_json::json!({
+            "url": url,
+            "name": name,
+            "description": "",
+            "active": true,
+        });
+        let request = TestRequest::put()
+            .uri("/beacon")
+            .set_json(payload)
+            .to_request();
+        assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+        let (id, ap_id): (i32, String) =
+            sqlx::query_as("SELECT id, activitypub_id FROM apps WHERE url = $1")
+                .bind(&url)
+                .fetch_one(&data.db)
+                .await
+                .unwrap();
+
+        let system_user = get_system_user(&data).await.unwrap();
+        assert_eq!(
+            ap_id,
+            format!("{}/beacon/{}", system_user.ap_id.inner(), id)
+        );
+        let request = TestRequest::get()
+            .uri(&format!("/app/{}", id))
+            .insert_header(("Accept", "text/html"))
+            .to_request();
+        let page = String::from_utf8(call_and_read_body(&app, request).await.to_vec()).unwrap();
+        assert!(page.contains(&name));
+        let request = TestRequest::get()
+            .uri(&format!("/relay/beacon/{}", id))
+            .insert_header(("Accept", FEDERATION_CONTENT_TYPE))
+            .to_request();
+        let object: serde_json::Value = call_and_read_body_json(&app, request).await;
+        assert_eq!(object["id"], ap_id);
+        assert_eq!(object["appId"], id);
+        assert_eq!(object["name"], name);
+    }
+
+    #[test]
+    fn beacon_urls_are_canonicalized() {
+        for (url, canonical) in [
+            (
+                "HTTPS://World.Example.COM/Lobby",
+                "https://world.example.com/Lobby",
+            ),
+            ("world.test", "https://world.test"),
+            ("https://world.test:443/", "https://world.test"),
+            ("http://world.test:80/rooms", "http://world.test/rooms"),
+            ("https://world.test:8443/", "https://world.test:8443"),
+            ("https://world.test/rooms/", "https://world.test/rooms"),
+            ("https://world.test/#lobby", "https://world.test#lobby"),
+            (
+                "https://world.test/rooms#lobby",
+                "https://world.test/rooms#lobby",
+            ),
+            (
+                "https://world.test/?utm_source=feed&ref=home&gclid=1&FBCLID=2&room=3",
+                "https://world.test?ref=home&room=3",
+            ),
+            (
+                "https://world.test/?utm_campaign=launch",
+                "https://world.test",
+            ),
+        ] {
+            assert_eq!(canonicalize_beacon_url(url), canonical, "{}", url);
+        }
+    }
+
+    #[actix_web::test]
+    async fn each_local_write_path_records_its_source() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let url = format!("https://{}.test/", test_support::unique("sourced"));
+        async fn last_source(data: &Data<AppState>, id: i32) -> String {
+            get_app_by_id(id, data).await.unwrap().last_source.unwrap()
+        }
+
+        let Ok(BeaconOutcome::Created(app_id)) =
+            ingest_beacon(&data, &beacon(&url), SOURCE_BEACON).await
+        else {
+            panic!("beacon did not create a world");
+        };
+        assert_eq!(last_source(&data, app_id).await, SOURCE_BEACON);
+
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(admin_edit_world)
+                .service(update_world),
+        )
+        .await;
+        let request = TestRequest::post()
+            .uri(&format!("/admin/edit/{}", app_id))
+            .cookie(admin_cookie(&data).await)
+            .set_form([
+                ("name", "Admin"),
+                ("description", ""),
+                ("image", ""),
+                ("tags", ""),
+            ])
+            .to_request();
+        assert_eq!(call_service(&app, request).await.status(), StatusCode::FOUND);
+        assert_eq!(last_source(&data, app_id).await, SOURCE_ADMIN);
+
+        let token = create_owner_token(&data, app_id, &app_id.to_string())
+            .await
+            .unwrap();
+        let request = TestRequest::post()
+            .uri(&format!("/world/{}/update", app_id))
+            .cookie(Cookie::new("relay-owner-token", token))
+            .set_json(serde_json::json!({ "name": "Owner", "description": "" }))
+            .to_request();
+        assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+        assert_eq!(last_source(&data, app_id).await, SOURCE_OWNER);
+
+        let mut payload = beacon(&url);
+        payload.name = "Beacon".to_string();
+        let outcome = ingest_beacon(&data, &payload, SOURCE_BEACON).await;
+        assert!(matches!(outcome, Ok(BeaconOutcome::Updated(id)) if id == app_id));
+        assert_eq!(last_source(&data, app_id).await, SOURCE_BEACON);
+    }
+
+    #[actix_web::test]
+    async fn follows_are_recorded_once_delivered() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let files: SiteFiles = Arc::new(Mutex::new(HashMap::new()));
+        let site = test_support::serve_peer(files.clone());
+        let actor = format!("{}/{}", site, test_support::unique("relay"));
+        let inbox = format!("{}/inbox", actor);
+        files
+            .lock()
+            .unwrap()
+            .insert(Url::parse(&inbox).unwrap().path().to_string(), "ok".into());
+        let peer = |inbox: &str| {
+            DbRelay::new(
+                "peer".to_string(),
+                Url::parse(&actor).unwrap().into(),
+                Url::parse(inbox).unwrap(),
+                Url::parse(&format!("{}/outbox", actor)).unwrap(),
+                "unused".to_string(),
+                None,
+                false,
+            )
+        };
+        let system_user = get_system_user(&data).await.unwrap();
+
+        // Nothing listens on port 1, so the Follow isn't delivered and isn't recorded
+        let result = system_user
+            .follow_relay(&peer("http://localhost:1/inbox"), &data)
+            .await;
+        assert!(result.is_err());
+        let peer_id = get_relay_follower_id_by_ap_id(&data, &actor).await.unwrap();
+        assert!(!is_following_relay(&data, peer_id).await.unwrap());
+
+        // So the admin can try again
+        system_user.follow_relay(&peer(&inbox), &data).await.unwrap();
+        assert!(is_following_relay(&data, peer_id).await.unwrap());
+        let result = system_user.follow_relay(&peer(&inbox), &data).await;
+        assert!(
+            matches!(result, Err(crate::activitypub::error::Error::Duplicate(_))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[actix_web::test]
+    async fn signed_inbox_deliveries_record_relay_activity() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let files: SiteFiles = Arc::new(Mutex::new(HashMap::new()));
+        let site = test_support::serve_peer(files.clone());
+        let actor = format!("{}/{}", site, test_support::unique("relay"));
+        // The peer signs with a copy of the system user's keys, since generating keys is slow
+        let system_user = get_system_user(&data).await.unwrap();
+        let peer = DbRelay::new(
+            "peer".to_string(),
+            Url::parse(&actor).unwrap().into(),
+            Url::parse(&format!("{}/inbox", actor)).unwrap(),
+            Url::parse(&format!("{}/outbox", actor)).unwrap(),
+            system_user.public_key_pem().to_string(),
+            system_user.private_key_pem(),
+            false,
+        );
+        create_relay(
+            &data,
+            "peer",
+            &actor,
+            peer.inbox.as_str(),
+            peer.outbox.as_str(),
+            peer.public_key_pem(),
+        )
+        .await
+        .unwrap();
+        let last_activity = || async {
+            get_relay_by_ap_id(actor.clone(), &data)
+                .await
+                .unwrap()
+                .unwrap()
+                .last_activity_at
+        };
+        assert!(last_activity().await.is_none());
+
+        let inbox_config = config.clone();
+        let server = actix_web::HttpServer::new(move || {
+            App::new()
+                .wrap(FederationMiddleware::new(inbox_config.clone()))
+                .service(http_post_shared_inbox)
+        })
+        .workers(1)
+        .disable_signals()
+        .shutdown_timeout(0)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let inbox = format!("http://localhost:{}/inbox", server.addrs()[0].port());
+        actix_web::rt::spawn(server.run());
+        // An Update for an object the peer no longer has is accepted and otherwise ignored
+        let update = Update {
+            actor: peer.ap_id.clone(),
+            object: Url::parse(&format!("{}/beacon/gone", actor)).unwrap().into(),
+            kind: Default::default(),
+            id: Url::parse(&format!(
+                "{}/activities/{}",
+                actor,
+                test_support::unique("update")
+            ))
+            .unwrap(),
+            published: None,
+        };
+        peer.send(update, vec![Url::parse(&inbox).unwrap()], false, &data)
+            .await
+            .unwrap();
+        assert!(last_activity().await.is_some());
+    }
+
+    #[actix_web::test]
+    async fn admin_ingested_worlds_are_listed_and_federated() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        // A follower whose inbox records what it's sent
+        let received: Arc<Mutex<Vec<serde_json::Value>>> = Arc::new(Mutex::new(Vec::new()));
+        let inbox_received = received.clone();
+        let server = actix_web::HttpServer::new(move || {
+            let received = inbox_received.clone();
+            App::new().default_service(web::to(move |body: Bytes| {
+                let received = received.clone();
+                async move {
+                    received
+                        .lock()
+                        .unwrap()
+                        .push(serde_json::from_slice(&body).unwrap());
+                    HttpResponse::Accepted().finish()
+                }
+            }))
+        })
+        .workers(1)
+        .disable_signals()
+        .shutdown_timeout(0)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let actor = format!("http://localhost:{}/relay", server.addrs()[0].port());
+        actix_web::rt::spawn(server.run());
+        let inbox = format!("{}/inbox", actor);
+        create_relay(&data, "peer", &actor, &inbox, &inbox, "unused")
+            .await
+            .unwrap();
+        let follower_id = get_relay_follower_id_by_ap_id(&data, &actor).await.unwrap();
+        add_follower_to_relay(&data, follower_id).await.unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(admin_ingest)
+                .service(get_apps),
+        )
+        .await;
+
+        let request = TestRequest::post()
+            .uri("/admin/ingest")
+            .cookie(admin_cookie(&data).await)
+            .set_json(serde_json::json!({
+                "url": "https://ingested.test",
+                "name": "Ingested World",
+                "description": "",
+                "active": true,
+            }))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ingested: serde_json::Value = read_body_json(response).await;
+
+        let body = call_and_read_body(&app, TestRequest::get().uri("/apps").to_request()).await;
+        assert!(String::from_utf8(body.to_vec())
+            .unwrap()
+            .contains("Ingested World"));
+        let app = get_app_by_id(ingested["id"].as_i64().unwrap() as i32, &data)
+            .await
+            .unwrap();
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["type"], "Create");
+        assert_eq!(received[0]["object"], app.ap_id.inner().as_str());
+        test_support::drop_database(&name).await;
+    }
+
+    #[actix_web::test]
+    async fn owners_can_delete_their_worlds() {
+        let Some(config) = test_support::db_config().await else {
+            return;
+        };
+        let data = test_support::data(&config);
+        let url = format!("https://{}.test", test_support::unique("owned"));
+        let app_id = test_support::insert_app(&data, &url, "Owned").await;
+        let other_id = test_support::insert_app(&data, &format!("{}/other", url), "Other").await;
+        let app = init_service(
+            App::new()
+                .wrap(FederationMiddleware::new(config.clone()))
+                .service(delete_world),
+        )
+        .await;
+        let delete = |id: i32, token: Option<String>| {
+            let mut request = TestRequest::post().uri(&format!("/world/{}/delete", id));
+            if let Some(token) = token {
+                request = request.cookie(Cookie::new("relay-owner-token", token));
+            }
+            request.to_request()
+        };
+        let token = create_owner_token(&data, app_id, &app_id.to_string())
+            .await
+            .unwrap();
+
+        let response = call_service(&app, delete(app_id, None)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // The token only covers the world it was issued for
+        let response = call_service(&app, delete(other_id, Some(token.clone()))).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(get_app_by_id(other_id, &data).await.is_ok());
+
+        let response = call_service(&app, delete(app_id, Some(token))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(get_app_by_id(app_id, &data).await.is_err());
+        let tombstone = get_app_tombstone(&data, &app_id.to_string()).await.unwrap();
+        assert!(tombstone.is_some());
+    }
 }
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A debounced flush is never put off for longer than this many debounce windows,
+/// so a client that never stops editing still gets its changes federated
+const MAX_DEBOUNCE_EXTENSIONS: u32 = 10;
+
+struct AppBroadcast {
+    last_sent: Option<Instant>,
+    /// When the scheduled flush was first requested, if one is pending
+    pending_since: Option<Instant>,
+    last_change: Instant,
+}
+
+/// What the caller should do with an app Update it wants to broadcast
+pub enum BroadcastDecision {
+    /// Send it right away
+    SendNow,
+    /// Send it once the window has passed; the caller owns the flush
+    SendAfter(Duration),
+    /// A flush is already scheduled and will carry the latest state
+    Coalesced,
+}
+
+/// Limits app Updates to one per `window` per app, and with a `debounce` holds each
+/// one back until the app has gone that long without changing. Updates only reference
+/// the app's AP id, so a delayed flush always federates the latest stored state.
+pub struct UpdateThrottle {
+    window: Duration,
+    debounce: Duration,
+    apps: Mutex<HashMap<String, AppBroadcast>>,
+}
+
+impl UpdateThrottle {
+    pub fn new(window: Duration, debounce: Duration) -> Self {
+        UpdateThrottle {
+            window,
+            debounce,
+            apps: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn decide(&self, ap_id: &str) -> BroadcastDecision {
+        if self.window.is_zero() && self.debounce.is_zero() {
+            return BroadcastDecision::SendNow;
+        }
+        let mut apps = match self.apps.lock() {
+            Ok(apps) => apps,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        // Forget apps that have been quiet for a while so the map doesn't grow forever
+        apps.retain(|_, app| {
+            app.pending_since.is_some()
+                || app.last_sent.is_some_and(|sent| sent.elapsed() < self.window)
+        });
+        let now = Instant::now();
+        let app = apps.entry(ap_id.to_string()).or_insert(AppBroadcast {
+            last_sent: None,
+            pending_since: None,
+            last_change: now,
+        });
+        app.last_change = now;
+        if app.pending_since.is_some() {
+            return BroadcastDecision::Coalesced;
+        }
+        let throttled = app
+            .last_sent
+            .map_or(Duration::ZERO, |sent| self.window.saturating_sub(sent.elapsed()));
+        let delay = throttled.max(self.debounce);
+        if delay.is_zero() {
+            app.last_sent = Some(now);
+            BroadcastDecision::SendNow
+        } else {
+            app.pending_since = Some(now);
+            BroadcastDecision::SendAfter(delay)
+        }
+    }
+
+    /// How much longer a scheduled flush should wait for the app to stop changing.
+    /// Zero once it has been quiet for the debounce window.
+    pub fn remaining_debounce(&self, ap_id: &str) -> Duration {
+        let apps = match self.apps.lock() {
+            Ok(apps) => apps,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let Some(app) = apps.get(ap_id) else {
+            return Duration::ZERO;
+        };
+        let quiet = self.debounce.saturating_sub(app.last_change.elapsed());
+        let deadline = app.pending_since.map_or(Duration::ZERO, |since| {
+            (self.debounce * MAX_DEBOUNCE_EXTENSIONS).saturating_sub(since.elapsed())
+        });
+        quiet.min(deadline)
+    }
+
+    /// Marks the scheduled flush for an app as sent
+    pub fn flushed(&self, ap_id: &str) {
+        let mut apps = match self.apps.lock() {
+            Ok(apps) => apps,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = Instant::now();
+        apps.insert(
+            ap_id.to_string(),
+            AppBroadcast {
+                last_sent: Some(now),
+                pending_since: None,
+                last_change: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP: &str = "https://relay.test/relay/beacon/1";
+
+    #[test]
+    fn two_rapid_updates_send_one_activity() {
+        let throttle = UpdateThrottle::new(Duration::from_secs(60), Duration::ZERO);
+        assert!(matches!(throttle.decide(APP), BroadcastDecision::SendNow));
+        // The second falls in the window and is held for a flush
+        assert!(
+            matches!(throttle.decide(APP), BroadcastDecision::SendAfter(delay)
+            if delay > Duration::ZERO && delay <= Duration::from_secs(60))
+        );
+        // Later ones ride on that flush
+        assert!(matches!(throttle.decide(APP), BroadcastDecision::Coalesced));
+        throttle.flushed(APP);
+        assert!(matches!(
+            throttle.decide(APP),
+            BroadcastDecision::SendAfter(_)
+        ));
+    }
+
+    #[test]
+    fn apps_are_throttled_separately() {
+        let throttle = UpdateThrottle::new(Duration::from_secs(60), Duration::ZERO);
+        assert!(matches!(throttle.decide(APP), BroadcastDecision::SendNow));
+        assert!(matches!(
+            throttle.decide("https://relay.test/relay/beacon/2"),
+            BroadcastDecision::SendNow
+        ));
+    }
+
+    #[test]
+    fn debounce_holds_the_first_update_and_waits_for_quiet() {
+        let throttle = UpdateThrottle::new(Duration::ZERO, Duration::from_secs(5));
+        assert!(
+            matches!(throttle.decide(APP), BroadcastDecision::SendAfter(delay)
+            if delay == Duration::from_secs(5))
+        );
+        assert!(matches!(throttle.decide(APP), BroadcastDecision::Coalesced));
+        assert!(throttle.remaining_debounce(APP) > Duration::from_secs(4));
+        assert_eq!(
+            throttle.remaining_debounce("https://relay.test/other"),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn no_window_sends_everything() {
+        let throttle = UpdateThrottle::new(Duration::ZERO, Duration::ZERO);
+        assert!(matches!(throttle.decide(APP), BroadcastDecision::SendNow));
+        assert!(matches!(throttle.decide(APP), BroadcastDecision::SendNow));
+    }
+}
@@ -0,0 +1,69 @@
+use std::env;
+
+use serde::Serialize;
+
+/// Feature flags that can be flipped from /admin/features without a restart
+pub const RUNTIME_FEATURES: [&str; 7] = [
+    "show_adult_content",
+    "index_hide_apps_with_no_images",
+    "mutual_only",
+    "proxy_remote_images",
+    "protect_admin_edits",
+    "auto_follow_back",
+    "strict_follows",
+];
+
+/// Boolean switches loaded once at startup from env, then overridden by any
+/// values saved in the features table
+#[derive(Clone, Debug, Serialize)]
+pub struct Features {
+    /// Not runtime-safe: also configures the federation library
+    pub debug: bool,
+    pub show_adult_content: bool,
+    pub index_hide_apps_with_no_images: bool,
+    pub mutual_only: bool,
+    /// Download images of federated apps instead of hotlinking them
+    pub proxy_remote_images: bool,
+    /// Ignore federated Updates to apps last edited by the relay admin
+    pub protect_admin_edits: bool,
+    /// Follow relays back when they follow us
+    pub auto_follow_back: bool,
+    /// Only deliver to a new follower once our Accept has reached it
+    pub strict_follows: bool,
+}
+
+impl Features {
+    pub fn from_env() -> Self {
+        Features {
+            debug: env::var("DEBUG").unwrap_or("false".to_string()) == "true",
+            show_adult_content: env::var("SHOW_ADULT_CONTENT").unwrap_or("false".to_string())
+                == "true",
+            index_hide_apps_with_no_images: env::var("INDEX_HIDE_APPS_WITH_NO_IMAGES")
+                .unwrap_or("true".to_string())
+                == "true",
+            mutual_only: env::var("MUTUAL_ONLY").unwrap_or("false".to_string()) == "true",
+            proxy_remote_images: env::var("PROXY_REMOTE_IMAGES").unwrap_or("false".to_string())
+                == "true",
+            protect_admin_edits: env::var("PROTECT_ADMIN_EDITS").unwrap_or("false".to_string())
+                == "true",
+            auto_follow_back: env::var("AUTO_FOLLOW_BACK").unwrap_or("false".to_string())
+                == "true",
+            strict_follows: env::var("STRICT_FOLLOWS").unwrap_or("false".to_string()) == "true",
+        }
+    }
+
+    /// Set a runtime feature by name, returning false if it can't be toggled
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        match name {
+            "show_adult_content" => self.show_adult_content = enabled,
+            "index_hide_apps_with_no_images" => self.index_hide_apps_with_no_images = enabled,
+            "mutual_only" => self.mutual_only = enabled,
+            "proxy_remote_images" => self.proxy_remote_images = enabled,
+            "protect_admin_edits" => self.protect_admin_edits = enabled,
+            "auto_follow_back" => self.auto_follow_back = enabled,
+            "strict_follows" => self.strict_follows = enabled,
+            _ => return false,
+        }
+        true
+    }
+}
@@ -1,30 +1,59 @@
+#[macro_use]
+mod request_id;
 mod activitypub;
+mod features;
+#[cfg(test)]
+mod test_support;
 
 use std::collections::HashMap;
+use std::future::Future;
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use std::env;
 
 use activitypub_federation::config::{FederationConfig, FederationMiddleware};
 use activitypub_federation::http_signatures::generate_actor_keypair;
+use activitypub_federation::traits::Actor;
 use actix_cors::Cors;
-use actix_web::http::header;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
 use actix_web::middleware::NormalizePath;
 use actix_web::{web, App, HttpServer};
 use dotenvy::dotenv;
+use jwt_simple::prelude::RS256KeyPair;
 use serde::Serialize;
 use sqlx::types::chrono::Utc;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 use tera::Tera;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, Semaphore};
 
+use crate::activitypub::activities::{retry_due_updates, ActivityKind};
+use crate::activitypub::actors::{DbRelay, PublicKeyCache, RELAY_USERNAME};
+use crate::activitypub::client_ip::TrustedProxies;
+use crate::activitypub::domain_verification::DomainVerification;
+use crate::activitypub::outbound::{build_http_client, OutboundGuard};
+use crate::activitypub::page_cache::PageCache;
+use crate::activitypub::pagination::PaginationLimits;
+use crate::activitypub::retry_queue::{UpdateRetryQueue, MAX_PENDING_UPDATES, UPDATE_RETRY_DELAYS};
+use crate::activitypub::throttle::UpdateThrottle;
 use crate::activitypub::services::{
-    admin_delete_world, admin_follow, admin_page, admin_toggle_visible, api_get_apps, get_activity, get_app, get_apps,
-    get_beacon, get_image, get_relays, get_world, get_world_edit, get_worlds, http_get_system_user,
-    http_post_relay_inbox, index, login, new_beacon, not_found, request_login_token,
-    request_world_verification, session_events, update_session_info, update_world,
-    verify_world_ownership, webfinger,
+    admin_activities, admin_add_tag, admin_app_history, admin_consistency_check,
+    admin_delete_world, admin_edit_page, admin_edit_world, admin_features_page, admin_follow,
+    admin_ingest, admin_page, admin_reach, admin_refresh_token, admin_relay, admin_remove_tag,
+    admin_rename_tag, admin_resolve_reports, admin_set_feature, admin_toggle_adult,
+    admin_toggle_visible, admin_verify_followers, api_get_apps, api_get_capabilities,
+    api_get_relay_apps, api_get_relay_info, api_get_similar_apps, check_beacon_health,
+    claim_beacon, delete_world, get_activity, get_app, get_apps, get_beacon, get_feed_json,
+    get_feed_xml, get_image, get_metrics, get_relay_catalog, get_relay_followers_collection,
+    get_relay_public_key, get_relays, get_robots_txt, get_sitemap, get_world, get_world_edit,
+    get_worlds, http_get_system_user, http_post_relay_inbox, http_post_shared_inbox, index, login,
+    new_beacon, not_found, remove_orphaned_images, report_app, request_login_token,
+    request_world_verification, run_self_check, session_events, update_session_info, update_world,
+    verify_world_ownership, webfinger, AppsSort, API_VERSION,
 };
+use crate::features::Features;
+use crate::request_id::REQUEST_ID_HEADER;
 
 #[derive(Clone, Eq, Hash, PartialEq)]
 pub struct SessionInfo {
@@ -43,33 +72,295 @@ pub struct NewSessionEvent {
 pub struct AppState {
     db: Pool<Postgres>,
     tera: Tera,
-    debug: bool,
-    show_adult_content: bool,
+    features: Arc<RwLock<Features>>,
     is_custom_page: HashMap<String, bool>,
     sessions: Arc<RwLock<HashMap<String, Vec<SessionInfo>>>>,
-    index_hide_apps_with_no_images: bool,
     google_analytics_id: Option<String>,
+    metrics_auth: Option<String>,
+    slow_query_ms: u64,
+    max_image_dimension: u32,
+    robots_txt: Option<String>,
+    relay_stale_days: i64,
+    public_key_cache: Arc<PublicKeyCache>,
+    max_apps_per_host: i64,
+    outbound_guard: OutboundGuard,
+    http_client: reqwest::Client,
+    context_aliases: Vec<(String, String)>,
+    /// Send world descriptions as HTML `content` instead of the address (`APP_HTML_CONTENT`)
+    app_html_content: bool,
+    /// `@context` IRIs for outbound activities and the actor document
+    activity_context: Vec<String>,
+    update_throttle: Arc<UpdateThrottle>,
+    /// Updates waiting to refetch an object that couldn't be fetched when they arrived
+    update_retries: Arc<UpdateRetryQueue>,
+    /// Rendered front page, cleared whenever an app changes
+    index_cache: Arc<PageCache>,
+    /// `max-age` for the actor and webfinger documents; 0 sends `no-cache`
+    actor_cache_max_age: u64,
+    /// Lifetime of admin login tokens and their cookie
+    admin_token_ttl_hours: u64,
+    inbox_max_depth: usize,
+    /// Bounds how many inbox activities are processed at once
+    inbox_permits: Arc<Semaphore>,
+    inbox_queue_timeout: Duration,
+    /// Inbox activities published longer ago than this are refused; `None` turns the check off
+    activity_max_age: Option<chrono::Duration>,
+    /// Inbox activity kinds this relay acts on; others get a 403
+    accepted_activities: Vec<ActivityKind>,
+    reports_per_hour: i64,
+    /// Default for `/apps?include_inactive=`
+    apps_include_inactive: bool,
+    /// Default for `/apps?sort=`
+    apps_default_sort: AppsSort,
+    /// Page size bounds for every `?page=`/`?per_page=` endpoint
+    pagination: PaginationLimits,
+    /// Refuse beacons and federated apps whose URL isn't https
+    require_https_beacons: bool,
+    /// Whether new beacons' sites must list this relay in their well-known file
+    domain_verification: DomainVerification,
+    /// Proxies allowed to tell us the client's address
+    trusted_proxies: TrustedProxies,
     new_session_tx: broadcast::Sender<NewSessionEvent>,
 }
 
+impl AppState {
+    /// Snapshot of the current feature flags
+    fn features(&self) -> Features {
+        match self.features.read() {
+            Ok(features) => features.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     // Load .env file if present (optional for Docker where env vars are passed directly)
     let _ = dotenv();
 
-    let debug = env::var("DEBUG").unwrap_or("false".to_string()) == "true";
+    let mut features = Features::from_env();
+    let debug = features.debug;
     let domain = env::var("DOMAIN").expect("DOMAIN must be set");
     let port = env::var("PORT").expect("PORT must be set");
-    let protocol = env::var("PROTOCOL").expect("PROTOCOL must be set");
+    let protocol = normalize_protocol(&env::var("PROTOCOL").expect("PROTOCOL must be set"))
+        .expect("PROTOCOL must be http or https, optionally followed by : or ://");
     let full_domain = format!("{}{}", protocol, domain);
+    // Serve HTTPS directly when a certificate is configured, otherwise expect a proxy in front
+    let tls_config = match (env::var("TLS_CERT_PATH"), env::var("TLS_KEY_PATH")) {
+        (Ok(cert_path), Ok(key_path)) if !cert_path.is_empty() && !key_path.is_empty() => {
+            if protocol != "https://" {
+                panic!("PROTOCOL must be https:// when TLS_CERT_PATH and TLS_KEY_PATH are set");
+            }
+            Some(load_tls_config(&cert_path, &key_path))
+        }
+        (Ok(path), _) | (_, Ok(path)) if !path.is_empty() => {
+            panic!("TLS_CERT_PATH and TLS_KEY_PATH must be set together")
+        }
+        _ => None,
+    };
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let show_adult_content =
-        env::var("SHOW_ADULT_CONTENT").unwrap_or("false".to_string()) == "true";
-    let index_hide_apps_with_no_images =
-        env::var("INDEX_HIDE_APPS_WITH_NO_IMAGES").unwrap_or("true".to_string()) == "true";
     let google_analytics_id = env::var("GOOGLE_ANALYTICS_ID").ok();
+    let metrics_auth = env::var("METRICS_AUTH").ok().filter(|auth| !auth.is_empty());
+    if let Some(auth) = &metrics_auth {
+        if !auth.contains(':') {
+            panic!("METRICS_AUTH must be in the form user:password");
+        }
+    }
+    let slow_query_ms = env::var("SLOW_QUERY_MS")
+        .unwrap_or("500".to_string())
+        .parse::<u64>()
+        .expect("SLOW_QUERY_MS must be a number of milliseconds");
+    let max_image_dimension = env::var("MAX_IMAGE_DIMENSION")
+        .unwrap_or("2048".to_string())
+        .parse::<u32>()
+        .ok()
+        .filter(|pixels| *pixels > 0)
+        .expect("MAX_IMAGE_DIMENSION must be a positive number of pixels");
+    let robots_txt = env::var("ROBOTS_TXT_PATH").ok().map(|path| {
+        std::fs::read_to_string(&path).expect("ROBOTS_TXT_PATH must point to a readable file")
+    });
+    let relay_stale_days = env::var("RELAY_STALE_DAYS")
+        .unwrap_or("30".to_string())
+        .parse::<i64>()
+        .expect("RELAY_STALE_DAYS must be a number of days");
+    let static_cache_max_age: u64 = env::var("STATIC_CACHE_MAX_AGE")
+        .unwrap_or("86400".to_string())
+        .parse()
+        .expect("STATIC_CACHE_MAX_AGE must be a number of seconds");
+    let public_key_cache_size: usize = env::var("PUBLIC_KEY_CACHE_SIZE")
+        .unwrap_or("1000".to_string())
+        .parse()
+        .expect("PUBLIC_KEY_CACHE_SIZE must be a number of entries");
+    let public_key_cache_ttl: u64 = env::var("PUBLIC_KEY_CACHE_TTL_SECS")
+        .unwrap_or("3600".to_string())
+        .parse()
+        .expect("PUBLIC_KEY_CACHE_TTL_SECS must be a number of seconds");
+    let max_apps_per_host = env::var("MAX_APPS_PER_HOST")
+        .unwrap_or("0".to_string())
+        .parse::<i64>()
+        .expect("MAX_APPS_PER_HOST must be a number of apps");
+    // Outbound fetches skip the private address check in debug mode unless asked
+    let block_private_addresses = env::var("BLOCK_PRIVATE_ADDRESSES")
+        .map(|value| value == "true")
+        .unwrap_or(!debug);
+    let require_https_beacons =
+        env::var("REQUIRE_HTTPS_BEACONS").unwrap_or("false".to_string()) == "true";
+    let domain_verification = env::var("REQUIRE_DOMAIN_VERIFICATION")
+        .unwrap_or("off".to_string())
+        .parse::<DomainVerification>()
+        .expect("REQUIRE_DOMAIN_VERIFICATION must be off, flag, hide or reject");
+    let outbound_allowed_hosts: Vec<String> = env::var("OUTBOUND_ALLOWED_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::to_string)
+        .collect();
+    let outbound_guard = OutboundGuard::new(block_private_addresses, outbound_allowed_hosts);
+    let outbound_proxy = env::var("OUTBOUND_PROXY").ok().filter(|proxy| !proxy.is_empty());
+    let http_client = build_http_client(outbound_proxy.as_deref(), &outbound_guard)
+        .expect("OUTBOUND_PROXY must be a valid proxy URL");
+    // Extra JSON-LD terms for app objects, as comma-separated term=iri pairs
+    let context_aliases: Vec<(String, String)> = env::var("JSONLD_CONTEXT_ALIASES")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((term, iri)) if !term.trim().is_empty() && !iri.trim().is_empty() => {
+                (term.trim().to_string(), iri.trim().to_string())
+            }
+            _ => panic!("JSONLD_CONTEXT_ALIASES entries must be in the form term=iri"),
+        })
+        .collect();
+    // Off until peers read the address from `url`; older relays take it from `content`
+    let app_html_content = env::var("APP_HTML_CONTENT").unwrap_or("false".to_string()) == "true";
+    let activity_context: Vec<String> = env::var("ACTIVITY_CONTEXT")
+        .unwrap_or(
+            "https://www.w3.org/ns/activitystreams,https://w3id.org/security/v1".to_string(),
+        )
+        .split(',')
+        .map(str::trim)
+        .filter(|iri| !iri.is_empty())
+        .map(str::to_string)
+        .collect();
+    if activity_context.first().map(String::as_str)
+        != Some("https://www.w3.org/ns/activitystreams")
+    {
+        panic!("ACTIVITY_CONTEXT must start with https://www.w3.org/ns/activitystreams");
+    }
+    let image_gc = env::var("IMAGE_GC").unwrap_or("false".to_string()) == "true";
+    let image_gc_grace_hours = env::var("IMAGE_GC_GRACE_HOURS")
+        .unwrap_or("24".to_string())
+        .parse::<u64>()
+        .expect("IMAGE_GC_GRACE_HOURS must be a number of hours");
+    let healthcheck_beacons =
+        env::var("HEALTHCHECK_BEACONS").unwrap_or("false".to_string()) == "true";
+    let healthcheck_interval_secs = env::var("HEALTHCHECK_INTERVAL_SECS")
+        .unwrap_or("3600".to_string())
+        .parse::<u64>()
+        .expect("HEALTHCHECK_INTERVAL_SECS must be a number of seconds");
+    let healthcheck_hide_after = env::var("HEALTHCHECK_HIDE_AFTER")
+        .unwrap_or("0".to_string())
+        .parse::<i32>()
+        .expect("HEALTHCHECK_HIDE_AFTER must be a number of checks");
+    let self_check = env::var("SELF_CHECK").unwrap_or("false".to_string()) == "true";
+    let index_cache_ttl = env::var("INDEX_CACHE_TTL")
+        .unwrap_or("0".to_string())
+        .parse::<u64>()
+        .expect("INDEX_CACHE_TTL must be a number of seconds");
+    let actor_cache_max_age: u64 = env::var("ACTOR_CACHE_MAX_AGE")
+        .unwrap_or("300".to_string())
+        .parse()
+        .expect("ACTOR_CACHE_MAX_AGE must be a number of seconds");
+    let admin_token_ttl_hours = env::var("ADMIN_TOKEN_TTL_HOURS")
+        .unwrap_or("24".to_string())
+        .parse::<u64>()
+        .ok()
+        .filter(|hours| (1..=24 * 365).contains(hours))
+        .expect("ADMIN_TOKEN_TTL_HOURS must be a number of hours between 1 and 8760");
+    let trusted_proxies_list = env::var("TRUSTED_PROXIES").unwrap_or_default();
+    let trusted_proxies = TrustedProxies::parse(&trusted_proxies_list)
+        .unwrap_or_else(|e| panic!("TRUSTED_PROXIES is invalid: {}", e));
+    let bootstrap_system_user =
+        env::var("BOOTSTRAP_SYSTEM_USER").unwrap_or("true".to_string()) == "true";
+    let inbox_max_bytes = env::var("INBOX_MAX_BYTES")
+        .unwrap_or("262144".to_string())
+        .parse::<usize>()
+        .expect("INBOX_MAX_BYTES must be a number of bytes");
+    let inbox_max_depth = env::var("INBOX_MAX_DEPTH")
+        .unwrap_or("32".to_string())
+        .parse::<usize>()
+        .expect("INBOX_MAX_DEPTH must be a number");
+    let inbox_max_concurrency = env::var("INBOX_MAX_CONCURRENCY")
+        .unwrap_or("16".to_string())
+        .parse::<usize>()
+        .expect("INBOX_MAX_CONCURRENCY must be a number");
+    let inbox_queue_timeout_ms = env::var("INBOX_QUEUE_TIMEOUT_MS")
+        .unwrap_or("5000".to_string())
+        .parse::<u64>()
+        .expect("INBOX_QUEUE_TIMEOUT_MS must be a number of milliseconds");
+    let activity_max_age_hours = env::var("ACTIVITY_MAX_AGE_HOURS")
+        .unwrap_or("12".to_string())
+        .parse::<i64>()
+        .expect("ACTIVITY_MAX_AGE_HOURS must be a number of hours");
+    let apps_include_inactive =
+        env::var("APPS_INCLUDE_INACTIVE").unwrap_or("false".to_string()) == "true";
+    let accepted_activities: Vec<ActivityKind> = match env::var("ACCEPTED_ACTIVITIES") {
+        Ok(kinds) => kinds
+            .split(',')
+            .map(str::trim)
+            .filter(|kind| !kind.is_empty())
+            .map(|kind| {
+                kind.parse::<ActivityKind>()
+                    .ok()
+                    .filter(|kind| ActivityKind::INBOX.contains(kind))
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "ACCEPTED_ACTIVITIES must only contain {}",
+                            ActivityKind::INBOX.map(|kind| kind.as_str()).join(", ")
+                        )
+                    })
+            })
+            .collect(),
+        Err(_) => ActivityKind::INBOX.to_vec(),
+    };
+    let apps_default_sort = env::var("APPS_DEFAULT_SORT")
+        .unwrap_or("name".to_string())
+        .parse::<AppsSort>()
+        .expect("APPS_DEFAULT_SORT must be name, newest, oldest or popular");
+    let pagination = PaginationLimits {
+        default_per_page: env::var("PAGINATION_DEFAULT_PER_PAGE")
+            .unwrap_or("50".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|per_page| *per_page > 0)
+            .expect("PAGINATION_DEFAULT_PER_PAGE must be a positive number"),
+        max_per_page: env::var("PAGINATION_MAX_PER_PAGE")
+            .unwrap_or("100".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|per_page| *per_page > 0)
+            .expect("PAGINATION_MAX_PER_PAGE must be a positive number"),
+    };
+    let reports_per_hour = env::var("REPORTS_PER_HOUR")
+        .unwrap_or("5".to_string())
+        .parse::<i64>()
+        .expect("REPORTS_PER_HOUR must be a number");
+    // Headers browsers may send to and read from the API, on top of the defaults below
+    let cors_allow_headers = parse_header_names("CORS_ALLOW_HEADERS", "idempotency-key,if-match");
+    let cors_expose_headers = parse_header_names(
+        "CORS_EXPOSE_HEADERS",
+        "x-ratelimit-limit,x-ratelimit-remaining,x-ratelimit-reset",
+    );
+    let update_min_interval_secs = env::var("UPDATE_MIN_INTERVAL_SECS")
+        .unwrap_or("30".to_string())
+        .parse::<u64>()
+        .expect("UPDATE_MIN_INTERVAL_SECS must be a number of seconds");
+    let update_debounce_ms = env::var("UPDATE_DEBOUNCE_MS")
+        .unwrap_or("0".to_string())
+        .parse::<u64>()
+        .expect("UPDATE_DEBOUNCE_MS must be a number of milliseconds");
+    let db_max_connections = 20;
     let pool = PgPoolOptions::new()
-        .max_connections(20)
+        .max_connections(db_max_connections)
         .connect(&database_url)
         .await
         .expect("Error building a connection pool");
@@ -80,17 +371,103 @@ async fn main() -> Result<(), anyhow::Error> {
         .await
         .expect("Failed to run database migrations");
 
-    // Insert default system user if not already exists
+    // Feature flags toggled from /admin/features take precedence over env
+    let overrides = sqlx::query_as::<_, (String, bool)>("SELECT name, enabled FROM features")
+        .fetch_all(&pool)
+        .await
+        .expect("Failed to load feature flags");
+    for (name, enabled) in overrides {
+        if !features.set(&name, enabled) {
+            eprintln!("Ignoring unknown feature flag in database: {}", name);
+        }
+    }
+
+    // Effective configuration, so misconfiguration is obvious at a glance.
+    // Secrets (passwords, credentials in URLs) are only reported as set or unset.
+    let is_set = |value: bool| if value { "set" } else { "unset" };
+    println!("Configuration:");
+    println!("  domain: {} (port {}, TLS {})", full_domain, port, is_set(tls_config.is_some()));
+    println!(
+        "  database: {} (pool size {})",
+        redact_database_url(&database_url),
+        db_max_connections
+    );
+    println!(
+        "  federation: {}, accepting {}, bootstrap_system_user={}",
+        if features.mutual_only { "mutual followers only" } else { "all followers" },
+        accepted_activities.iter().map(ActivityKind::as_str).collect::<Vec<_>>().join(", "),
+        bootstrap_system_user
+    );
+    println!(
+        "  features: debug={} show_adult_content={} index_hide_apps_with_no_images={} \
+         mutual_only={} proxy_remote_images={} protect_admin_edits={} auto_follow_back={} \
+         strict_follows={}",
+        features.debug,
+        features.show_adult_content,
+        features.index_hide_apps_with_no_images,
+        features.mutual_only,
+        features.proxy_remote_images,
+        features.protect_admin_edits,
+        features.auto_follow_back,
+        features.strict_follows
+    );
+    println!(
+        "  limits: max_apps_per_host={} reports_per_hour={} update_min_interval_secs={} \
+         update_debounce_ms={} inbox_max_bytes={} inbox_max_depth={} inbox_max_concurrency={} per_page={}/{} \
+         activity_max_age_hours={} require_https_beacons={} domain_verification={}",
+        max_apps_per_host,
+        reports_per_hour,
+        update_min_interval_secs,
+        update_debounce_ms,
+        inbox_max_bytes,
+        inbox_max_depth,
+        inbox_max_concurrency,
+        pagination.default_per_page,
+        pagination.max_per_page,
+        activity_max_age_hours,
+        require_https_beacons,
+        domain_verification.as_str()
+    );
+    println!(
+        "  trusted proxies: {}",
+        if trusted_proxies_list.trim().is_empty() { "none" } else { &trusted_proxies_list }
+    );
+    println!(
+        "  outbound: block_private_addresses={} proxy {}",
+        block_private_addresses,
+        is_set(outbound_proxy.is_some())
+    );
+    println!(
+        "  background jobs: image_gc={} healthcheck_beacons={} self_check={}",
+        image_gc, healthcheck_beacons, self_check
+    );
+    println!(
+        "  caching: index_cache_ttl={}s actor_cache_max_age={}s",
+        index_cache_ttl, actor_cache_max_age
+    );
+    println!(
+        "  secrets: ADMIN_PASSWORD {} (tokens last {}h), METRICS_AUTH {}",
+        is_set(env::var("ADMIN_PASSWORD").is_ok_and(|password| !password.is_empty())),
+        admin_token_ttl_hours,
+        is_set(metrics_auth.is_some())
+    );
+
+    // Insert default system user if not already exists, unless the actor and its keys
+    // are provisioned outside the relay
     match sqlx::query("SELECT * FROM relays WHERE id = 0 LIMIT 1;")
         .fetch_optional(&pool)
         .await
     {
         Ok(Some(_)) => {}
+        Ok(None) if !bootstrap_system_user => panic!(
+            "Relay not initialized: system user (relays row 0) is missing and \
+             BOOTSTRAP_SYSTEM_USER is false; check DATABASE_URL or provision the actor"
+        ),
         Ok(None) => {
             let keypair = generate_actor_keypair().expect("Failed to generate actor keypair");
             sqlx::query("INSERT INTO relays VALUES (0, $1, $2, $3, $4, $5, $6, $7, $8);")
                 .bind(format!("{}/relay", &full_domain))
-                .bind("relay".to_string())
+                .bind(RELAY_USERNAME)
                 .bind(format!("{}/relay/inbox", &full_domain))
                 .bind(format!("{}/relay/outbox", &full_domain))
                 .bind(keypair.public_key)
@@ -104,17 +481,53 @@ async fn main() -> Result<(), anyhow::Error> {
         Err(e) => eprintln!("Error locating default relay: {}", e),
     };
 
+    // Verify the system user is usable before accepting any requests
+    if let Err(e) = check_system_user(&pool).await {
+        panic!("{}", e);
+    }
+    // Id 0 is reserved for the system user; an app there would be unreachable
+    let app_at_id_0 = sqlx::query_scalar::<_, String>("SELECT url FROM apps WHERE id = 0")
+        .fetch_optional(&pool)
+        .await
+        .expect("Failed to query the apps table during startup self-check");
+    if let Some(url) = app_at_id_0 {
+        panic!(
+            "Database invariant violated: app {} occupies id 0, which is reserved for the relay. \
+             Move it to a new id before starting the relay.",
+            url
+        );
+    }
+
     // Always use default templates (*.default.html)
     let mut is_custom_page = HashMap::<String, bool>::new();
     is_custom_page.insert("admin".to_string(), false);
+    is_custom_page.insert("admin_edit".to_string(), false);
+    is_custom_page.insert("admin_features".to_string(), false);
+    is_custom_page.insert("admin_activities".to_string(), false);
     is_custom_page.insert("app".to_string(), false);
     is_custom_page.insert("apps".to_string(), false);
     is_custom_page.insert("edit".to_string(), false);
     is_custom_page.insert("error".to_string(), false);
     is_custom_page.insert("index".to_string(), false);
     is_custom_page.insert("login".to_string(), false);
+    is_custom_page.insert("relay".to_string(), false);
     is_custom_page.insert("relays".to_string(), false);
 
+    // Register locale-suffixed templates (e.g. index.es.html) for Accept-Language selection
+    if let Ok(entries) = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/frontend")) {
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let Some(stem) = file_name.strip_suffix(".html") else {
+                continue;
+            };
+            if let Some((page, lang)) = stem.split_once('.') {
+                if lang != "default" && is_custom_page.contains_key(page) {
+                    is_custom_page.insert(stem.to_string(), true);
+                }
+            }
+        }
+    }
+
     // Create in-memory session store for app live counts
     let sessions = Arc::new(RwLock::new(HashMap::<String, Vec<SessionInfo>>::new()));
 
@@ -128,35 +541,168 @@ async fn main() -> Result<(), anyhow::Error> {
         .app_data(AppState {
             db: pool.clone(),
             tera,
-            debug,
-            show_adult_content,
+            features: Arc::new(RwLock::new(features)),
             is_custom_page,
             sessions,
-            index_hide_apps_with_no_images,
             google_analytics_id,
+            metrics_auth,
+            slow_query_ms,
+            max_image_dimension,
+            robots_txt,
+            relay_stale_days,
+            public_key_cache: Arc::new(PublicKeyCache::new(
+                public_key_cache_size,
+                Duration::from_secs(public_key_cache_ttl),
+            )),
+            max_apps_per_host,
+            outbound_guard: outbound_guard.clone(),
+            http_client: http_client.clone(),
+            context_aliases,
+            app_html_content,
+            activity_context,
+            update_throttle: Arc::new(UpdateThrottle::new(
+                Duration::from_secs(update_min_interval_secs),
+                Duration::from_millis(update_debounce_ms),
+            )),
+            update_retries: Arc::new(UpdateRetryQueue::new(
+                MAX_PENDING_UPDATES,
+                UPDATE_RETRY_DELAYS.to_vec(),
+            )),
+            index_cache: Arc::new(PageCache::new(Duration::from_secs(index_cache_ttl))),
+            actor_cache_max_age,
+            admin_token_ttl_hours,
+            inbox_max_depth,
+            inbox_permits: Arc::new(Semaphore::new(if inbox_max_concurrency == 0 {
+                Semaphore::MAX_PERMITS
+            } else {
+                inbox_max_concurrency
+            })),
+            inbox_queue_timeout: Duration::from_millis(inbox_queue_timeout_ms),
+            activity_max_age: (activity_max_age_hours > 0)
+                .then(|| chrono::Duration::hours(activity_max_age_hours)),
+            accepted_activities,
+            reports_per_hour,
+            apps_include_inactive,
+            apps_default_sort,
+            pagination,
+            require_https_beacons,
+            domain_verification,
+            trusted_proxies,
             new_session_tx,
         })
+        .client(http_client.into())
+        .url_verifier(Box::new(outbound_guard))
         .debug(debug)
         .build()
         .await?;
+    // Periodically delete image files no app points at anymore
+    if image_gc {
+        let data = config.to_request_data();
+        let grace = Duration::from_secs(image_gc_grace_hours * 3600);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(3600));
+            loop {
+                interval.tick().await;
+                remove_orphaned_images(&data, grace).await;
+            }
+        });
+    }
+    if healthcheck_beacons {
+        let data = config.to_request_data();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(healthcheck_interval_secs.max(60)));
+            loop {
+                interval.tick().await;
+                check_beacon_health(&data, healthcheck_hide_after).await;
+            }
+        });
+    }
+    // Retry Updates whose object couldn't be fetched when they arrived
+    {
+        let data = config.to_request_data();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(5));
+            loop {
+                interval.tick().await;
+                retry_due_updates(&data).await;
+            }
+        });
+    }
+    if self_check {
+        let data = config.to_request_data();
+        tokio::spawn(async move {
+            // Give the server a moment to start accepting connections
+            tokio::time::sleep(Duration::from_secs(3)).await;
+            run_self_check(&data).await;
+        });
+    }
+
     // Increase max JSON payload size from 2 MB to 10 MB
     let json_config = web::JsonConfig::default().limit(1024 * 1024 * 10);
+    // Raw bodies are only taken by the inboxes; larger ones are refused with 413
+    let payload_config = web::PayloadConfig::default().limit(inbox_max_bytes);
     println!("Server listening on: {}", full_domain);
-    let _ = HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
             .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT, header::CONTENT_TYPE])
+            .allowed_headers(vec![header::IF_NONE_MATCH])
+            .allowed_headers(cors_allow_headers.clone())
+            .expose_headers(vec![
+                HeaderName::from_static("x-relay-api-version"),
+                HeaderName::from_static(REQUEST_ID_HEADER),
+                header::RETRY_AFTER,
+                header::ETAG,
+            ])
+            .expose_headers(cors_expose_headers.clone())
             .max_age(3600);
         App::new()
             .app_data(json_config.clone())
+            .app_data(payload_config.clone())
             .wrap(NormalizePath::trim())
             .wrap(FederationMiddleware::new(config.clone()))
             .wrap(cors)
+            .wrap_fn(move |req, srv| static_cache_headers(req, srv, static_cache_max_age))
+            .wrap_fn(|req, srv| {
+                let is_api = req.path().starts_with("/api/");
+                let fut = srv.call(req);
+                async move {
+                    let mut res = fut.await?;
+                    if is_api {
+                        res.headers_mut().insert(
+                            HeaderName::from_static("x-relay-api-version"),
+                            HeaderValue::from_static(API_VERSION),
+                        );
+                    }
+                    Ok(res)
+                }
+            })
+            // Outermost, so every log line written while serving a request carries its id
+            .wrap_fn(|req, srv| {
+                let request_id = request_id::for_request(&req);
+                let fut = request_id::scope(request_id.clone(), srv.call(req));
+                async move {
+                    let mut res = fut.await?;
+                    if let Ok(value) = HeaderValue::from_str(&request_id) {
+                        res.headers_mut()
+                            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                    }
+                    Ok(res)
+                }
+            })
             .service(index)
             .service(http_get_system_user)
+            .service(get_relay_followers_collection)
+            .service(get_relay_public_key)
+            .service(get_relay_catalog)
+            .service(api_get_relay_info)
+            .service(api_get_capabilities)
             .service(http_post_relay_inbox)
+            .service(http_post_shared_inbox)
             .service(new_beacon)
+            .service(claim_beacon)
             .service(get_beacon)
             .service(get_activity)
             .service(get_app)
@@ -167,23 +713,262 @@ async fn main() -> Result<(), anyhow::Error> {
             .service(request_world_verification)
             .service(verify_world_ownership)
             .service(update_world)
+            .service(delete_world)
             .service(api_get_apps)
+            .service(api_get_similar_apps)
+            .service(get_metrics)
+            .service(get_robots_txt)
+            .service(get_sitemap)
+            .service(get_feed_json)
+            .service(get_feed_xml)
             .service(get_relays)
             .service(login)
             .service(request_login_token)
+            .service(admin_refresh_token)
             .service(admin_page)
             .service(admin_follow)
             .service(admin_toggle_visible)
+            .service(admin_toggle_adult)
+            .service(admin_add_tag)
+            .service(admin_remove_tag)
+            .service(admin_rename_tag)
+            .service(admin_ingest)
             .service(admin_delete_world)
+            .service(admin_edit_page)
+            .service(admin_edit_world)
+            .service(admin_features_page)
+            .service(admin_activities)
+            .service(admin_set_feature)
+            .service(admin_consistency_check)
+            .service(admin_verify_followers)
+            .service(admin_reach)
+            .service(admin_relay)
+            .service(admin_app_history)
+            .service(api_get_relay_apps)
+            .service(report_app)
+            .service(admin_resolve_reports)
             .service(webfinger)
             .service(get_image)
             .service(update_session_info)
             .service(session_events)
             .service(actix_files::Files::new("/static", "frontend"))
             .default_service(web::route().to(not_found))
-    })
-    .bind(("0.0.0.0", u16::from_str(&port).unwrap()))?
-    .run()
-    .await;
+    });
+    let address = ("0.0.0.0", u16::from_str(&port).unwrap());
+    let server = match tls_config {
+        Some(tls_config) => server.bind_rustls_021(address, tls_config)?,
+        None => server.bind(address)?,
+    };
+    let _ = server.run().await;
+    Ok(())
+}
+
+/// Middleware marking successful `/static/` responses as cacheable for `max_age` seconds,
+/// except HTML, which is revalidated on every load
+fn static_cache_headers<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+    max_age: u64,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let is_static = req.path().starts_with("/static/");
+    let is_html = req.path().ends_with(".html");
+    let fut = srv.call(req);
+    async move {
+        let mut res = fut.await?;
+        if is_static && res.status().is_success() {
+            let cache_control = if is_html {
+                "no-cache".to_string()
+            } else {
+                format!("public, max-age={}", max_age)
+            };
+            res.headers_mut().insert(
+                header::CACHE_CONTROL,
+                HeaderValue::from_str(&cache_control).unwrap(),
+            );
+            res.headers_mut().insert(
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            );
+        }
+        Ok(res)
+    }
+}
+
+/// Fails with the reason when the system user (relays row 0) is missing, isn't local
+/// or has no usable private key
+async fn check_system_user(pool: &Pool<Postgres>) -> Result<(), String> {
+    let system_user = sqlx::query_as::<_, DbRelay>("SELECT * FROM relays WHERE id = 0 LIMIT 1;")
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to query the relays table during startup self-check: {}",
+                e
+            )
+        })?;
+    let Some(system_user) = system_user else {
+        return Err("Relay not initialized: system user (relays row 0) is missing".to_string());
+    };
+    match system_user.private_key_pem() {
+        Some(private_key) if RS256KeyPair::from_pem(&private_key).is_ok() => {}
+        Some(_) => {
+            return Err("Relay not initialized: system user private key does not parse".to_string())
+        }
+        None => return Err("Relay not initialized: system user has no private key".to_string()),
+    }
+    if !system_user.local {
+        return Err(
+            "Relay not initialized: relays row 0 is a remote relay, not the system user"
+                .to_string(),
+        );
+    }
     Ok(())
 }
+
+/// Accepts `PROTOCOL` as `https`, `https:` or `https://` (likewise for http) and returns
+/// the `scheme://` prefix that is joined with `DOMAIN`
+fn normalize_protocol(protocol: &str) -> Option<&'static str> {
+    let protocol = protocol.trim().to_ascii_lowercase();
+    match protocol.trim_end_matches("//").trim_end_matches(':') {
+        "https" => Some("https://"),
+        "http" => Some("http://"),
+        _ => None,
+    }
+}
+
+/// `PROTOCOL` as a `scheme://` prefix. It is validated at startup, so this can't panic
+/// once the server is running.
+pub fn public_protocol() -> &'static str {
+    normalize_protocol(&env::var("PROTOCOL").expect("PROTOCOL must be set"))
+        .expect("PROTOCOL must be http or https")
+}
+
+/// Describes the database by host and name only, dropping any credentials
+fn redact_database_url(database_url: &str) -> String {
+    match url::Url::parse(database_url) {
+        Ok(url) => format!(
+            "{}:{}{}",
+            url.host_str().unwrap_or("localhost"),
+            url.port().unwrap_or(5432),
+            url.path()
+        ),
+        Err(_) => "<unparseable DATABASE_URL>".to_string(),
+    }
+}
+
+/// Reads a comma-separated list of header names from `var`, falling back to `default`
+fn parse_header_names(var: &str, default: &str) -> Vec<HeaderName> {
+    env::var(var)
+        .unwrap_or(default.to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            HeaderName::from_bytes(name.as_bytes())
+                .unwrap_or_else(|_| panic!("{} contains an invalid header name: {}", var, name))
+        })
+        .collect()
+}
+
+/// Reads a PEM certificate chain and private key into a rustls server config
+fn load_tls_config(cert_path: &str, key_path: &str) -> rustls::ServerConfig {
+    let cert_file = std::fs::File::open(cert_path).expect("TLS_CERT_PATH must be readable");
+    let certs: Vec<rustls::Certificate> =
+        rustls_pemfile::certs(&mut std::io::BufReader::new(cert_file))
+            .expect("TLS_CERT_PATH must contain PEM certificates")
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+    if certs.is_empty() {
+        panic!("TLS_CERT_PATH contains no certificates");
+    }
+
+    let key_file = std::fs::File::open(key_path).expect("TLS_KEY_PATH must be readable");
+    let key = rustls_pemfile::read_all(&mut std::io::BufReader::new(key_file))
+        .expect("TLS_KEY_PATH must contain a PEM private key")
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+            _ => None,
+        })
+        .expect("TLS_KEY_PATH contains no private key");
+
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .expect("TLS certificate and key do not match")
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, TestRequest};
+
+    use super::*;
+    use crate::test_support;
+
+    #[actix_web::test]
+    async fn static_assets_are_cached_except_html() {
+        let app = init_service(
+            App::new()
+                .wrap_fn(|req, srv| static_cache_headers(req, srv, 600))
+                .service(actix_files::Files::new("/static", "frontend")),
+        )
+        .await;
+        let cache_control = |path: &str| {
+            let request = TestRequest::get().uri(path).to_request();
+            let app = &app;
+            async move {
+                let response = call_service(app, request).await;
+                let header = response.headers().get(header::CACHE_CONTROL);
+                let header = header.map(|value| value.to_str().unwrap().to_string());
+                (response.status(), header)
+            }
+        };
+
+        let (status, header) = cache_control("/static/styles.css").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(header.as_deref(), Some("public, max-age=600"));
+        let (status, header) = cache_control("/static/index.default.html").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(header.as_deref(), Some("no-cache"));
+        // Errors aren't cached
+        let (status, header) = cache_control("/static/missing.css").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(header, None);
+    }
+
+    #[actix_web::test]
+    async fn startup_check_refuses_a_missing_or_broken_system_user() {
+        let Some((config, name)) = test_support::fresh_db_config().await else {
+            return;
+        };
+        let pool = config.to_request_data().db.clone();
+        assert_eq!(check_system_user(&pool).await, Ok(()));
+
+        sqlx::query("UPDATE relays SET private_key = 'not a key' WHERE id = 0")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let error = check_system_user(&pool).await.unwrap_err();
+        assert!(error.contains("private key does not parse"), "{}", error);
+
+        sqlx::query("DELETE FROM relays WHERE id = 0")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let error = check_system_user(&pool).await.unwrap_err();
+        assert!(
+            error.contains("system user (relays row 0) is missing"),
+            "{}",
+            error
+        );
+        test_support::drop_database(&name).await;
+    }
+}
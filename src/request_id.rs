@@ -0,0 +1,56 @@
+use actix_web::dev::ServiceRequest;
+
+/// Header carrying the request id, both from clients/proxies and on our responses
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being served on this task, if any
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(String::clone).ok()
+}
+
+/// Runs `fut` with `id` as the current request id
+pub async fn scope<F: std::future::Future>(id: String, fut: F) -> F::Output {
+    REQUEST_ID.scope(id, fut).await
+}
+
+/// Reuses an incoming `X-Request-Id` so ids line up with the proxy's logs, as long as it
+/// is short and printable; otherwise mints a fresh UUID
+pub fn for_request(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= 128
+                && id
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Like `println!`, with the current request id in front of the line when there is one.
+/// `#[macro_use]` on this module makes it available to the modules declared after it.
+macro_rules! log_info {
+    ($($arg:tt)+) => {
+        match $crate::request_id::current() {
+            Some(id) => println!("[{}] {}", id, format_args!($($arg)+)),
+            None => println!($($arg)+),
+        }
+    };
+}
+
+/// [`log_info!`] for stderr, where errors and warnings go
+macro_rules! log_error {
+    ($($arg:tt)+) => {
+        match $crate::request_id::current() {
+            Some(id) => eprintln!("[{}] {}", id, format_args!($($arg)+)),
+            None => eprintln!($($arg)+),
+        }
+    };
+}
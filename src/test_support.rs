@@ -0,0 +1,298 @@
+//! Shared setup for unit tests. Tests that only need an `AppState` get one backed by a
+//! lazy pool that never connects; tests that touch the database need `TEST_DATABASE_URL`
+//! and are skipped without it.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Once, RwLock};
+use std::time::Duration;
+
+use activitypub_federation::config::{Data, FederationConfig};
+use activitypub_federation::http_signatures::generate_actor_keypair;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+use sqlx::types::chrono::Utc;
+use tera::Tera;
+use tokio::sync::{broadcast, Semaphore};
+
+use crate::activitypub::activities::ActivityKind;
+use crate::activitypub::actors::{PublicKeyCache, RELAY_USERNAME};
+use crate::activitypub::client_ip::TrustedProxies;
+use crate::activitypub::domain_verification::DomainVerification;
+use crate::activitypub::outbound::{build_http_client, OutboundGuard};
+use crate::activitypub::page_cache::PageCache;
+use crate::activitypub::retry_queue::UpdateRetryQueue;
+use crate::activitypub::pagination::PaginationLimits;
+use crate::activitypub::services::AppsSort;
+use crate::activitypub::throttle::UpdateThrottle;
+use crate::features::Features;
+use crate::AppState;
+
+pub const TEST_DOMAIN: &str = "relay.test";
+
+static ENV: Once = Once::new();
+
+/// The env the handlers read at request time
+fn set_env() {
+    ENV.call_once(|| {
+        std::env::set_var("DOMAIN", TEST_DOMAIN);
+        std::env::set_var("PROTOCOL", "https");
+    });
+}
+
+fn app_state(db: sqlx::PgPool) -> AppState {
+    let mut is_custom_page = HashMap::new();
+    for page in [
+        "admin",
+        "admin_edit",
+        "admin_features",
+        "admin_activities",
+        "app",
+        "apps",
+        "edit",
+        "error",
+        "index",
+        "login",
+        "relay",
+        "relays",
+    ] {
+        is_custom_page.insert(page.to_string(), false);
+    }
+    // Tests fetch from servers they start on loopback addresses
+    let outbound_guard = OutboundGuard::new(false, vec![]);
+    let http_client = build_http_client(None, &outbound_guard).unwrap();
+    AppState {
+        db,
+        tera: Tera::new(concat!(env!("CARGO_MANIFEST_DIR"), "/frontend/**/*.html")).unwrap(),
+        features: Arc::new(RwLock::new(Features::from_env())),
+        is_custom_page,
+        sessions: Arc::new(RwLock::new(HashMap::new())),
+        google_analytics_id: None,
+        metrics_auth: None,
+        slow_query_ms: 500,
+        max_image_dimension: 2048,
+        robots_txt: None,
+        relay_stale_days: 30,
+        public_key_cache: Arc::new(PublicKeyCache::new(100, Duration::from_secs(60))),
+        max_apps_per_host: 0,
+        outbound_guard,
+        http_client,
+        context_aliases: vec![],
+        app_html_content: false,
+        activity_context: vec![
+            "https://www.w3.org/ns/activitystreams".to_string(),
+            "https://w3id.org/security/v1".to_string(),
+        ],
+        update_throttle: Arc::new(UpdateThrottle::new(Duration::ZERO, Duration::ZERO)),
+        update_retries: Arc::new(UpdateRetryQueue::new(100, vec![Duration::ZERO; 2])),
+        index_cache: Arc::new(PageCache::new(Duration::ZERO)),
+        actor_cache_max_age: 300,
+        admin_token_ttl_hours: 24,
+        inbox_max_depth: 32,
+        inbox_permits: Arc::new(Semaphore::new(16)),
+        inbox_queue_timeout: Duration::from_secs(5),
+        activity_max_age: None,
+        accepted_activities: ActivityKind::INBOX.to_vec(),
+        reports_per_hour: 5,
+        apps_include_inactive: false,
+        apps_default_sort: AppsSort::Name,
+        pagination: PaginationLimits::default(),
+        require_https_beacons: false,
+        domain_verification: DomainVerification::Off,
+        trusted_proxies: TrustedProxies::default(),
+        new_session_tx: broadcast::channel(16).0,
+    }
+}
+
+async fn federation_config(state: AppState) -> FederationConfig<AppState> {
+    FederationConfig::builder()
+        .domain(TEST_DOMAIN)
+        .app_data(state)
+        .debug(true)
+        .build()
+        .await
+        .unwrap()
+}
+
+/// Config whose database is never reachable, for tests of code that doesn't query it
+pub async fn offline_config() -> FederationConfig<AppState> {
+    set_env();
+    let db = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(200))
+        .connect_lazy("postgres://offline@127.0.0.1:1/offline")
+        .unwrap();
+    federation_config(app_state(db)).await
+}
+
+/// Config against `TEST_DATABASE_URL`, migrated and with the system user in place.
+/// `None` when the variable isn't set, so callers can skip.
+pub async fn db_config_with(
+    configure: impl FnOnce(&mut AppState),
+) -> Option<FederationConfig<AppState>> {
+    set_env();
+    let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL is not set; skipping database test");
+        return None;
+    };
+    let db = PgPoolOptions::new()
+        .max_connections(4)
+        .connect(&url)
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&db).await.unwrap();
+    let has_system_user =
+        sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM relays WHERE id = 0)")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    // Key generation is slow in debug builds, so the system user is kept between runs
+    if !has_system_user {
+        let keypair = generate_actor_keypair().unwrap();
+        insert_system_user(&db, &keypair.public_key, &keypair.private_key).await;
+    }
+    let mut state = app_state(db);
+    configure(&mut state);
+    Some(federation_config(state).await)
+}
+
+pub async fn db_config() -> Option<FederationConfig<AppState>> {
+    db_config_with(|_| {}).await
+}
+
+async fn insert_system_user(db: &PgPool, public_key: &str, private_key: &str) {
+    let base = format!("https://{}", TEST_DOMAIN);
+    sqlx::query(
+        "INSERT INTO relays VALUES (0, $1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT DO NOTHING",
+    )
+    .bind(format!("{}/relay", base))
+    .bind(RELAY_USERNAME)
+    .bind(format!("{}/relay/inbox", base))
+    .bind(format!("{}/relay/outbox", base))
+    .bind(public_key)
+    .bind(Some(private_key))
+    .bind(Utc::now())
+    .bind(true)
+    .execute(db)
+    .await
+    .unwrap();
+}
+
+/// A new, empty database beside the test database, for tests that need tables no other
+/// test writes to. Returns a pool on it and its name for [`drop_database`].
+pub async fn fresh_database() -> Option<(PgPool, String)> {
+    let url = std::env::var("TEST_DATABASE_URL").ok()?;
+    let name = unique("relay_test").replace('-', "_");
+    let shared = PgPool::connect(&url).await.unwrap();
+    sqlx::query(&format!("CREATE DATABASE {}", name))
+        .execute(&shared)
+        .await
+        .unwrap();
+    let options = PgConnectOptions::from_str(&url).unwrap().database(&name);
+    let db = PgPoolOptions::new()
+        .max_connections(4)
+        .connect_with(options)
+        .await
+        .unwrap();
+    Some((db, name))
+}
+
+/// Like [`db_config`], but on a [`fresh_database`] holding only the system user
+pub async fn fresh_db_config() -> Option<(FederationConfig<AppState>, String)> {
+    // The system user's keys are copied from the shared database to skip key generation
+    let shared = db_config().await?;
+    let (public_key, private_key): (String, String) =
+        sqlx::query_as("SELECT public_key, private_key FROM relays WHERE id = 0")
+            .fetch_one(&shared.to_request_data().db)
+            .await
+            .unwrap();
+    let (db, name) = fresh_database().await?;
+    sqlx::migrate!("./migrations").run(&db).await.unwrap();
+    insert_system_user(&db, &public_key, &private_key).await;
+    Some((federation_config(app_state(db)).await, name))
+}
+
+pub async fn drop_database(name: &str) {
+    let url = std::env::var("TEST_DATABASE_URL").unwrap();
+    let shared = PgPool::connect(&url).await.unwrap();
+    sqlx::query(&format!("DROP DATABASE {} WITH (FORCE)", name))
+        .execute(&shared)
+        .await
+        .unwrap();
+}
+
+/// A string no other test run will use, for URLs and names of test rows
+pub fn unique(prefix: &str) -> String {
+    format!("{}-{}", prefix, uuid::Uuid::new_v4().simple())
+}
+
+pub fn data(config: &FederationConfig<AppState>) -> Data<AppState> {
+    config.to_request_data()
+}
+
+/// Files served by [`serve_site`], by path; tests change them while the site runs
+pub type SiteFiles = Arc<std::sync::Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Serves `files` over http from a random loopback address and returns its origin.
+/// Each site gets its own address, since app lookups ignore the port.
+pub fn serve_site(files: SiteFiles) -> String {
+    use rand::Rng;
+
+    let mut rng = rand::thread_rng();
+    let ip = format!(
+        "127.{}.{}.{}",
+        rng.gen_range(1..255),
+        rng.gen_range(0..255),
+        rng.gen_range(1..255)
+    );
+    let port = serve(files, &ip);
+    format!("http://{}:{}", ip, port)
+}
+
+/// Like [`serve_site`] for a federated peer, which needs a host name rather than an IP.
+/// Files holding a JSON object are served as ActivityPub documents.
+pub fn serve_peer(files: SiteFiles) -> String {
+    let port = serve(files, "127.0.0.1");
+    format!("http://localhost:{}", port)
+}
+
+fn serve(files: SiteFiles, ip: &str) -> u16 {
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+
+    let server = HttpServer::new(move || {
+        let files = files.clone();
+        App::new().default_service(web::to(move |request: HttpRequest| {
+            let body = files.lock().unwrap().get(request.path()).cloned();
+            async move {
+                match body {
+                    Some(body) if body.starts_with(b"{") => HttpResponse::Ok()
+                        .content_type("application/activity+json")
+                        .body(body),
+                    Some(body) => HttpResponse::Ok().body(body),
+                    None => HttpResponse::NotFound().finish(),
+                }
+            }
+        }))
+    })
+    .workers(1)
+    .disable_signals()
+    .shutdown_timeout(0)
+    .bind((ip, 0))
+    .unwrap();
+    let port = server.addrs()[0].port();
+    actix_web::rt::spawn(server.run());
+    port
+}
+
+/// Inserts a visible app at `url` and returns its id
+pub async fn insert_app(data: &Data<AppState>, url: &str, name: &str) -> i32 {
+    sqlx::query_scalar::<_, i32>(
+        "INSERT INTO apps (activitypub_id, url, name, description, is_active, image, is_adult, tags) \
+         VALUES ($1, $2, $3, '', true, '#', false, '') RETURNING id",
+    )
+    .bind(format!("https://{}/relay/beacon/{}", TEST_DOMAIN, unique("app")))
+    .bind(url)
+    .bind(name)
+    .fetch_one(&data.db)
+    .await
+    .unwrap()
+}